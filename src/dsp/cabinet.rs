@@ -0,0 +1,208 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::path::Path;
+
+use super::convolution::uniform::UniformConvolver;
+
+/// The partition size used for the cabinet convolution. The first this many taps are convolved
+/// directly, and the rest is handled with FFTs of twice this size.
+pub const CABINET_BLOCK_SIZE: usize = 128;
+/// Impulse responses are truncated to this length. Cabinet IRs rarely contain anything useful past
+/// the first couple hundred milliseconds, and this keeps the number of partitions reasonable.
+pub const MAX_IMPULSE_RESPONSE_SECONDS: f32 = 1.0;
+/// The number of zero crossings on either side of the windowed sinc used for resampling.
+const RESAMPLING_ZERO_CROSSINGS: usize = 16;
+
+#[derive(Debug)]
+pub enum ImpulseResponseError {
+    Wav(hound::Error),
+    /// The file did not contain any samples.
+    Empty,
+    /// Only mono and stereo impulse responses are supported.
+    UnsupportedChannelCount(u16),
+}
+
+impl fmt::Display for ImpulseResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImpulseResponseError::Wav(err) => write!(f, "Could not read the WAV file: {err}"),
+            ImpulseResponseError::Empty => write!(f, "The impulse response is empty"),
+            ImpulseResponseError::UnsupportedChannelCount(channels) => write!(
+                f,
+                "Impulse responses need to be mono or stereo, this one has {channels} channels"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImpulseResponseError {}
+
+impl From<hound::Error> for ImpulseResponseError {
+    fn from(err: hound::Error) -> Self {
+        ImpulseResponseError::Wav(err)
+    }
+}
+
+/// A de-interleaved mono or stereo impulse response.
+#[derive(Debug, Clone)]
+pub struct ImpulseResponse {
+    pub channels: Vec<Vec<f32>>,
+    pub sample_rate: f32,
+}
+
+impl ImpulseResponse {
+    /// Read a mono or stereo impulse response from a WAV file. Integer samples are scaled to the
+    /// `[-1, 1]` range.
+    pub fn load_wav(path: impl AsRef<Path>) -> Result<Self, ImpulseResponseError> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let num_channels = spec.channels as usize;
+        if !(1..=2).contains(&num_channels) {
+            return Err(ImpulseResponseError::UnsupportedChannelCount(spec.channels));
+        }
+
+        let interleaved: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 * scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        if interleaved.len() < num_channels {
+            return Err(ImpulseResponseError::Empty);
+        }
+
+        let mut channels = vec![Vec::with_capacity(interleaved.len() / num_channels); num_channels];
+        for frame in interleaved.chunks_exact(num_channels) {
+            for (channel, sample) in channels.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+
+        Ok(ImpulseResponse {
+            channels,
+            sample_rate: spec.sample_rate as f32,
+        })
+    }
+
+    /// Resample the impulse response to `target_sample_rate` using windowed sinc interpolation.
+    /// When downsampling the sinc's cutoff is lowered to avoid aliasing.
+    pub fn resample(&self, target_sample_rate: f32) -> Self {
+        if self.sample_rate == target_sample_rate {
+            return self.clone();
+        }
+
+        let ratio = self.sample_rate / target_sample_rate;
+        let cutoff = ratio.recip().min(1.0);
+        let half_width = RESAMPLING_ZERO_CROSSINGS as f32 / cutoff;
+
+        let channels = self
+            .channels
+            .iter()
+            .map(|channel| {
+                let output_len = (channel.len() as f32 / ratio).ceil() as usize;
+                (0..output_len)
+                    .map(|output_idx| {
+                        let position = output_idx as f32 * ratio;
+                        let first = (position - half_width).ceil().max(0.0) as usize;
+                        let last =
+                            ((position + half_width).floor() as usize).min(channel.len() - 1);
+
+                        (first..=last)
+                            .map(|input_idx| {
+                                let t = input_idx as f32 - position;
+                                let x = t * cutoff;
+                                let sinc = if x == 0.0 {
+                                    1.0
+                                } else {
+                                    (PI * x).sin() / (PI * x)
+                                };
+                                // Blackman window spanning `[-half_width, half_width]`
+                                let window_phase = (t / half_width + 1.0) * 0.5;
+                                let window = 0.42 - 0.5 * (2.0 * PI * window_phase).cos()
+                                    + 0.08 * (4.0 * PI * window_phase).cos();
+
+                                channel[input_idx] * sinc * window * cutoff
+                            })
+                            .sum()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        ImpulseResponse {
+            channels,
+            sample_rate: target_sample_rate,
+        }
+    }
+
+    /// Shorten the impulse response to at most `num_samples` samples per channel.
+    pub fn truncate(&mut self, num_samples: usize) {
+        for channel in &mut self.channels {
+            channel.truncate(num_samples);
+        }
+    }
+
+    /// Scale the impulse response so that the loudest channel has unit energy, which keeps loudness
+    /// roughly consistent when switching between IRs.
+    pub fn normalize(&mut self) {
+        let max_energy = self
+            .channels
+            .iter()
+            .map(|channel| channel.iter().map(|sample| sample * sample).sum::<f32>())
+            .fold(0.0f32, f32::max);
+        if max_energy > 0.0 {
+            let gain = max_energy.sqrt().recip();
+            for sample in self.channels.iter_mut().flatten() {
+                *sample *= gain;
+            }
+        }
+    }
+}
+
+/// A speaker cabinet simulation that convolves every channel with an impulse response. Mono IRs
+/// are used for all channels, and stereo IRs are applied channel by channel.
+pub struct Cabinet {
+    convolvers: Vec<UniformConvolver>,
+}
+
+impl Cabinet {
+    pub fn new(impulse_response: &ImpulseResponse, num_channels: usize) -> Self {
+        let convolvers = (0..num_channels)
+            .map(|channel_idx| {
+                let ir_channel = &impulse_response.channels
+                    [channel_idx.min(impulse_response.channels.len() - 1)];
+                UniformConvolver::new(ir_channel, CABINET_BLOCK_SIZE)
+            })
+            .collect();
+
+        Cabinet { convolvers }
+    }
+
+    /// Load, resample, truncate, and normalize an impulse response and build a cabinet from it.
+    /// This allocates and does file IO, so it should only be called from a background thread.
+    pub fn load(
+        path: impl AsRef<Path>,
+        sample_rate: f32,
+        num_channels: usize,
+    ) -> Result<Self, ImpulseResponseError> {
+        let mut impulse_response = ImpulseResponse::load_wav(path)?.resample(sample_rate);
+        impulse_response.truncate((MAX_IMPULSE_RESPONSE_SECONDS * sample_rate) as usize);
+        impulse_response.normalize();
+
+        Ok(Cabinet::new(&impulse_response, num_channels))
+    }
+
+    pub fn reset(&mut self) {
+        for convolver in &mut self.convolvers {
+            convolver.reset();
+        }
+    }
+
+    pub fn process(&mut self, input: f32, channel_idx: usize) -> f32 {
+        self.convolvers[channel_idx].process(input)
+    }
+}
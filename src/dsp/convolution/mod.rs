@@ -0,0 +1,3 @@
+pub mod non_uniform;
+pub mod stage;
+pub mod uniform;
@@ -0,0 +1,120 @@
+use super::stage::{DirectConvolver, FftStage};
+
+/// A sensible number of taps to convolve directly before the first FFT stage takes over.
+pub const DEFAULT_HEAD_SIZE: usize = 64;
+/// A sensible upper limit for the partition size. Larger partitions are cheaper per sample but
+/// make the CPU load spikier when they're computed on the audio thread.
+pub const DEFAULT_MAX_BLOCK_SIZE: usize = 8192;
+
+/// Zero-latency non-uniformly partitioned convolution for long impulse responses, like reverbs
+/// that last several seconds. The first `head_size` taps are convolved directly. After that the
+/// impulse response is split into FFT stages with partition sizes that double every stage, going
+/// from `head_size` up to `max_block_size`, with the last stage covering however much of the
+/// impulse response is left. With a head size of `B` the layout looks like this:
+///
+/// ```text
+/// | direct | 3 x B | 2 x 2B | 2 x 4B | ... | n x max_block_size |
+/// 0        B       4B       8B       16B
+/// ```
+///
+/// Every stage starts at a multiple of its own block size that's at least twice that block size
+/// (except for the first), so every stage's FFT latency is hidden by its position in the impulse
+/// response. That same slack of one extra block allows the larger stages to be computed on worker
+/// threads without changing the output.
+///
+/// Everything runs sample by sample internally, so hosts can use any block size.
+pub struct NonUniformConvolver {
+    head: DirectConvolver,
+    stages: Vec<FftStage>,
+    num_taps: usize,
+}
+
+impl NonUniformConvolver {
+    /// Create a convolver for an impulse response of any length. `max_block_size` must be
+    /// `head_size` multiplied by a power of two. Stages with a block size of at least
+    /// `threaded_block_size` get their own worker thread, or `None` to compute everything on the
+    /// calling thread.
+    pub fn new(
+        impulse_response: &[f32],
+        head_size: usize,
+        max_block_size: usize,
+        threaded_block_size: Option<usize>,
+    ) -> Self {
+        assert!(head_size > 0, "The head size must be positive");
+        assert!(
+            max_block_size.is_multiple_of(head_size)
+                && (max_block_size / head_size).is_power_of_two(),
+            "The maximum block size must be the head size multiplied by a power of two"
+        );
+
+        let mut stages = Vec::new();
+        let mut offset = head_size;
+        let mut block_size = head_size;
+        while offset < impulse_response.len() {
+            let num_partitions = if block_size == max_block_size {
+                usize::MAX
+            } else if block_size == head_size {
+                // This brings the next stage's offset to `4 * head_size`, which is twice its block
+                // size
+                3
+            } else {
+                2
+            };
+            let end = offset
+                .saturating_add(num_partitions.saturating_mul(block_size))
+                .min(impulse_response.len());
+            let segment = &impulse_response[offset..end];
+
+            let threaded = threaded_block_size.is_some_and(|min_size| block_size >= min_size)
+                && offset >= block_size * 2;
+            stages.push(if threaded {
+                FftStage::new_threaded(segment, block_size, offset)
+            } else {
+                FftStage::new(segment, block_size, offset)
+            });
+
+            offset = end;
+            block_size = (block_size * 2).min(max_block_size);
+        }
+
+        NonUniformConvolver {
+            head: DirectConvolver::new(impulse_response, head_size),
+            stages,
+            num_taps: impulse_response.len(),
+        }
+    }
+
+    /// The length of the impulse response this convolver was created with.
+    pub fn num_taps(&self) -> usize {
+        self.num_taps
+    }
+
+    /// The partition sizes of the FFT stages, in order.
+    pub fn block_sizes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.stages.iter().map(FftStage::block_size)
+    }
+
+    pub fn reset(&mut self) {
+        self.head.reset();
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let mut output = self.head.process(input);
+        for stage in &mut self.stages {
+            output += stage.process(input);
+        }
+
+        output
+    }
+
+    /// Convolve a block of any size. `input` and `output` must have the same length.
+    pub fn process_block(&mut self, input: &[f32], output: &mut [f32]) {
+        assert_eq!(input.len(), output.len());
+        for (input_sample, output_sample) in input.iter().zip(output.iter_mut()) {
+            *output_sample = self.process(*input_sample);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use parking_lot::{Condvar, Mutex};
+use realfft::num_complex::Complex32;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// Direct time-domain convolution with a short kernel. Used for the head of an impulse response,
+/// since that part can't tolerate any FFT latency.
+#[derive(Debug, Clone)]
+pub struct DirectConvolver {
+    taps: Vec<f32>,
+    /// The last `taps.len()` input samples, stored twice so that
+    /// `history[history_pos..history_pos + taps.len()]` always contains the most recent sample
+    /// followed by the older ones without having to wrap around.
+    history: Vec<f32>,
+    history_pos: usize,
+}
+
+impl DirectConvolver {
+    /// Convolve with the first `num_taps` taps of `impulse_response`, zero padded if it's shorter
+    /// than that.
+    pub fn new(impulse_response: &[f32], num_taps: usize) -> Self {
+        let mut taps = vec![0.0; num_taps];
+        let len = impulse_response.len().min(num_taps);
+        taps[..len].copy_from_slice(&impulse_response[..len]);
+
+        DirectConvolver {
+            taps,
+            history: vec![0.0; num_taps * 2],
+            history_pos: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.history_pos = 0;
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let num_taps = self.taps.len();
+        if num_taps == 0 {
+            return 0.0;
+        }
+
+        // The history is read newest to oldest, so it's filled in backwards
+        self.history_pos = (self.history_pos + num_taps - 1) % num_taps;
+        self.history[self.history_pos] = input;
+        self.history[self.history_pos + num_taps] = input;
+
+        self.history[self.history_pos..self.history_pos + num_taps]
+            .iter()
+            .zip(&self.taps)
+            .map(|(sample, tap)| sample * tap)
+            .sum()
+    }
+}
+
+/// The FFT half of a partitioned convolution stage: the partition spectra, the frequency-domain
+/// delay line, and the FFT plans and scratch buffers needed to compute one block of output. This
+/// is kept separate from [`FftStage`] so it can be moved to a worker thread.
+struct StageCore {
+    block_size: usize,
+    /// The number of blocks between the most recent input block and the block the first partition
+    /// is multiplied with.
+    delay_blocks: usize,
+
+    /// The normalized spectra of the `2 * block_size` zero-padded partitions.
+    ir_spectra: Vec<Vec<Complex32>>,
+    /// The frequency-domain delay line. `input_spectra[fdl_pos]` holds the spectrum of the most
+    /// recent input block, and the spectra of older blocks follow it.
+    input_spectra: Vec<Vec<Complex32>>,
+    fdl_pos: usize,
+
+    r2c_plan: Arc<dyn RealToComplex<f32>>,
+    c2r_plan: Arc<dyn ComplexToReal<f32>>,
+    real_scratch_buffer: Vec<f32>,
+    complex_scratch_buffer: Vec<Complex32>,
+    fft_scratch_buffer: Vec<Complex32>,
+}
+
+impl StageCore {
+    fn new(segment: &[f32], block_size: usize, delay_blocks: usize) -> Self {
+        assert!(!segment.is_empty(), "Empty stages are not supported");
+
+        let fft_size = block_size * 2;
+        let num_bins = fft_size / 2 + 1;
+        let mut planner = RealFftPlanner::new();
+        let r2c_plan = planner.plan_fft_forward(fft_size);
+        let c2r_plan = planner.plan_fft_inverse(fft_size);
+        let fft_scratch_len = r2c_plan.get_scratch_len().max(c2r_plan.get_scratch_len());
+
+        let mut real_scratch_buffer = vec![0.0; fft_size];
+        let mut fft_scratch_buffer = vec![Complex32::default(); fft_scratch_len];
+        let normalization_factor = 1.0 / fft_size as f32;
+        let ir_spectra: Vec<Vec<Complex32>> = segment
+            .chunks(block_size)
+            .map(|partition| {
+                real_scratch_buffer.fill(0.0);
+                real_scratch_buffer[..partition.len()].copy_from_slice(partition);
+
+                let mut spectrum = vec![Complex32::default(); num_bins];
+                r2c_plan
+                    .process_with_scratch(
+                        &mut real_scratch_buffer,
+                        &mut spectrum,
+                        &mut fft_scratch_buffer,
+                    )
+                    .unwrap();
+                for bin in &mut spectrum {
+                    *bin *= normalization_factor;
+                }
+
+                spectrum
+            })
+            .collect();
+        let input_spectra =
+            vec![vec![Complex32::default(); num_bins]; ir_spectra.len() + delay_blocks];
+
+        StageCore {
+            block_size,
+            delay_blocks,
+
+            ir_spectra,
+            input_spectra,
+            fdl_pos: 0,
+
+            r2c_plan,
+            c2r_plan,
+            real_scratch_buffer,
+            complex_scratch_buffer: vec![Complex32::default(); num_bins],
+            fft_scratch_buffer,
+        }
+    }
+
+    fn reset(&mut self) {
+        for spectrum in &mut self.input_spectra {
+            spectrum.fill(Complex32::default());
+        }
+        self.fdl_pos = 0;
+    }
+
+    /// Add the `2 * block_size` overlap-save `input_block` to the delay line, and then compute a
+    /// block of output where the first partition is multiplied with the input from `lag` blocks
+    /// ago.
+    fn compute(&mut self, input_block: &[f32], output: &mut [f32], lag: usize) {
+        let block_size = self.block_size;
+        let fdl_len = self.input_spectra.len();
+
+        self.fdl_pos = (self.fdl_pos + fdl_len - 1) % fdl_len;
+        self.real_scratch_buffer.copy_from_slice(input_block);
+        self.r2c_plan
+            .process_with_scratch(
+                &mut self.real_scratch_buffer,
+                &mut self.input_spectra[self.fdl_pos],
+                &mut self.fft_scratch_buffer,
+            )
+            .unwrap();
+
+        // Partition `k` gets multiplied with the spectrum of the input from `lag + k` blocks ago
+        self.complex_scratch_buffer.fill(Complex32::default());
+        for (partition_idx, ir_spectrum) in self.ir_spectra.iter().enumerate() {
+            let input_spectrum =
+                &self.input_spectra[(self.fdl_pos + lag + partition_idx) % fdl_len];
+            for ((output_bin, input_bin), ir_bin) in self
+                .complex_scratch_buffer
+                .iter_mut()
+                .zip(input_spectrum)
+                .zip(ir_spectrum)
+            {
+                *output_bin += input_bin * ir_bin;
+            }
+        }
+
+        // Rounding errors could leave tiny imaginary parts in these bins, which the inverse real
+        // FFT doesn't accept
+        let last_bin = self.complex_scratch_buffer.len() - 1;
+        self.complex_scratch_buffer[0].im = 0.0;
+        self.complex_scratch_buffer[last_bin].im = 0.0;
+        self.c2r_plan
+            .process_with_scratch(
+                &mut self.complex_scratch_buffer,
+                &mut self.real_scratch_buffer,
+                &mut self.fft_scratch_buffer,
+            )
+            .unwrap();
+
+        // With overlap-save only the second half is valid, the first half contains the circular
+        // convolution's wrapped around tail
+        output.copy_from_slice(&self.real_scratch_buffer[block_size..]);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JobState {
+    /// No job has been submitted since the last reset.
+    Idle,
+    /// `WorkerSlot::input` contains a block the worker hasn't processed yet.
+    Pending,
+    /// `WorkerSlot::output` contains the result for the last submitted block.
+    Done,
+}
+
+/// The buffers shared between the audio thread and a stage's worker thread.
+struct WorkerSlot {
+    input: Vec<f32>,
+    output: Vec<f32>,
+    state: JobState,
+    reset_requested: bool,
+    shutdown: bool,
+}
+
+/// A worker thread that owns a [`StageCore`] and computes one block at a time.
+struct StageWorker {
+    shared: Arc<(Mutex<WorkerSlot>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl StageWorker {
+    fn spawn(mut core: StageCore) -> Self {
+        let block_size = core.block_size;
+        // The result is only picked up at the next block boundary, so the worker uses one block
+        // less of delay than the synchronous version to end up with the exact same output
+        let lag = core.delay_blocks - 1;
+        let shared = Arc::new((
+            Mutex::new(WorkerSlot {
+                input: vec![0.0; block_size * 2],
+                output: vec![0.0; block_size],
+                state: JobState::Idle,
+                reset_requested: false,
+                shutdown: false,
+            }),
+            Condvar::new(),
+        ));
+
+        let worker_shared = shared.clone();
+        let handle = std::thread::Builder::new()
+            .name(format!("convolution-{block_size}"))
+            .spawn(move || {
+                let (lock, condvar) = &*worker_shared;
+                let mut input = vec![0.0; block_size * 2];
+                let mut output = vec![0.0; block_size];
+                loop {
+                    {
+                        let mut slot = lock.lock();
+                        while slot.state != JobState::Pending && !slot.shutdown {
+                            condvar.wait(&mut slot);
+                        }
+                        if slot.shutdown {
+                            return;
+                        }
+                        if slot.reset_requested {
+                            slot.reset_requested = false;
+                            core.reset();
+                        }
+                        input.copy_from_slice(&slot.input);
+                    }
+
+                    core.compute(&input, &mut output, lag);
+
+                    let mut slot = lock.lock();
+                    slot.output.copy_from_slice(&output);
+                    slot.state = JobState::Done;
+                    condvar.notify_all();
+                }
+            })
+            .expect("Could not spawn the convolution worker thread");
+
+        StageWorker {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Wait for the previously submitted block to finish, write its result to `output`, and then
+    /// submit `input_block`.
+    fn exchange(&mut self, input_block: &[f32], output: &mut [f32]) {
+        let (lock, condvar) = &*self.shared;
+        let mut slot = lock.lock();
+        while slot.state == JobState::Pending {
+            condvar.wait(&mut slot);
+        }
+
+        if slot.state == JobState::Done {
+            output.copy_from_slice(&slot.output);
+        } else {
+            output.fill(0.0);
+        }
+        slot.input.copy_from_slice(input_block);
+        slot.state = JobState::Pending;
+        condvar.notify_all();
+    }
+
+    fn reset(&mut self) {
+        let (lock, condvar) = &*self.shared;
+        let mut slot = lock.lock();
+        while slot.state == JobState::Pending {
+            condvar.wait(&mut slot);
+        }
+
+        slot.state = JobState::Idle;
+        slot.reset_requested = true;
+    }
+}
+
+impl Drop for StageWorker {
+    fn drop(&mut self) {
+        {
+            let (lock, condvar) = &*self.shared;
+            lock.lock().shutdown = true;
+            condvar.notify_all();
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+enum StageEngine {
+    Local(StageCore),
+    Worker(StageWorker),
+}
+
+/// One uniformly partitioned segment of an impulse response, convolved using overlap-save FFT
+/// convolution with a frequency-domain delay line. The FFTs introduce `block_size` samples of
+/// latency, so the segment needs to start at least `block_size` samples into the impulse response
+/// for the stage's output to line up without any added latency.
+pub struct FftStage {
+    block_size: usize,
+    engine: StageEngine,
+
+    /// The previous and the current input block, used for the overlap-save FFT.
+    input_block: Vec<f32>,
+    /// The output for the current block, computed at the end of the previous block.
+    output: Vec<f32>,
+    /// The position within the current block.
+    block_pos: usize,
+}
+
+impl FftStage {
+    /// Create a stage for `segment`, which starts `offset` samples into the impulse response.
+    /// `offset` needs to be a non-zero multiple of `block_size`.
+    pub fn new(segment: &[f32], block_size: usize, offset: usize) -> Self {
+        let core = StageCore::new(segment, block_size, Self::delay_blocks(block_size, offset));
+
+        Self::with_engine(block_size, StageEngine::Local(core))
+    }
+
+    /// Like [`new()`][Self::new()], but the FFTs are computed on a dedicated worker thread. The
+    /// worker gets a full block's worth of time to compute its output, and the audio thread only
+    /// waits for it at the next block boundary, so the output is identical to that of a
+    /// synchronous stage. This requires `offset` to be at least `2 * block_size`.
+    pub fn new_threaded(segment: &[f32], block_size: usize, offset: usize) -> Self {
+        assert!(
+            offset >= block_size * 2,
+            "Threaded stages need at least one block of extra delay"
+        );
+        let core = StageCore::new(segment, block_size, Self::delay_blocks(block_size, offset));
+
+        Self::with_engine(block_size, StageEngine::Worker(StageWorker::spawn(core)))
+    }
+
+    fn delay_blocks(block_size: usize, offset: usize) -> usize {
+        assert!(
+            offset >= block_size && offset.is_multiple_of(block_size),
+            "The stage's offset must be a non-zero multiple of its block size"
+        );
+
+        offset / block_size - 1
+    }
+
+    fn with_engine(block_size: usize, engine: StageEngine) -> Self {
+        FftStage {
+            block_size,
+            engine,
+
+            input_block: vec![0.0; block_size * 2],
+            output: vec![0.0; block_size],
+            block_pos: 0,
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn reset(&mut self) {
+        match &mut self.engine {
+            StageEngine::Local(core) => core.reset(),
+            StageEngine::Worker(worker) => worker.reset(),
+        }
+        self.input_block.fill(0.0);
+        self.output.fill(0.0);
+        self.block_pos = 0;
+    }
+
+    /// Feed a sample into the stage and return this stage's contribution to the output at the same
+    /// point in time.
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = self.output[self.block_pos];
+        self.input_block[self.block_size + self.block_pos] = input;
+        self.block_pos += 1;
+        if self.block_pos == self.block_size {
+            self.block_pos = 0;
+            self.finish_block();
+        }
+
+        output
+    }
+
+    /// Compute the stage's output for the next block.
+    fn finish_block(&mut self) {
+        match &mut self.engine {
+            StageEngine::Local(core) => {
+                let lag = core.delay_blocks;
+                core.compute(&self.input_block, &mut self.output, lag);
+            }
+            StageEngine::Worker(worker) => worker.exchange(&self.input_block, &mut self.output),
+        }
+        self.input_block.copy_within(self.block_size.., 0);
+    }
+}
@@ -0,0 +1,62 @@
+use super::stage::{DirectConvolver, FftStage};
+
+/// Zero-latency uniformly partitioned convolution. The first `block_size` taps of the impulse
+/// response (the head) are convolved directly in the time domain, and the rest (the tail) is split
+/// into `block_size` sized partitions that are convolved using overlap-save FFT convolution with a
+/// frequency-domain delay line. The FFT part inherently has `block_size` samples of latency, but
+/// since the tail starts `block_size` samples into the impulse response that latency lines up
+/// exactly and the output is not delayed at all.
+///
+/// Everything is allocated up front, so processing is realtime safe. Changing the impulse response
+/// requires creating a new convolver, which should be done off the audio thread.
+pub struct UniformConvolver {
+    block_size: usize,
+    head: DirectConvolver,
+    /// The FFT convolved part of the impulse response, if it's longer than a single block.
+    tail: Option<FftStage>,
+    num_taps: usize,
+}
+
+impl UniformConvolver {
+    /// Create a convolver for `impulse_response`. `block_size` should be a power of two, and it
+    /// trades off the cost of the direct head against the overhead of the FFTs for the tail.
+    pub fn new(impulse_response: &[f32], block_size: usize) -> Self {
+        assert!(block_size > 0, "The block size must be positive");
+
+        let tail = impulse_response
+            .get(block_size..)
+            .filter(|tail| !tail.is_empty())
+            .map(|tail| FftStage::new(tail, block_size, block_size));
+
+        UniformConvolver {
+            block_size,
+            head: DirectConvolver::new(impulse_response, block_size),
+            tail,
+            num_taps: impulse_response.len(),
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// The length of the impulse response this convolver was created with.
+    pub fn num_taps(&self) -> usize {
+        self.num_taps
+    }
+
+    pub fn reset(&mut self) {
+        self.head.reset();
+        if let Some(tail) = &mut self.tail {
+            tail.reset();
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let head_output = self.head.process(input);
+        match &mut self.tail {
+            Some(tail) => head_output + tail.process(input),
+            None => head_output,
+        }
+    }
+}
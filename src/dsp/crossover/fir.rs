@@ -15,50 +15,72 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use realfft::num_complex::Complex32;
-use realfft::{ComplexToReal, RealToComplex};
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
 use std::f32;
+use std::f64;
+use std::sync::Arc;
 
-use crate::crossover::iir::biquad::{Biquad, BiquadCoefficients};
+use super::iir::biquad::{Biquad, BiquadCoefficients};
+use crate::dsp::response::{self, FrequencyResponse, Response};
 use crate::NUM_CHANNELS;
 
 /// We're doing FFT convolution here since otherwise there's no way to get decent low-frequency
 /// accuracy while still having acceptable performance. The input going into the STFT will be
 /// smaller since it will be padding with zeroes to compensate for the otherwise overlapping tail
-/// caused by the convolution.
+/// caused by the convolution. This is the default FFT size, [`FftFirFilter`] can be instantiated
+/// with any other power of two to trade latency for low-frequency accuracy.
 pub const FFT_SIZE: usize = 4096;
-/// The input chunk size the FFT convolution is processing. This is also part of the latency, with
-/// the total latency being `FFT_INPUT_SIZE + (FILTER_SIZE / 2)` samples. By having this be exactly
-/// half of FFT_SIZE, we can make the overlap-add part of the FFT convolution a lot simpler for
-/// ourselves. (check the `StftHelper` struct in NIH-plug itself for an examples that can handle
-/// arbitrary padding)
-pub const FFT_INPUT_SIZE: usize = FFT_SIZE / 2;
-/// The size of the FIR filter window, or the number of taps. Convoling `FFT_INPUT_SIZE` samples
-/// with this filter should fit exactly in `FFT_SIZE`, and it should be an odd number.
-pub const FILTER_SIZE: usize = FFT_SIZE - FFT_INPUT_SIZE + 1;
+/// The input chunk size the FFT convolution is processing for the default FFT size. This is also
+/// part of the latency, with the total latency being `FFT_INPUT_SIZE + (FILTER_SIZE / 2)` samples.
+/// By having this be exactly half of FFT_SIZE, we can make the overlap-add part of the FFT
+/// convolution a lot simpler for ourselves. (check the `StftHelper` struct in NIH-plug itself for
+/// an examples that can handle arbitrary padding)
+pub const FFT_INPUT_SIZE: usize = FftFirFilter::<FFT_SIZE>::INPUT_SIZE;
+/// The size of the FIR filter window, or the number of taps, for the default FFT size. Convoling
+/// `FFT_INPUT_SIZE` samples with this filter should fit exactly in `FFT_SIZE`, and it should be an
+/// odd number.
+pub const FILTER_SIZE: usize = FftFirFilter::<FFT_SIZE>::FILTER_SIZE;
 
 /// A single FIR filter that may be configured in any way. In this plugin this will be a
 /// linear-phase low-pass, band-pass, or high-pass filter. Implemented using FFT convolution. `git
 /// blame` this for a version that uses direct convolution.
 ///
-/// `N_INPUT` is the size of the input that will be processed. The size of the FFT window becomes
-/// `N_INPUT * 2`. That makes handling the overlap easy, as each IDFT after multiplying the padded
-/// input and the padded impulse response FFTs will result one `N_INPUT` period of output that can
-/// be taken as is, followed by one `N_INPUT` period of samples that need to be added to the next
-/// period's outputs as part of the overlap-add process.
+/// `FFT_SIZE` is the size of the FFT window and needs to be a power of two. The input that will be
+/// processed is `FFT_SIZE / 2` samples long. That makes handling the overlap easy, as each IDFT
+/// after multiplying the padded input and the padded impulse response FFTs will result one
+/// `INPUT_SIZE` period of output that can be taken as is, followed by one `INPUT_SIZE` period of
+/// samples that need to be added to the next period's outputs as part of the overlap-add process.
+/// Larger FFTs allow longer filters and thus better low-frequency accuracy, at the cost of more
+/// latency. The number of channels is set when creating the filter, and all buffers are allocated
+/// up front.
 #[derive(Debug, Clone)]
-pub struct FftFirFilter {
-    /// An `N_INPUT + 1` sized IIR. Padded, ran through the DFT, and then normalized by dividing by
-    /// `FFT_SIZE`.
-    padded_ir_fft: [Complex32; FFT_SIZE / 2 + 1],
+pub struct FftFirFilter<const FFT_SIZE: usize> {
+    /// A `FILTER_SIZE` sized FIR. Padded, ran through the DFT, and then normalized by dividing by
+    /// `FFT_SIZE`. Contains `NUM_BINS` bins.
+    padded_ir_fft: Vec<Complex32>,
+    /// The coefficients `padded_ir_fft` was computed from, padded with zeroes to `FILTER_SIZE`
+    /// taps. Only used to compute the filter's frequency response.
+    coefficients: Vec<f32>,
 
     /// The padding from the previous IDFT operation that needs to be added to the next output
     /// buffer. After the IDFT process there will be an `FFT_SIZE` real scratch buffer containing
-    /// the output. At that point the first `FFT_INPUT_SIZE` samples of those will be copied to
+    /// the output. At that point the first `INPUT_SIZE` samples of those will be copied to
     /// `output_buffers` in the FIR crossover, `unapplied_padding_buffer` will be added to that
-    /// output buffer, and then finally the last `FFT_INPUT_SIZE` samples of the scratch buffer are
+    /// output buffer, and then finally the last `INPUT_SIZE` samples of the scratch buffer are
     /// copied to `unapplied_padding_buffer`. This thus makes sure the tail gets delayed by another
-    /// period so that everything matches up.
-    unapplied_padding_buffers: [[f32; FFT_INPUT_SIZE]; NUM_CHANNELS as usize],
+    /// period so that everything matches up. Contains one `INPUT_SIZE` buffer per channel.
+    unapplied_padding_buffers: Vec<Vec<f32>>,
+}
+
+/// The phase response to use when designing a FIR filter from only a magnitude response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FirPhase {
+    /// A symmetrical kernel with a constant group delay of `N / 2` samples. Requires an odd number
+    /// of taps.
+    Linear,
+    /// The minimum-phase kernel with the same magnitude response, computed using the cepstral
+    /// method. This has almost no latency, but the group delay varies with frequency.
+    Minimum,
 }
 
 /// Coefficients for a (linear-phase) FIR filter. This struct includes ways to design the filter.
@@ -67,15 +89,9 @@ pub struct FftFirFilter {
 #[derive(Debug, Clone)]
 pub struct FirCoefficients<const N: usize>(pub [f32; N]);
 
-impl Default for FftFirFilter {
+impl<const FFT_SIZE: usize> Default for FftFirFilter<FFT_SIZE> {
     fn default() -> Self {
-        Self {
-            // Would be nicer to initialize this to an impulse response that actually had the
-            // correct position wrt the usual linear-phase latency, but this is fine since it should
-            // never be used anyways
-            padded_ir_fft: [Complex32::new(1.0 / FFT_SIZE as f32, 0.0); FFT_SIZE / 2 + 1],
-            unapplied_padding_buffers: [[0.0; FFT_INPUT_SIZE]; NUM_CHANNELS as usize],
-        }
+        Self::new(NUM_CHANNELS as usize)
     }
 }
 
@@ -90,21 +106,66 @@ impl<const N: usize> Default for FirCoefficients<N> {
     }
 }
 
-impl FftFirFilter {
-    /// Filter `FFT_INPUT_SIZE` samples padded to `FFT_SIZE` through this filter, and write the
-    /// outputs to `output_samples` (belonging to channel `channel_idx`), at an `FFT_INPUT_SIZE`
-    /// delay. This is a bit weird and probably difficult to follow because as an optimization the
-    /// DFT is taken only once, and then the IDFT is taken once for every filtered band. This
-    /// function is thus called inside of the overlap-add loop to avoid duplicate work.
+impl<const FFT_SIZE: usize> FftFirFilter<FFT_SIZE> {
+    /// The input chunk size the FFT convolution is processing. This is also part of the latency.
+    pub const INPUT_SIZE: usize = FFT_SIZE / 2;
+    /// The size of the FIR filter window, or the number of taps. Convoling `INPUT_SIZE` samples
+    /// with this filter should fit exactly in `FFT_SIZE`.
+    pub const FILTER_SIZE: usize = FFT_SIZE - Self::INPUT_SIZE + 1;
+    /// The number of bins in the real FFTs used by this filter.
+    pub const NUM_BINS: usize = FFT_SIZE / 2 + 1;
+    /// The total latency of a linear-phase filter with `FILTER_SIZE` taps, in samples.
+    pub const LATENCY: usize = Self::INPUT_SIZE + Self::FILTER_SIZE / 2;
+
+    /// Create a filter for `num_channels` channels. Until coefficients are set this passes the
+    /// input through with `INPUT_SIZE` samples of delay.
+    pub fn new(num_channels: usize) -> Self {
+        const {
+            assert!(
+                FFT_SIZE.is_power_of_two() && FFT_SIZE >= 4,
+                "The FFT size must be a power of two"
+            )
+        };
+
+        Self {
+            // Would be nicer to initialize this to an impulse response that actually had the
+            // correct position wrt the usual linear-phase latency, but this is fine since it should
+            // never be used anyways
+            padded_ir_fft: vec![Complex32::new(1.0 / FFT_SIZE as f32, 0.0); Self::NUM_BINS],
+            coefficients: (0..Self::FILTER_SIZE)
+                .map(|tap_idx| if tap_idx == 0 { 1.0 } else { 0.0 })
+                .collect(),
+            unapplied_padding_buffers: vec![vec![0.0; Self::INPUT_SIZE]; num_channels],
+        }
+    }
+
+    /// The number of channels this filter was created for.
+    pub fn num_channels(&self) -> usize {
+        self.unapplied_padding_buffers.len()
+    }
+
+    /// Filter `INPUT_SIZE` samples padded to `FFT_SIZE` through this filter, and write the outputs
+    /// to `output_samples` (belonging to channel `channel_idx`), at an `INPUT_SIZE` delay. This is
+    /// a bit weird and probably difficult to follow because as an optimization the DFT is taken
+    /// only once, and then the IDFT is taken once for every filtered band. This function is thus
+    /// called inside of the overlap-add loop to avoid duplicate work.
+    ///
+    /// `input_fft` and `complex_scratch_buffer` need to contain `NUM_BINS` bins,
+    /// `output_samples` needs to be `INPUT_SIZE` samples long, and `real_scratch_buffer` needs to
+    /// be `FFT_SIZE` samples long.
     pub fn process(
         &mut self,
-        input_fft: &[Complex32; FFT_SIZE / 2 + 1],
-        output_samples: &mut [f32; FFT_INPUT_SIZE],
+        input_fft: &[Complex32],
+        output_samples: &mut [f32],
         output_channel_idx: usize,
         c2r_plan: &dyn ComplexToReal<f32>,
-        real_scratch_buffer: &mut [f32; FFT_SIZE],
-        complex_scratch_buffer: &mut [Complex32; FFT_SIZE / 2 + 1],
+        real_scratch_buffer: &mut [f32],
+        complex_scratch_buffer: &mut [Complex32],
     ) {
+        debug_assert_eq!(input_fft.len(), Self::NUM_BINS);
+        debug_assert_eq!(output_samples.len(), Self::INPUT_SIZE);
+        debug_assert_eq!(real_scratch_buffer.len(), FFT_SIZE);
+
         // The padded input FFT has already been taken, so we only need to copy it to the scratch
         // buffer (the input cannot change as the next band might need it as well).
         complex_scratch_buffer.copy_from_slice(input_fft);
@@ -121,12 +182,12 @@ impl FftFirFilter {
             .process_with_scratch(complex_scratch_buffer, real_scratch_buffer, &mut [])
             .unwrap();
 
-        // At this point the first `FFT_INPUT_SIZE` elements in `real_scratch_buffer`
-        // contain the output for the next period, while the last `FFT_INPUT_SIZE` elements
+        // At this point the first `INPUT_SIZE` elements in `real_scratch_buffer`
+        // contain the output for the next period, while the last `INPUT_SIZE` elements
         // contain output that needs to be added to the period after that. Since previous
         // period also produced similar delayed output, we'll need to copy that to the
         // results as well.
-        output_samples.copy_from_slice(&real_scratch_buffer[..FFT_INPUT_SIZE]);
+        output_samples.copy_from_slice(&real_scratch_buffer[..Self::INPUT_SIZE]);
         for (output_sample, padding_sample) in output_samples
             .iter_mut()
             .zip(self.unapplied_padding_buffers[output_channel_idx].iter())
@@ -134,21 +195,31 @@ impl FftFirFilter {
             *output_sample += *padding_sample;
         }
         self.unapplied_padding_buffers[output_channel_idx]
-            .copy_from_slice(&real_scratch_buffer[FFT_INPUT_SIZE..]);
+            .copy_from_slice(&real_scratch_buffer[Self::INPUT_SIZE..]);
     }
 
     /// Set the filter's coefficients based on raw FIR filter coefficients. These will be padded,
-    /// ran through the DFT, and normalized.
-    pub fn recompute_coefficients(
+    /// ran through the DFT, and normalized. Filters can have at most `FILTER_SIZE` taps, and
+    /// shorter filters are simply padded with more zeroes.
+    pub fn recompute_coefficients<const N: usize>(
         &mut self,
-        coefficients: FirCoefficients<FILTER_SIZE>,
+        coefficients: &FirCoefficients<N>,
         r2c_plan: &dyn RealToComplex<f32>,
-        real_scratch_buffer: &mut [f32; FFT_SIZE],
-        complex_scratch_buffer: &mut [Complex32; FFT_SIZE / 2 + 1],
+        real_scratch_buffer: &mut [f32],
+        complex_scratch_buffer: &mut [Complex32],
     ) {
+        const {
+            assert!(
+                N <= Self::FILTER_SIZE,
+                "The filter does not fit in the FFT window"
+            )
+        };
+
         // This needs to be padded with zeroes
-        real_scratch_buffer[..FILTER_SIZE].copy_from_slice(&coefficients.0);
-        real_scratch_buffer[FILTER_SIZE..].fill(0.0);
+        real_scratch_buffer[..N].copy_from_slice(&coefficients.0);
+        real_scratch_buffer[N..].fill(0.0);
+        self.coefficients[..N].copy_from_slice(&coefficients.0);
+        self.coefficients[N..].fill(0.0);
 
         r2c_plan
             .process_with_scratch(real_scratch_buffer, complex_scratch_buffer, &mut [])
@@ -174,6 +245,160 @@ impl FftFirFilter {
     }
 }
 
+impl<const FFT_SIZE: usize> FrequencyResponse for FftFirFilter<FFT_SIZE> {
+    /// The response of the current coefficients. This does not include the latency of the
+    /// buffering around the filter, see [`BufferedFftFirFilter`] for that.
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        let coefficients: Vec<f64> = self.coefficients.iter().map(|c| *c as f64).collect();
+        response::transfer_function_response(&coefficients, &[1.0], frequency, sample_rate)
+    }
+}
+
+/// Wraps an [`FftFirFilter`] so it can be used with whatever block sizes the host throws at it,
+/// similar to NIH-plug's `StftHelper`. Incoming samples are collected in per-channel input buffers,
+/// and once `INPUT_SIZE` samples have been gathered the block is filtered and its output is
+/// played back while the next input block is being collected. This results in a fixed latency of
+/// `INPUT_SIZE` samples on top of the filter's own group delay, see
+/// [`latency_samples()`][Self::latency_samples()].
+pub struct BufferedFftFirFilter<const FFT_SIZE: usize> {
+    filter: FftFirFilter<FFT_SIZE>,
+    /// The samples collected for the next block, one `INPUT_SIZE` buffer per channel.
+    input_buffers: Vec<Vec<f32>>,
+    /// The filtered samples for the block that's currently being played back.
+    output_buffers: Vec<Vec<f32>>,
+    /// The current position in both the input and the output buffers.
+    buffer_pos: usize,
+    /// The group delay of the current (linear-phase) coefficients, in samples.
+    filter_latency: usize,
+
+    r2c_plan: Arc<dyn RealToComplex<f32>>,
+    c2r_plan: Arc<dyn ComplexToReal<f32>>,
+    real_scratch_buffer: Vec<f32>,
+    complex_scratch_buffer: Vec<Complex32>,
+    input_fft_buffer: Vec<Complex32>,
+}
+
+impl<const FFT_SIZE: usize> BufferedFftFirFilter<FFT_SIZE> {
+    /// Create a filter for `num_channels` channels. Until coefficients are set this acts as a delay
+    /// of `INPUT_SIZE` samples.
+    pub fn new(num_channels: usize) -> Self {
+        let mut planner = RealFftPlanner::new();
+
+        Self {
+            filter: FftFirFilter::new(num_channels),
+            input_buffers: vec![vec![0.0; FftFirFilter::<FFT_SIZE>::INPUT_SIZE]; num_channels],
+            output_buffers: vec![vec![0.0; FftFirFilter::<FFT_SIZE>::INPUT_SIZE]; num_channels],
+            buffer_pos: 0,
+            filter_latency: 0,
+
+            r2c_plan: planner.plan_fft_forward(FFT_SIZE),
+            c2r_plan: planner.plan_fft_inverse(FFT_SIZE),
+            real_scratch_buffer: vec![0.0; FFT_SIZE],
+            complex_scratch_buffer: vec![Complex32::default(); FftFirFilter::<FFT_SIZE>::NUM_BINS],
+            input_fft_buffer: vec![Complex32::default(); FftFirFilter::<FFT_SIZE>::NUM_BINS],
+        }
+    }
+
+    /// The latency introduced by this filter in samples, assuming the coefficients are
+    /// linear-phase. This should be reported to the host using `context.set_latency_samples()`.
+    pub fn latency_samples(&self) -> u32 {
+        (FftFirFilter::<FFT_SIZE>::INPUT_SIZE + self.filter_latency) as u32
+    }
+
+    /// Set new (linear-phase) filter coefficients. Does not allocate, so this can be called from
+    /// the audio thread. Changing the number of taps also changes
+    /// [`latency_samples()`][Self::latency_samples()]. Minimum-phase coefficients work too, but the
+    /// reported latency will then include a group delay that isn't actually there.
+    pub fn set_coefficients<const N: usize>(&mut self, coefficients: &FirCoefficients<N>) {
+        self.filter.recompute_coefficients(
+            coefficients,
+            &*self.r2c_plan,
+            &mut self.real_scratch_buffer,
+            &mut self.complex_scratch_buffer,
+        );
+        self.filter_latency = N / 2;
+    }
+
+    /// Clear all buffered input and output along with the filter's overlap-add tails. Should be
+    /// called from the plugin's `reset()` function so transport jumps don't replay stale audio.
+    pub fn reset(&mut self) {
+        for buffer in self
+            .input_buffers
+            .iter_mut()
+            .chain(self.output_buffers.iter_mut())
+        {
+            buffer.fill(0.0);
+        }
+        self.buffer_pos = 0;
+        self.filter.reset();
+    }
+
+    /// Filter a block of audio in place. `buffer` contains one slice per channel, all with the
+    /// same length, and that length can be anything.
+    pub fn process(&mut self, buffer: &mut [&mut [f32]]) {
+        debug_assert!(buffer.len() <= self.input_buffers.len());
+
+        let num_samples = buffer.first().map_or(0, |channel| channel.len());
+        for sample_idx in 0..num_samples {
+            for (channel_idx, channel) in buffer.iter_mut().enumerate() {
+                self.input_buffers[channel_idx][self.buffer_pos] = channel[sample_idx];
+                channel[sample_idx] = self.output_buffers[channel_idx][self.buffer_pos];
+            }
+
+            self.buffer_pos += 1;
+            if self.buffer_pos == FftFirFilter::<FFT_SIZE>::INPUT_SIZE {
+                self.buffer_pos = 0;
+                self.process_block(buffer.len());
+            }
+        }
+    }
+
+    /// Filter the collected input blocks for the first `num_channels` channels.
+    fn process_block(&mut self, num_channels: usize) {
+        for channel_idx in 0..num_channels {
+            let input_size = FftFirFilter::<FFT_SIZE>::INPUT_SIZE;
+            self.real_scratch_buffer[..input_size]
+                .copy_from_slice(&self.input_buffers[channel_idx]);
+            self.real_scratch_buffer[input_size..].fill(0.0);
+            self.r2c_plan
+                .process_with_scratch(
+                    &mut self.real_scratch_buffer,
+                    &mut self.input_fft_buffer,
+                    &mut [],
+                )
+                .unwrap();
+
+            self.filter.process(
+                &self.input_fft_buffer,
+                &mut self.output_buffers[channel_idx],
+                channel_idx,
+                &*self.c2r_plan,
+                &mut self.real_scratch_buffer,
+                &mut self.complex_scratch_buffer,
+            );
+        }
+    }
+}
+
+impl<const FFT_SIZE: usize> FrequencyResponse for BufferedFftFirFilter<FFT_SIZE> {
+    /// The response of the current coefficients, including the `INPUT_SIZE` samples of latency
+    /// added by the buffering.
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        let response = self.filter.frequency_response(frequency, sample_rate);
+        let buffering_delay = FftFirFilter::<FFT_SIZE>::INPUT_SIZE as f64;
+        let omega = 2.0 * f64::consts::PI * frequency as f64 / sample_rate as f64;
+        let phase = response.phase as f64 - omega * buffering_delay;
+
+        Response {
+            magnitude: response.magnitude,
+            // Wrapped to `[-pi, pi]` like the other responses
+            phase: ((phase + f64::consts::PI).rem_euclid(f64::consts::TAU) - f64::consts::PI)
+                as f32,
+            group_delay: response.group_delay + buffering_delay as f32,
+        }
+    }
+}
+
 impl<const N: usize> FirCoefficients<N> {
     /// A somewhat crude but very functional and relatively fast way create linear phase FIR
     /// **low-pass** filter that matches the frequency response of a fourth order biquad low-pass
@@ -205,7 +430,9 @@ impl<const N: usize> FirCoefficients<N> {
     /// - The non-reversed bidirectionally filtered impulse response is copied to the second half of
     ///   the coefficients. (one of the copies doesn't need to include the centermost coefficient)
     ///
-    /// The corresponding high-pass filter can be computed through spectral inversion.
+    /// The corresponding high-pass filter can be computed through spectral inversion, see
+    /// [`spectrally_inverted()`][Self::spectrally_inverted()] and
+    /// [`design_linear_phase_lr4_high_pass()`][Self::design_linear_phase_lr4_high_pass()].
     pub fn design_fourth_order_linear_phase_low_pass_from_biquad(
         biquad_coefs: BiquadCoefficients<f32>,
     ) -> Self {
@@ -261,4 +488,237 @@ impl<const N: usize> FirCoefficients<N> {
 
         Self(impulse_response)
     }
-}
\ No newline at end of file
+
+    /// A linear-phase low-pass with the magnitude response of a fourth order Linkwitz-Riley
+    /// low-pass at `frequency`. This is simply a Butterworth biquad applied in both directions.
+    pub fn design_linear_phase_lr4_low_pass(sample_rate: f32, frequency: f32) -> Self {
+        Self::design_fourth_order_linear_phase_low_pass_from_biquad(BiquadCoefficients::lowpass(
+            sample_rate,
+            frequency,
+            f32::consts::FRAC_1_SQRT_2,
+        ))
+    }
+
+    /// A linear-phase high-pass with the magnitude response of a fourth order Linkwitz-Riley
+    /// high-pass at `frequency`. The squared magnitude responses of a Butterworth low-pass and
+    /// high-pass sum to one, so the spectral inversion of the matching low-pass has exactly this
+    /// magnitude response.
+    pub fn design_linear_phase_lr4_high_pass(sample_rate: f32, frequency: f32) -> Self {
+        Self::design_linear_phase_lr4_low_pass(sample_rate, frequency).spectrally_inverted()
+    }
+
+    /// A linear-phase band-pass between `low_frequency` and `high_frequency`, computed as the
+    /// difference between the LR4 low-passes at those frequencies. Its edges thus match the
+    /// crossover slopes of the adjacent low-pass and high-pass bands.
+    pub fn design_linear_phase_lr4_band_pass(
+        sample_rate: f32,
+        low_frequency: f32,
+        high_frequency: f32,
+    ) -> Self {
+        Self::design_linear_phase_lr4_low_pass(sample_rate, high_frequency).subtract(
+            &Self::design_linear_phase_lr4_low_pass(sample_rate, low_frequency),
+        )
+    }
+
+    /// Design band `band_idx` of a linear-phase LR4 crossover with the (sorted) crossover
+    /// `frequencies`. There are `frequencies.len() + 1` bands, with the first one being a low-pass,
+    /// the last one a high-pass, and everything in between band-passes. Because every band is built
+    /// from the same low-passes, the bands sum back to a pure delay of `N / 2` samples.
+    pub fn design_linear_phase_lr4_crossover_band(
+        sample_rate: f32,
+        frequencies: &[f32],
+        band_idx: usize,
+    ) -> Self {
+        assert!(band_idx <= frequencies.len());
+
+        match (band_idx.checked_sub(1), frequencies.get(band_idx)) {
+            (None, Some(&high_frequency)) => {
+                Self::design_linear_phase_lr4_low_pass(sample_rate, high_frequency)
+            }
+            (Some(low_idx), Some(&high_frequency)) => Self::design_linear_phase_lr4_band_pass(
+                sample_rate,
+                frequencies[low_idx],
+                high_frequency,
+            ),
+            (Some(low_idx), None) => {
+                Self::design_linear_phase_lr4_high_pass(sample_rate, frequencies[low_idx])
+            }
+            // Without any crossover frequencies there's only a single band
+            (None, None) => Self::default(),
+        }
+    }
+
+    /// Spectrally invert this linear-phase filter, turning a low-pass into a high-pass with a
+    /// complementary magnitude response. This subtracts the filter from a unit impulse at the
+    /// center tap, so the filter needs to be linear-phase with an odd number of taps.
+    pub fn spectrally_inverted(mut self) -> Self {
+        for coefficient in &mut self.0 {
+            *coefficient = -*coefficient;
+        }
+        self.0[N / 2] += 1.0;
+
+        self
+    }
+
+    /// Subtract `other`'s coefficients from these coefficients.
+    pub fn subtract(mut self, other: &Self) -> Self {
+        for (coefficient, other_coefficient) in self.0.iter_mut().zip(other.0.iter()) {
+            *coefficient -= *other_coefficient;
+        }
+
+        self
+    }
+
+    /// Design a filter from a magnitude response given as `(frequency, gain)` points, with
+    /// frequencies in Hertz sorted in ascending order and linear gains. The response is
+    /// interpolated in decibels on a logarithmic frequency axis, and it's held constant below the
+    /// first and above the last point. This allocates.
+    pub fn design_from_magnitude_points(
+        sample_rate: f32,
+        points: &[(f32, f32)],
+        phase: FirPhase,
+    ) -> Self {
+        assert!(!points.is_empty(), "At least one point is needed");
+        debug_assert!(points.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+
+        // Interpolating in decibels means zero gains need to be clamped to something finite
+        let points_db: Vec<(f32, f32)> = points
+            .iter()
+            .map(|&(frequency, gain)| (frequency.max(1e-3).ln(), gain_to_db(gain)))
+            .collect();
+        Self::design_from_magnitude_fn(
+            |normalized_frequency| {
+                let log_frequency = (normalized_frequency * sample_rate).max(1e-3).ln();
+                let next_idx = points_db.partition_point(|&(point, _)| point < log_frequency);
+                let gain_db = if next_idx == 0 {
+                    points_db[0].1
+                } else if next_idx == points_db.len() {
+                    points_db[next_idx - 1].1
+                } else {
+                    let (prev_frequency, prev_gain) = points_db[next_idx - 1];
+                    let (next_frequency, next_gain) = points_db[next_idx];
+                    let t = (log_frequency - prev_frequency) / (next_frequency - prev_frequency);
+                    prev_gain + (next_gain - prev_gain) * t
+                };
+
+                db_to_gain(gain_db)
+            },
+            phase,
+        )
+    }
+
+    /// Design a filter from a densely sampled magnitude response. `magnitudes` contains linear
+    /// gains at evenly spaced frequencies from DC up to and including the Nyquist frequency, and it
+    /// is linearly interpolated onto the design grid. This allocates.
+    pub fn design_from_magnitude_curve(magnitudes: &[f32], phase: FirPhase) -> Self {
+        assert!(magnitudes.len() >= 2, "The curve needs at least two points");
+
+        let last_idx = (magnitudes.len() - 1) as f32;
+        Self::design_from_magnitude_fn(
+            |normalized_frequency| {
+                let position = (normalized_frequency * 2.0 * last_idx).clamp(0.0, last_idx);
+                let idx = (position.floor() as usize).min(magnitudes.len() - 2);
+                let t = position - idx as f32;
+                magnitudes[idx] + (magnitudes[idx + 1] - magnitudes[idx]) * t
+            },
+            phase,
+        )
+    }
+
+    /// Frequency sampling: evaluate `magnitude` (taking frequencies normalized to the sample rate,
+    /// so `[0, 0.5]`) on a dense FFT grid and turn that into a windowed kernel with the requested
+    /// phase response.
+    fn design_from_magnitude_fn(magnitude: impl Fn(f32) -> f32, phase: FirPhase) -> Self {
+        // The grid needs to be quite a bit denser than the number of taps, both to reduce time
+        // domain aliasing and, for minimum-phase filters, to keep the cepstrum from wrapping around
+        let grid_size = (N * 8).next_power_of_two().max(1024);
+        let num_bins = grid_size / 2 + 1;
+        let mut planner = RealFftPlanner::new();
+        let r2c_plan = planner.plan_fft_forward(grid_size);
+        let c2r_plan = planner.plan_fft_inverse(grid_size);
+        let mut real_buffer = vec![0.0; grid_size];
+        let mut spectrum: Vec<Complex32> = (0..num_bins)
+            .map(|bin_idx| Complex32::new(magnitude(bin_idx as f32 / grid_size as f32), 0.0))
+            .collect();
+
+        let mut coefficients = [0.0; N];
+        match phase {
+            FirPhase::Linear => {
+                assert!(
+                    N % 2 == 1,
+                    "Linear-phase filters need an odd number of taps"
+                );
+
+                // The zero-phase impulse response is centered around the first sample and wraps
+                // around, so it only needs to be rotated to the center of the kernel
+                c2r_plan.process(&mut spectrum, &mut real_buffer).unwrap();
+                let center_idx = N / 2;
+                let blackman_scale_1 = (2.0 * f32::consts::PI) / (N - 1) as f32;
+                let blackman_scale_2 = blackman_scale_1 * 2.0;
+                for (tap_idx, coefficient) in coefficients.iter_mut().enumerate() {
+                    let source_idx = (tap_idx + grid_size - center_idx) % grid_size;
+                    let cos_1 = (blackman_scale_1 * tap_idx as f32).cos();
+                    let cos_2 = (blackman_scale_2 * tap_idx as f32).cos();
+                    let window = 0.42 - (0.5 * cos_1) + (0.08 * cos_2);
+                    *coefficient = real_buffer[source_idx] / grid_size as f32 * window;
+                }
+            }
+            FirPhase::Minimum => {
+                // The real cepstrum of the magnitude response...
+                for bin in &mut spectrum {
+                    *bin = Complex32::new(bin.re.max(1e-6).ln(), 0.0);
+                }
+                c2r_plan.process(&mut spectrum, &mut real_buffer).unwrap();
+
+                // ...is folded onto the positive quefrencies, which makes it causal...
+                let normalization_factor = 1.0 / grid_size as f32;
+                for (idx, sample) in real_buffer.iter_mut().enumerate() {
+                    *sample *= match idx {
+                        0 => normalization_factor,
+                        idx if idx == grid_size / 2 => normalization_factor,
+                        idx if idx < grid_size / 2 => 2.0 * normalization_factor,
+                        _ => 0.0,
+                    };
+                }
+
+                // ...and exponentiating its spectrum gives the minimum-phase spectrum
+                r2c_plan.process(&mut real_buffer, &mut spectrum).unwrap();
+                for bin in &mut spectrum {
+                    *bin = bin.exp();
+                }
+                spectrum[0].im = 0.0;
+                spectrum[num_bins - 1].im = 0.0;
+                c2r_plan.process(&mut spectrum, &mut real_buffer).unwrap();
+
+                // Everything past `N` taps gets cut off, so the right half of a Blackman window is
+                // used to fade out the tail
+                let blackman_scale_1 = f32::consts::PI / N as f32;
+                let blackman_scale_2 = blackman_scale_1 * 2.0;
+                for (tap_idx, coefficient) in coefficients.iter_mut().enumerate() {
+                    let cos_1 = (blackman_scale_1 * (tap_idx + N) as f32).cos();
+                    let cos_2 = (blackman_scale_2 * (tap_idx + N) as f32).cos();
+                    let window = 0.42 - (0.5 * cos_1) + (0.08 * cos_2);
+                    *coefficient = real_buffer[tap_idx] / grid_size as f32 * window;
+                }
+            }
+        }
+
+        Self(coefficients)
+    }
+}
+
+impl<const N: usize> FrequencyResponse for FirCoefficients<N> {
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        let coefficients: Vec<f64> = self.0.iter().map(|c| *c as f64).collect();
+        response::transfer_function_response(&coefficients, &[1.0], frequency, sample_rate)
+    }
+}
+
+/// Gains below -120 dB are treated as -120 dB.
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-6).log10()
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
@@ -0,0 +1,223 @@
+// Crossover: clean crossovers as a multi-out plugin
+// Copyright (C) 2022-2024 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::f32::consts;
+
+use crate::dsp::response::{self, FrequencyResponse, Response};
+
+/// A simple biquad filter with functions for generating coefficients for second order low-pass,
+/// high-pass, and all-pass filters. Uses the transposed direct form II, which works well when the
+/// coefficients change.
+///
+/// Based on <https://en.wikipedia.org/wiki/Digital_biquad_filter#Transposed_direct_forms>.
+///
+/// The type parameter `T` is the sample type. Only `f32` is implemented for now.
+#[derive(Clone, Copy, Debug)]
+pub struct Biquad<T> {
+    pub coefficients: BiquadCoefficients<T>,
+    s1: T,
+    s2: T,
+}
+
+/// The coefficients `[b0, b1, b2, a1, a2]` for [`Biquad`]. These coefficients are all
+/// prenormalized, i.e. they have been divided by `a0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiquadCoefficients<T> {
+    pub b0: T,
+    pub b1: T,
+    pub b2: T,
+    pub a1: T,
+    pub a2: T,
+}
+
+impl Default for Biquad<f32> {
+    /// Before setting coefficients, this acts as an identity function.
+    fn default() -> Self {
+        Self {
+            coefficients: BiquadCoefficients::identity(),
+            s1: 0.0,
+            s2: 0.0,
+        }
+    }
+}
+
+impl Biquad<f32> {
+    /// Process a single sample.
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let result = self.coefficients.b0 * sample + self.s1;
+
+        self.s1 = self.coefficients.b1 * sample - self.coefficients.a1 * result + self.s2;
+        self.s2 = self.coefficients.b2 * sample - self.coefficients.a2 * result;
+
+        result
+    }
+
+    /// Reset the state to zero, useful after making large, non-interpolatable changes to the
+    /// filter coefficients.
+    pub fn reset(&mut self) {
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+    }
+}
+
+impl FrequencyResponse for Biquad<f32> {
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } = self.coefficients;
+        response::transfer_function_response(
+            &[b0 as f64, b1 as f64, b2 as f64],
+            &[1.0, a1 as f64, a2 as f64],
+            frequency,
+            sample_rate,
+        )
+    }
+}
+
+/// A series of biquads, used to run higher order filters as a cascade of second-order sections.
+/// See [`crate::dsp::iir_design`] for designing these.
+#[derive(Clone, Debug, Default)]
+pub struct BiquadCascade {
+    sections: Vec<Biquad<f32>>,
+}
+
+impl BiquadCascade {
+    pub fn new(coefficients: &[BiquadCoefficients<f32>]) -> Self {
+        let mut cascade = Self::default();
+        cascade.set_coefficients(coefficients);
+
+        cascade
+    }
+
+    /// Replace the coefficients of all sections. The filter state is kept as long as the number
+    /// of sections doesn't change, so this can be used for parameter changes.
+    pub fn set_coefficients(&mut self, coefficients: &[BiquadCoefficients<f32>]) {
+        if coefficients.len() != self.sections.len() {
+            self.sections.clear();
+            self.sections
+                .resize_with(coefficients.len(), Biquad::default);
+        }
+
+        for (section, coefficients) in self.sections.iter_mut().zip(coefficients) {
+            section.coefficients = *coefficients;
+        }
+    }
+
+    pub fn num_sections(&self) -> usize {
+        self.sections.len()
+    }
+
+    /// Process a single sample.
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.sections
+            .iter_mut()
+            .fold(sample, |sample, section| section.process(sample))
+    }
+
+    pub fn reset(&mut self) {
+        for section in &mut self.sections {
+            section.reset();
+        }
+    }
+}
+
+impl FrequencyResponse for BiquadCascade {
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        let mut magnitude = 1.0;
+        let mut phase = 0.0;
+        let mut group_delay = 0.0;
+        for section in &self.sections {
+            let response = section.frequency_response(frequency, sample_rate);
+            magnitude *= response.magnitude;
+            phase += response.phase;
+            group_delay += response.group_delay;
+        }
+
+        Response {
+            magnitude,
+            // Wrapped to `[-pi, pi]` like the other responses
+            phase: (phase + consts::PI).rem_euclid(consts::TAU) - consts::PI,
+            group_delay,
+        }
+    }
+}
+
+impl BiquadCoefficients<f32> {
+    /// Create coefficients from prenormalized values.
+    pub fn from_f32s(b0: f32, b1: f32, b2: f32, a1: f32, a2: f32) -> Self {
+        Self { b0, b1, b2, a1, a2 }
+    }
+
+    /// Filter coefficients that would cause the sound to be passed through as is.
+    pub fn identity() -> Self {
+        Self::from_f32s(1.0, 0.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Compute the coefficients for a low-pass filter.
+    ///
+    /// Based on <http://shepazu.github.io/Audio-EQ-Cookbook/audio-eq-cookbook.html>.
+    pub fn lowpass(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let omega0 = consts::TAU * (frequency / sample_rate);
+        let cos_omega0 = omega0.cos();
+        let alpha = omega0.sin() / (2.0 * q);
+
+        // We'll prenormalize everything with a0
+        let a0 = 1.0 + alpha;
+        let b0 = ((1.0 - cos_omega0) / 2.0) / a0;
+        let b1 = (1.0 - cos_omega0) / a0;
+        let b2 = ((1.0 - cos_omega0) / 2.0) / a0;
+        let a1 = (-2.0 * cos_omega0) / a0;
+        let a2 = (1.0 - alpha) / a0;
+
+        Self::from_f32s(b0, b1, b2, a1, a2)
+    }
+
+    /// Compute the coefficients for a high-pass filter.
+    ///
+    /// Based on <http://shepazu.github.io/Audio-EQ-Cookbook/audio-eq-cookbook.html>.
+    pub fn highpass(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let omega0 = consts::TAU * (frequency / sample_rate);
+        let cos_omega0 = omega0.cos();
+        let alpha = omega0.sin() / (2.0 * q);
+
+        // We'll prenormalize everything with a0
+        let a0 = 1.0 + alpha;
+        let b0 = ((1.0 + cos_omega0) / 2.0) / a0;
+        let b1 = -(1.0 + cos_omega0) / a0;
+        let b2 = ((1.0 + cos_omega0) / 2.0) / a0;
+        let a1 = (-2.0 * cos_omega0) / a0;
+        let a2 = (1.0 - alpha) / a0;
+
+        Self::from_f32s(b0, b1, b2, a1, a2)
+    }
+
+    /// Compute the coefficients for an all-pass filter.
+    ///
+    /// Based on <http://shepazu.github.io/Audio-EQ-Cookbook/audio-eq-cookbook.html>.
+    pub fn allpass(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let omega0 = consts::TAU * (frequency / sample_rate);
+        let cos_omega0 = omega0.cos();
+        let alpha = omega0.sin() / (2.0 * q);
+
+        // We'll prenormalize everything with a0
+        let a0 = 1.0 + alpha;
+        let b0 = (1.0 - alpha) / a0;
+        let b1 = (-2.0 * cos_omega0) / a0;
+        let b2 = (1.0 + alpha) / a0;
+        let a1 = (-2.0 * cos_omega0) / a0;
+        let a2 = (1.0 - alpha) / a0;
+
+        Self::from_f32s(b0, b1, b2, a1, a2)
+    }
+}
@@ -0,0 +1,2 @@
+pub mod fir;
+pub mod iir;
\ No newline at end of file
@@ -39,6 +39,39 @@ pub mod wave_shapers {
         x.min(t).max(-t)
     }
 
+    /// The curves above that saturate, as a selectable option for processors that let the user
+    /// choose one. `sigmoid` is left out because it grows faster than its input. The red clipper
+    /// is shifted so it passes through the origin, but both it and the green clipper are
+    /// asymmetric and turn part of any signal going through them into a DC offset. Only the hard
+    /// clipper is symmetric and passes signals below full scale unchanged, so it's the default.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum WaveShaper {
+        GreenClipper,
+        RedClipper,
+        #[default]
+        HardClipper,
+    }
+
+    impl WaveShaper {
+        pub fn apply(self, x: f32) -> f32 {
+            match self {
+                WaveShaper::GreenClipper => green_clipper(x),
+                WaveShaper::RedClipper => (red_clipper(x as f64) - red_clipper(0.0)) as f32,
+                WaveShaper::HardClipper => hard_clipper(x as f64, 1.0) as f32,
+            }
+        }
+
+        /// The curve's steepest slope at the origin, which is its gain for small signals.
+        pub fn small_signal_gain(self) -> f32 {
+            match self {
+                // The negative half is `exp(2.65 x) - 1`
+                WaveShaper::GreenClipper => 2.65,
+                WaveShaper::RedClipper => 0.5 / 1.75f32.sqrt(),
+                WaveShaper::HardClipper => 1.0,
+            }
+        }
+    }
+
     /*
     // sine sigmoid piecewise
     pub fn h(x: &[f64]) -> Vec<f64> {
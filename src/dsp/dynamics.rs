@@ -0,0 +1,821 @@
+//! Dynamics processors. These work on whole blocks instead of single samples, since their
+//! detectors can be linked between channels and can listen to an external sidechain.
+
+use std::collections::VecDeque;
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+use super::crossover::iir::biquad::{Biquad, BiquadCoefficients};
+use super::effects::DelayLine;
+
+/// The longest lookahead a [`Compressor`] supports, in milliseconds.
+pub const COMPRESSOR_MAX_LOOKAHEAD_MS: f32 = 10.0;
+/// The averaging time of the RMS detector, in milliseconds.
+const RMS_WINDOW_MS: f32 = 10.0;
+/// Levels are floored to this before converting them to decibels.
+const MIN_LEVEL_DB: f32 = -120.0;
+
+/// How a detector measures the level of its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DetectorMode {
+    /// The rectified signal. Reacts to every transient.
+    #[default]
+    Peak,
+    /// The root mean square over a short window, which follows perceived loudness more closely.
+    Rms,
+}
+
+/// The coefficient for a one-pole smoother that reaches about 63% of a step after `time_ms`.
+pub fn smoothing_coefficient(sample_rate: f32, time_ms: f32) -> f32 {
+    if time_ms <= 0.0 {
+        0.0
+    } else {
+        (-1000.0 / (time_ms * sample_rate)).exp()
+    }
+}
+
+/// The zeroth order modified Bessel function of the first kind, for Kaiser windows.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..32 {
+        term *= (x / (2.0 * k as f32)).powi(2);
+        sum += term;
+    }
+
+    sum
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    (20.0 * gain.log10()).max(MIN_LEVEL_DB)
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// A feed-forward compressor with a soft knee. The gain computer follows Giannoulis, Massberg and
+/// Reiss' "Digital Dynamic Range Compressor Design", and the gain reduction is smoothed in the
+/// decibel domain. With lookahead the audio is delayed while the detector isn't, so the gain
+/// starts going down before a transient arrives.
+#[derive(Debug, Clone)]
+pub struct Compressor {
+    sample_rate: f32,
+    /// The squared signal averaged over [`RMS_WINDOW_MS`] for every channel, only used in RMS
+    /// mode.
+    mean_squares: Vec<f32>,
+    /// The current, smoothed gain reduction for every channel in decibels. This is zero or
+    /// negative.
+    gain_reductions_db: Vec<f32>,
+    lookahead_delay_lines: Vec<DelayLine>,
+    levels: Vec<f32>,
+
+    threshold_db: f32,
+    ratio: f32,
+    knee_db: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    detector_mode: DetectorMode,
+    makeup_gain_db: f32,
+    lookahead_ms: f32,
+    stereo_link: f32,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        Compressor {
+            sample_rate,
+            mean_squares: vec![0.0; num_channels],
+            gain_reductions_db: vec![0.0; num_channels],
+            lookahead_delay_lines: vec![
+                DelayLine::new(Self::max_lookahead_samples(sample_rate));
+                num_channels
+            ],
+            levels: vec![0.0; num_channels],
+
+            threshold_db: -18.0,
+            ratio: 4.0,
+            knee_db: 6.0,
+            attack_ms: 10.0,
+            release_ms: 100.0,
+            detector_mode: DetectorMode::Peak,
+            makeup_gain_db: 0.0,
+            lookahead_ms: 0.0,
+            stereo_link: 1.0,
+        }
+    }
+
+    fn max_lookahead_samples(sample_rate: f32) -> usize {
+        (sample_rate * COMPRESSOR_MAX_LOOKAHEAD_MS / 1000.0).ceil() as usize
+    }
+
+    pub fn set_threshold(&mut self, threshold_db: f32) {
+        self.threshold_db = threshold_db;
+    }
+
+    /// Set the ratio. Values below 1 are clamped to 1.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.max(1.0);
+    }
+
+    /// Set the width of the soft knee in decibels, centered on the threshold. A width of zero
+    /// gives a hard knee.
+    pub fn set_knee(&mut self, knee_db: f32) {
+        self.knee_db = knee_db.max(0.0);
+    }
+
+    pub fn set_attack(&mut self, attack_ms: f32) {
+        self.attack_ms = attack_ms.max(0.0);
+    }
+
+    pub fn set_release(&mut self, release_ms: f32) {
+        self.release_ms = release_ms.max(0.0);
+    }
+
+    pub fn set_detector_mode(&mut self, detector_mode: DetectorMode) {
+        self.detector_mode = detector_mode;
+    }
+
+    pub fn set_makeup_gain(&mut self, makeup_gain_db: f32) {
+        self.makeup_gain_db = makeup_gain_db;
+    }
+
+    /// Set the lookahead, up to [`COMPRESSOR_MAX_LOOKAHEAD_MS`]. This changes the latency, see
+    /// [`Self::latency_samples()`].
+    pub fn set_lookahead(&mut self, lookahead_ms: f32) {
+        self.lookahead_ms = lookahead_ms.clamp(0.0, COMPRESSOR_MAX_LOOKAHEAD_MS);
+    }
+
+    /// Set how much the channels share their gain reduction, from 0 (every channel is compressed
+    /// on its own) to 1 (every channel gets the gain reduction of the loudest channel).
+    pub fn set_stereo_link(&mut self, stereo_link: f32) {
+        self.stereo_link = stereo_link.clamp(0.0, 1.0);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for delay_line in &mut self.lookahead_delay_lines {
+            delay_line.resize(Self::max_lookahead_samples(sample_rate));
+        }
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.mean_squares.fill(0.0);
+        self.gain_reductions_db.fill(0.0);
+        for delay_line in &mut self.lookahead_delay_lines {
+            delay_line.reset();
+        }
+    }
+
+    /// The latency caused by the lookahead, in samples.
+    pub fn latency_samples(&self) -> u32 {
+        (self.lookahead_ms * self.sample_rate / 1000.0).round() as u32
+    }
+
+    /// The static gain curve, returning the gain change in decibels for an input level.
+    fn gain_computer(&self, level_db: f32) -> f32 {
+        let overshoot = level_db - self.threshold_db;
+        let slope = 1.0 / self.ratio - 1.0;
+        if 2.0 * overshoot <= -self.knee_db {
+            0.0
+        } else if 2.0 * overshoot.abs() < self.knee_db {
+            slope * (overshoot + self.knee_db / 2.0).powi(2) / (2.0 * self.knee_db)
+        } else {
+            slope * overshoot
+        }
+    }
+
+    /// Compress `buffer` in place. The detector listens to `sidechain` instead of the buffer
+    /// itself when it's provided, which needs to have the same number of channels and samples as
+    /// `buffer`.
+    pub fn process(&mut self, buffer: &mut [&mut [f32]], sidechain: Option<&[&[f32]]>) {
+        let attack_coefficient = smoothing_coefficient(self.sample_rate, self.attack_ms);
+        let release_coefficient = smoothing_coefficient(self.sample_rate, self.release_ms);
+        let rms_coefficient = smoothing_coefficient(self.sample_rate, RMS_WINDOW_MS);
+        let makeup_gain = db_to_gain(self.makeup_gain_db);
+        let lookahead_samples = self.latency_samples();
+
+        let num_samples = buffer.first().map_or(0, |channel| channel.len());
+        for sample_idx in 0..num_samples {
+            for (channel_idx, level) in self.levels.iter_mut().enumerate() {
+                let detector_input = match sidechain {
+                    Some(sidechain) => sidechain[channel_idx][sample_idx],
+                    None => buffer[channel_idx][sample_idx],
+                };
+
+                *level = match self.detector_mode {
+                    DetectorMode::Peak => detector_input.abs(),
+                    DetectorMode::Rms => {
+                        let mean_square = &mut self.mean_squares[channel_idx];
+                        *mean_square = detector_input * detector_input * (1.0 - rms_coefficient)
+                            + *mean_square * rms_coefficient;
+                        mean_square.sqrt()
+                    }
+                };
+            }
+
+            let linked_level = self.levels.iter().copied().fold(0.0, f32::max);
+            for (channel_idx, channel) in buffer.iter_mut().enumerate() {
+                let level = self.levels[channel_idx] * (1.0 - self.stereo_link)
+                    + linked_level * self.stereo_link;
+                let target_db = self.gain_computer(gain_to_db(level));
+
+                // More gain reduction means the attack phase
+                let gain_reduction_db = &mut self.gain_reductions_db[channel_idx];
+                let coefficient = if target_db < *gain_reduction_db {
+                    attack_coefficient
+                } else {
+                    release_coefficient
+                };
+                *gain_reduction_db =
+                    target_db * (1.0 - coefficient) + *gain_reduction_db * coefficient;
+
+                let input = channel[sample_idx];
+                let delayed = if lookahead_samples > 0 {
+                    let delay_line = &mut self.lookahead_delay_lines[channel_idx];
+                    let delayed = delay_line.read(lookahead_samples as f32);
+                    delay_line.write(input);
+                    delayed
+                } else {
+                    input
+                };
+                channel[sample_idx] = delayed * db_to_gain(*gain_reduction_db) * makeup_gain;
+            }
+        }
+    }
+}
+
+/// The oversampling factor of the true peak detector. This is twice what ITU-R BS.1770 asks for,
+/// which halves the worst case error from peaks that fall between two interpolated points.
+const TRUE_PEAK_OVERSAMPLING: usize = 8;
+/// The number of input samples on either side of the interpolated point the detector's windowed
+/// sinc spans.
+const TRUE_PEAK_HALF_TAPS: usize = 32;
+/// The number of interpolation filter taps for every oversampled phase.
+const TRUE_PEAK_TAPS_PER_PHASE: usize = 2 * TRUE_PEAK_HALF_TAPS + 1;
+/// The Kaiser window's shape parameter for the interpolation filter. Lower values keep the
+/// passband flat closer to the Nyquist frequency, at the cost of more ripple.
+const TRUE_PEAK_KAISER_BETA: f32 = 5.0;
+/// The limiter aims this far below its ceiling to absorb what's left of the detector's error, in
+/// decibels. That's the droop of the interpolation filter close to the Nyquist frequency, peaks
+/// falling between the interpolated points, and the gain changes themselves adding a little
+/// overshoot.
+const TRUE_PEAK_HEADROOM_DB: f32 = 0.2;
+/// The time the limiter looks ahead to bring the gain down smoothly, in milliseconds.
+pub const TRUE_PEAK_LIMITER_LOOKAHEAD_MS: f32 = 1.5;
+
+/// Estimates the peaks between samples by interpolating with a windowed sinc, like the true peak
+/// meters in ITU-R BS.1770. The first phase lands exactly on the input samples, so the estimate
+/// never reads lower than the sample peak. The estimate lags the input by half the filter length,
+/// see [`Self::delay_samples()`].
+#[derive(Debug, Clone)]
+pub struct TruePeakDetector {
+    /// The interpolation filter split into `TRUE_PEAK_OVERSAMPLING` phases.
+    phases: [[f32; TRUE_PEAK_TAPS_PER_PHASE]; TRUE_PEAK_OVERSAMPLING],
+    /// The last `TRUE_PEAK_TAPS_PER_PHASE` input samples for every channel, as ring buffers.
+    histories: Vec<[f32; TRUE_PEAK_TAPS_PER_PHASE]>,
+    history_pos: usize,
+}
+
+impl TruePeakDetector {
+    pub fn new(num_channels: usize) -> Self {
+        let mut phases = [[0.0; TRUE_PEAK_TAPS_PER_PHASE]; TRUE_PEAK_OVERSAMPLING];
+        for (phase_idx, phase) in phases.iter_mut().enumerate() {
+            // Phase `phase_idx` interpolates the point `phase_idx / TRUE_PEAK_OVERSAMPLING` samples
+            // after the center tap, and `x` is the distance from that point to the tap's sample
+            let fraction = phase_idx as f32 / TRUE_PEAK_OVERSAMPLING as f32;
+            for (tap_idx, tap) in phase.iter_mut().enumerate() {
+                let x = TRUE_PEAK_HALF_TAPS as f32 - tap_idx as f32 - fraction;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                // Kaiser window, reaching zero one sample beyond the outermost taps
+                let window_position = x / (TRUE_PEAK_HALF_TAPS + 1) as f32;
+                let window = bessel_i0(
+                    TRUE_PEAK_KAISER_BETA * (1.0 - window_position.powi(2)).max(0.0).sqrt(),
+                ) / bessel_i0(TRUE_PEAK_KAISER_BETA);
+                *tap = sinc * window;
+            }
+
+            // Every phase should pass DC unchanged
+            let sum: f32 = phase.iter().sum();
+            for tap in phase.iter_mut() {
+                *tap /= sum;
+            }
+        }
+
+        TruePeakDetector {
+            phases,
+            histories: vec![[0.0; TRUE_PEAK_TAPS_PER_PHASE]; num_channels],
+            history_pos: 0,
+        }
+    }
+
+    /// How many samples the peak estimates lag behind the input.
+    pub fn delay_samples() -> usize {
+        TRUE_PEAK_HALF_TAPS
+    }
+
+    pub fn reset(&mut self) {
+        for history in &mut self.histories {
+            history.fill(0.0);
+        }
+        self.history_pos = 0;
+    }
+
+    /// Add the next sample for a channel and return the absolute peak of the interpolated signal
+    /// around it. Call [`Self::advance()`] once all channels have been processed.
+    pub fn process(&mut self, input: f32, channel_idx: usize) -> f32 {
+        let history = &mut self.histories[channel_idx];
+        history[self.history_pos] = input;
+
+        let mut peak = 0.0f32;
+        for phase in &self.phases {
+            let mut sample = 0.0;
+            for (tap_idx, tap) in phase.iter().enumerate() {
+                let history_idx = (self.history_pos + TRUE_PEAK_TAPS_PER_PHASE - tap_idx)
+                    % TRUE_PEAK_TAPS_PER_PHASE;
+                sample += tap * history[history_idx];
+            }
+            peak = peak.max(sample.abs());
+        }
+
+        peak
+    }
+
+    pub fn advance(&mut self) {
+        self.history_pos = (self.history_pos + 1) % TRUE_PEAK_TAPS_PER_PHASE;
+    }
+}
+
+/// A brickwall limiter that keeps the true peak level, estimated with [`TruePeakDetector`], below
+/// a ceiling. The ceiling holds for content up to 20 kHz at 44.1 and 48 kHz; tones that start or
+/// stop abruptly within a kilohertz or so of the Nyquist frequency can still overshoot it
+/// slightly. The gain reduction is held over the lookahead window plus the detector's filter
+/// length and then smoothed with a moving average of the lookahead's length, so the gain has
+/// fully come down by the time the peak and its neighbouring samples come out of the delay. The
+/// gain is linked between all channels.
+#[derive(Debug, Clone)]
+pub struct TruePeakLimiter {
+    sample_rate: f32,
+    detector: TruePeakDetector,
+    delay_lines: Vec<DelayLine>,
+    /// The required gain after the release smoothing.
+    released_gain: f32,
+    /// A monotonic queue of `(sample index, gain)` pairs for the sliding window minimum of
+    /// `released_gain`. The capacity is reserved up front.
+    hold_queue: VecDeque<(u64, f32)>,
+    /// The last held gains for the moving average, as a ring buffer, and their sum.
+    average_buffer: Vec<f32>,
+    average_pos: usize,
+    average_sum: f64,
+    sample_idx: u64,
+
+    ceiling: f32,
+    release_ms: f32,
+}
+
+impl TruePeakLimiter {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        let mut limiter = TruePeakLimiter {
+            sample_rate,
+            detector: TruePeakDetector::new(num_channels),
+            delay_lines: Vec::new(),
+            released_gain: 1.0,
+            hold_queue: VecDeque::new(),
+            average_buffer: Vec::new(),
+            average_pos: 0,
+            average_sum: 0.0,
+            sample_idx: 0,
+
+            ceiling: db_to_gain(-1.0),
+            release_ms: 100.0,
+        };
+        limiter.set_sample_rate(sample_rate);
+        limiter.delay_lines =
+            vec![DelayLine::new(limiter.latency_samples() as usize); num_channels];
+
+        limiter
+    }
+
+    fn lookahead_samples(&self) -> usize {
+        ((TRUE_PEAK_LIMITER_LOOKAHEAD_MS * self.sample_rate / 1000.0).round() as usize).max(1)
+    }
+
+    /// The number of samples the gain reduction is held for.
+    fn hold_samples(&self) -> usize {
+        self.lookahead_samples() + 2 * TruePeakDetector::delay_samples()
+    }
+
+    /// Set the maximum true peak level in dBTP.
+    pub fn set_ceiling(&mut self, ceiling_db: f32) {
+        self.ceiling = db_to_gain(ceiling_db);
+    }
+
+    pub fn set_release(&mut self, release_ms: f32) {
+        self.release_ms = release_ms.max(0.0);
+    }
+
+    /// Resizes the buffers for the new sample rate. This allocates.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.hold_queue = VecDeque::with_capacity(self.hold_samples() + 1);
+        self.average_buffer = vec![1.0; self.lookahead_samples()];
+        let latency_samples = self.latency_samples() as usize;
+        for delay_line in &mut self.delay_lines {
+            delay_line.resize(latency_samples);
+        }
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.detector.reset();
+        for delay_line in &mut self.delay_lines {
+            delay_line.reset();
+        }
+        self.released_gain = 1.0;
+        self.hold_queue.clear();
+        self.average_buffer.fill(1.0);
+        self.average_pos = 0;
+        self.average_sum = self.average_buffer.len() as f64;
+        self.sample_idx = 0;
+    }
+
+    /// The latency in samples. This covers the lookahead, the detector's delay, and half of the
+    /// extra hold time so the samples on either side of a peak are also limited.
+    pub fn latency_samples(&self) -> u32 {
+        (2 * TruePeakDetector::delay_samples() + self.lookahead_samples() - 1) as u32
+    }
+
+    pub fn process(&mut self, buffer: &mut [&mut [f32]]) {
+        let release_coefficient = smoothing_coefficient(self.sample_rate, self.release_ms);
+        let hold_samples = self.hold_samples() as u64;
+        let latency_samples = self.latency_samples() as f32;
+        let target_peak = self.ceiling * db_to_gain(-TRUE_PEAK_HEADROOM_DB);
+
+        let num_samples = buffer.first().map_or(0, |channel| channel.len());
+        for sample_idx in 0..num_samples {
+            let mut peak = 0.0f32;
+            for (channel_idx, channel) in buffer.iter().enumerate() {
+                peak = peak.max(self.detector.process(channel[sample_idx], channel_idx));
+            }
+            self.detector.advance();
+
+            // Attacks are instant here, the moving average below takes care of smoothing them
+            let required_gain = if peak > target_peak {
+                target_peak / peak
+            } else {
+                1.0
+            };
+            self.released_gain =
+                required_gain.min(1.0 - (1.0 - self.released_gain) * release_coefficient);
+
+            while self
+                .hold_queue
+                .back()
+                .is_some_and(|&(_, gain)| gain >= self.released_gain)
+            {
+                self.hold_queue.pop_back();
+            }
+            self.hold_queue
+                .push_back((self.sample_idx, self.released_gain));
+            while self
+                .hold_queue
+                .front()
+                .is_some_and(|&(idx, _)| idx + hold_samples <= self.sample_idx)
+            {
+                self.hold_queue.pop_front();
+            }
+            let held_gain = self.hold_queue.front().map_or(1.0, |&(_, gain)| gain);
+            self.sample_idx += 1;
+
+            self.average_sum += (held_gain - self.average_buffer[self.average_pos]) as f64;
+            self.average_buffer[self.average_pos] = held_gain;
+            self.average_pos = (self.average_pos + 1) % self.average_buffer.len();
+            let gain = (self.average_sum / self.average_buffer.len() as f64) as f32;
+
+            for (channel_idx, channel) in buffer.iter_mut().enumerate() {
+                let delay_line = &mut self.delay_lines[channel_idx];
+                let delayed = delay_line.read(latency_samples);
+                delay_line.write(channel[sample_idx]);
+                channel[sample_idx] = delayed * gain;
+            }
+        }
+    }
+}
+
+/// The longest lookahead a [`NoiseGate`] supports, in milliseconds.
+pub const GATE_MAX_LOOKAHEAD_MS: f32 = 10.0;
+/// The release time of the gate's peak detector, in milliseconds. This bridges the zero crossings
+/// of low notes so the gate doesn't chatter on every cycle.
+const GATE_DETECTOR_RELEASE_MS: f32 = 10.0;
+
+/// A noise gate that can also act as a downward expander. The gate opens once the detector level
+/// rises above the open threshold and only closes again after it has stayed below the lower close
+/// threshold for the hold time. While closed, the signal is attenuated by `ratio` below the open
+/// threshold, down to at most `range`. A high ratio gives a classic gate. The detector listens to
+/// a high-passed copy of the input so low-frequency rumble doesn't keep the gate open, and the
+/// state is shared between all channels.
+#[derive(Debug, Clone)]
+pub struct NoiseGate {
+    sample_rate: f32,
+    detector_filters: Vec<Biquad<f32>>,
+    lookahead_delay_lines: Vec<DelayLine>,
+    /// The detector level shared by all channels, linear.
+    level: f32,
+    is_open: bool,
+    hold_samples_remaining: usize,
+    /// The current, smoothed linear gain.
+    gain: f32,
+
+    open_threshold_db: f32,
+    close_threshold_db: f32,
+    hold_ms: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    range_db: f32,
+    ratio: f32,
+    sidechain_high_pass_frequency: f32,
+    lookahead_ms: f32,
+}
+
+impl NoiseGate {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        let mut gate = NoiseGate {
+            sample_rate,
+            detector_filters: vec![Biquad::default(); num_channels],
+            lookahead_delay_lines: vec![
+                DelayLine::new(Self::max_lookahead_samples(sample_rate));
+                num_channels
+            ],
+            level: 0.0,
+            is_open: false,
+            hold_samples_remaining: 0,
+            gain: 0.0,
+
+            open_threshold_db: -50.0,
+            close_threshold_db: -56.0,
+            hold_ms: 50.0,
+            attack_ms: 1.0,
+            release_ms: 100.0,
+            range_db: -80.0,
+            ratio: 100.0,
+            sidechain_high_pass_frequency: 80.0,
+            lookahead_ms: 0.0,
+        };
+        gate.update_detector_filters();
+        gate.reset();
+
+        gate
+    }
+
+    fn max_lookahead_samples(sample_rate: f32) -> usize {
+        (sample_rate * GATE_MAX_LOOKAHEAD_MS / 1000.0).ceil() as usize
+    }
+
+    /// Set the open and close thresholds in decibels. The close threshold is clamped to the open
+    /// threshold, and the difference between the two is the hysteresis.
+    pub fn set_thresholds(&mut self, open_threshold_db: f32, close_threshold_db: f32) {
+        self.open_threshold_db = open_threshold_db;
+        self.close_threshold_db = close_threshold_db.min(open_threshold_db);
+    }
+
+    /// Set how long the gate stays open after the level has dropped below the close threshold.
+    pub fn set_hold(&mut self, hold_ms: f32) {
+        self.hold_ms = hold_ms.max(0.0);
+    }
+
+    /// Set how quickly the gate opens.
+    pub fn set_attack(&mut self, attack_ms: f32) {
+        self.attack_ms = attack_ms.max(0.0);
+    }
+
+    /// Set how quickly the gate closes.
+    pub fn set_release(&mut self, release_ms: f32) {
+        self.release_ms = release_ms.max(0.0);
+    }
+
+    /// Set the maximum attenuation in decibels while the gate is closed. This is zero or negative.
+    pub fn set_range(&mut self, range_db: f32) {
+        self.range_db = range_db.min(0.0);
+    }
+
+    /// Set the expansion ratio applied below the open threshold while the gate is closed. Values
+    /// below 1 are clamped to 1.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.max(1.0);
+    }
+
+    /// Set the cutoff of the high-pass filter in front of the detector. This only affects what the
+    /// gate listens to, not the audio passing through it.
+    pub fn set_sidechain_high_pass(&mut self, frequency: f32) {
+        if frequency != self.sidechain_high_pass_frequency {
+            self.sidechain_high_pass_frequency = frequency;
+            self.update_detector_filters();
+        }
+    }
+
+    /// Set the lookahead, up to [`GATE_MAX_LOOKAHEAD_MS`]. This changes the latency, see
+    /// [`Self::latency_samples()`].
+    pub fn set_lookahead(&mut self, lookahead_ms: f32) {
+        self.lookahead_ms = lookahead_ms.clamp(0.0, GATE_MAX_LOOKAHEAD_MS);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for delay_line in &mut self.lookahead_delay_lines {
+            delay_line.resize(Self::max_lookahead_samples(sample_rate));
+        }
+        self.update_detector_filters();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        for filter in &mut self.detector_filters {
+            filter.reset();
+        }
+        for delay_line in &mut self.lookahead_delay_lines {
+            delay_line.reset();
+        }
+        self.level = 0.0;
+        self.is_open = false;
+        self.hold_samples_remaining = 0;
+        self.gain = db_to_gain(self.range_db);
+    }
+
+    /// The latency caused by the lookahead, in samples.
+    pub fn latency_samples(&self) -> u32 {
+        (self.lookahead_ms * self.sample_rate / 1000.0).round() as u32
+    }
+
+    fn update_detector_filters(&mut self) {
+        let frequency = self
+            .sidechain_high_pass_frequency
+            .clamp(10.0, self.sample_rate * 0.45);
+        let coefficients = BiquadCoefficients::highpass(self.sample_rate, frequency, FRAC_1_SQRT_2);
+        for filter in &mut self.detector_filters {
+            filter.coefficients = coefficients;
+        }
+    }
+
+    /// The gain in decibels while the gate is closed.
+    fn closed_gain_db(&self, level_db: f32) -> f32 {
+        let undershoot = (level_db - self.open_threshold_db).min(0.0);
+        (undershoot * (self.ratio - 1.0)).max(self.range_db)
+    }
+
+    /// Gate `buffer` in place.
+    pub fn process(&mut self, buffer: &mut [&mut [f32]]) {
+        let attack_coefficient = smoothing_coefficient(self.sample_rate, self.attack_ms);
+        let release_coefficient = smoothing_coefficient(self.sample_rate, self.release_ms);
+        let detector_coefficient =
+            smoothing_coefficient(self.sample_rate, GATE_DETECTOR_RELEASE_MS);
+        let hold_samples = (self.hold_ms * self.sample_rate / 1000.0).round() as usize;
+        let open_threshold = db_to_gain(self.open_threshold_db);
+        let close_threshold = db_to_gain(self.close_threshold_db);
+        let lookahead_samples = self.latency_samples();
+
+        let num_samples = buffer.first().map_or(0, |channel| channel.len());
+        for sample_idx in 0..num_samples {
+            let mut peak = 0.0f32;
+            for (channel_idx, channel) in buffer.iter().enumerate() {
+                let filtered = self.detector_filters[channel_idx].process(channel[sample_idx]);
+                peak = peak.max(filtered.abs());
+            }
+            self.level = peak.max(self.level * detector_coefficient);
+
+            if self.level >= close_threshold && self.is_open {
+                self.hold_samples_remaining = hold_samples;
+            } else if self.level >= open_threshold {
+                self.is_open = true;
+                self.hold_samples_remaining = hold_samples;
+            } else if self.is_open {
+                if self.hold_samples_remaining > 0 {
+                    self.hold_samples_remaining -= 1;
+                } else {
+                    self.is_open = false;
+                }
+            }
+
+            let target = if self.is_open {
+                1.0
+            } else {
+                db_to_gain(self.closed_gain_db(gain_to_db(self.level)))
+            };
+            let coefficient = if target > self.gain {
+                attack_coefficient
+            } else {
+                release_coefficient
+            };
+            self.gain = target * (1.0 - coefficient) + self.gain * coefficient;
+
+            for (channel_idx, channel) in buffer.iter_mut().enumerate() {
+                let input = channel[sample_idx];
+                let delayed = if lookahead_samples > 0 {
+                    let delay_line = &mut self.lookahead_delay_lines[channel_idx];
+                    let delayed = delay_line.read(lookahead_samples as f32);
+                    delay_line.write(input);
+                    delayed
+                } else {
+                    input
+                };
+                channel[sample_idx] = delayed * self.gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The true peak of `signal` in dBTP, measured by interpolating 32 times with a much longer
+    /// windowed sinc than the one the detector uses.
+    fn measure_true_peak_db(signal: &[f32]) -> f32 {
+        const OVERSAMPLING: usize = 32;
+        const HALF_LENGTH: i64 = 64;
+
+        let mut peak = 0.0f64;
+        for center_idx in 0..signal.len() as i64 {
+            for step in 0..OVERSAMPLING {
+                let time = center_idx as f64 + step as f64 / OVERSAMPLING as f64;
+                let mut sample = 0.0;
+                let first_idx = (center_idx - HALF_LENGTH).max(0);
+                let last_idx = (center_idx + HALF_LENGTH).min(signal.len() as i64 - 1);
+                for idx in first_idx..=last_idx {
+                    let x = time - idx as f64;
+                    let sinc = if x == 0.0 {
+                        1.0
+                    } else {
+                        (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                    };
+                    let window =
+                        0.5 + 0.5 * (std::f64::consts::PI * x / (HALF_LENGTH + 1) as f64).cos();
+                    sample += signal[idx as usize] as f64 * sinc * window;
+                }
+                peak = peak.max(sample.abs());
+            }
+        }
+
+        20.0 * peak.log10() as f32
+    }
+
+    /// Limit `signal` to -1 dBTP at 48 kHz. Silence is appended so the limiter also gets to see
+    /// the signal's end.
+    fn limit(signal: &[f32]) -> Vec<f32> {
+        let mut limiter = TruePeakLimiter::new(48000.0, 1);
+        limiter.set_ceiling(-1.0);
+
+        let mut output = signal.to_vec();
+        output.resize(signal.len() + 500, 0.0);
+        for block in output.chunks_mut(128) {
+            limiter.process(&mut [block]);
+        }
+
+        output
+    }
+
+    #[test]
+    fn limiter_keeps_sine_onsets_below_ceiling() {
+        for frequency in [12000.0, 19000.0, 20000.0] {
+            for phase in [0.0, 0.7, 1.3] {
+                let signal: Vec<f32> = (0..1500)
+                    .map(|idx| {
+                        if idx < 300 {
+                            0.0
+                        } else {
+                            2.0 * (2.0 * PI * frequency * idx as f32 / 48000.0 + phase).sin()
+                        }
+                    })
+                    .collect();
+
+                let true_peak_db = measure_true_peak_db(&limit(&signal));
+                assert!(
+                    true_peak_db <= -1.0,
+                    "{frequency} Hz at phase {phase}: {true_peak_db} dBTP"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn limiter_keeps_impulses_below_ceiling() {
+        let impulses: Vec<f32> = (0..1500)
+            .map(|idx| if idx % 300 == 150 { 3.0 } else { 0.0 })
+            .collect();
+        let doublets: Vec<f32> = (0..1500)
+            .map(|idx| match idx % 300 {
+                150 => 3.0,
+                151 => -3.0,
+                _ => 0.0,
+            })
+            .collect();
+
+        for signal in [impulses, doublets] {
+            let true_peak_db = measure_true_peak_db(&limit(&signal));
+            assert!(true_peak_db <= -1.0, "{true_peak_db} dBTP");
+        }
+    }
+}
@@ -1,5 +1,9 @@
 use std::f32::consts::PI;
-use super::filters::AllPassFilter;
+use super::crossover::iir::biquad::{Biquad, BiquadCoefficients};
+use super::drives::wave_shapers::WaveShaper;
+use super::dynamics::smoothing_coefficient;
+use super::filters::{AllPassFilter, FirstOrderAllPass};
+use super::modulation::{EnvelopeFollower, LfoShape, RandomDrift};
 
 #[derive(Debug, Clone)]
 pub struct UniVibe {
@@ -8,6 +12,7 @@ pub struct UniVibe {
     allpass_filters: Vec<Vec<AllPassFilter>>,
     base_delay_samples: usize,
     delay_modulation_range: f32,
+    lfo_shape: LfoShape,
 }
 
 impl UniVibe {
@@ -30,9 +35,14 @@ impl UniVibe {
             allpass_filters: filters,
             base_delay_samples,
             delay_modulation_range,
+            lfo_shape: LfoShape::Sine,
         }
     }
 
+    pub fn set_lfo_shape(&mut self, lfo_shape: LfoShape) {
+        self.lfo_shape = lfo_shape;
+    }
+
     pub fn reset(&mut self) {
         for stage_filters in &mut self.allpass_filters {
             for filter in stage_filters {
@@ -55,7 +65,7 @@ impl UniVibe {
         for stage in 0..num_stages {
             let lfo_frequency = rate;
             let modulation_depth = depth;
-            let lfo_value = (2.0 * PI * self.lfo_phases[stage]).sin();
+            let lfo_value = self.lfo_shape.value(self.lfo_phases[stage]);
             self.lfo_phases[stage] += lfo_frequency / self.sample_rate;
             if self.lfo_phases[stage] > 1.0 {
                 self.lfo_phases[stage] -= 1.0;
@@ -64,24 +74,10 @@ impl UniVibe {
             let delay_offset = lfo_value * modulation_depth * self.delay_modulation_range;
             let current_delay = (self.base_delay_samples as f32 + delay_offset)
                 .clamp(1.0, self.allpass_filters[stage][0].delay_buffer.len() as f32 - 1.0);
-            let floor_delay = current_delay.floor() as usize;
-            let frac = current_delay - floor_delay as f32;
 
             let filter = &mut self.allpass_filters[stage][0];
             filter.set_feedback(feedback);
-
-            let delayed1_index = (filter.delay_index + filter.delay_buffer.len() - floor_delay)
-                % filter.delay_buffer.len();
-            let delayed2_index = (filter.delay_index + filter.delay_buffer.len() - floor_delay - 1 + filter.delay_buffer.len())
-                % filter.delay_buffer.len();
-            let delayed1 = filter.delay_buffer[delayed1_index];
-            let delayed2 = filter.delay_buffer[delayed2_index];
-            let interpolated_delay = delayed1 + frac * (delayed2 - delayed1);
-
-            let output = filter.feedback * processed + interpolated_delay;
-            filter.delay_buffer[filter.delay_index] = processed - filter.feedback * output;
-            filter.delay_index = (filter.delay_index + 1) % filter.delay_buffer.len();
-            processed = output;
+            processed = process_modulated_allpass(filter, processed, current_delay);
         }
 
         processed
@@ -99,4 +95,1793 @@ impl UniVibe {
         }
         self.lfo_phases.fill(0.0);
     }
-}
\ No newline at end of file
+}
+
+/// Read from a circular buffer with linear interpolation between the two nearest samples.
+/// `write_index` is the position the next sample will be written to, so a delay of 1 is the most
+/// recently written sample. `delay` needs to be in `[1, buffer.len() - 1]`.
+pub fn read_interpolated(buffer: &[f32], write_index: usize, delay: f32) -> f32 {
+    let len = buffer.len();
+    let floor_delay = delay.floor() as usize;
+    let frac = delay - floor_delay as f32;
+
+    let delayed1 = buffer[(write_index + len - floor_delay) % len];
+    let delayed2 = buffer[(write_index + 2 * len - floor_delay - 1) % len];
+    delayed1 + frac * (delayed2 - delayed1)
+}
+
+/// Process a sample through an [`AllPassFilter`] whose delay is read from its buffer with
+/// [`read_interpolated()`] instead of using the buffer's full length, so the delay can be
+/// modulated. `delay` is clamped to `[1, buffer.len() - 1]`.
+pub fn process_modulated_allpass(filter: &mut AllPassFilter, input: f32, delay: f32) -> f32 {
+    let delay = delay.clamp(1.0, (filter.delay_buffer.len() - 1) as f32);
+    let delayed = read_interpolated(&filter.delay_buffer, filter.delay_index, delay);
+
+    let output = filter.feedback * input + delayed;
+    filter.delay_buffer[filter.delay_index] = input - filter.feedback * output;
+    filter.delay_index = (filter.delay_index + 1) % filter.delay_buffer.len();
+
+    output
+}
+
+/// A circular delay line with fractional, linearly interpolated reads. Reads should happen before
+/// the current sample is written.
+#[derive(Debug, Clone)]
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    write_index: usize,
+}
+
+impl DelayLine {
+    pub fn new(max_delay_samples: usize) -> Self {
+        DelayLine {
+            buffer: vec![0.0; max_delay_samples + 2],
+            write_index: 0,
+        }
+    }
+
+    /// Change the maximum delay. This clears the delay line.
+    pub fn resize(&mut self, max_delay_samples: usize) {
+        self.buffer.resize(max_delay_samples + 2, 0.0);
+        self.reset();
+    }
+
+    /// The longest delay that can be read from this delay line, in samples.
+    pub fn max_delay_samples(&self) -> usize {
+        self.buffer.len() - 2
+    }
+
+    /// Read the signal from `delay` samples ago. The delay is clamped to at least one sample,
+    /// which is the last sample that was written.
+    pub fn read(&self, delay: f32) -> f32 {
+        let delay = delay.clamp(1.0, (self.buffer.len() - 1) as f32);
+        read_interpolated(&self.buffer, self.write_index, delay)
+    }
+
+    pub fn write(&mut self, sample: f32) {
+        self.buffer[self.write_index] = sample;
+        self.write_index = (self.write_index + 1) % self.buffer.len();
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_index = 0;
+    }
+}
+
+/// The maximum number of allpass stages in a [`Phaser`].
+pub const PHASER_MAX_STAGES: usize = 24;
+
+/// A classic phaser built from a chain of first-order allpasses whose break frequencies are swept
+/// together by an LFO. When mixed with the dry signal, every 360 degrees of phase shift causes a
+/// notch, so there are `num_stages / 2` notches moving through the spectrum. The mixing is left to
+/// the caller.
+#[derive(Debug, Clone)]
+pub struct Phaser {
+    sample_rate: f32,
+    /// One LFO phase per channel. These advance in lockstep, and the stereo offset is added when
+    /// they're evaluated.
+    lfo_phases: Vec<f32>,
+    allpass_filters: Vec<Vec<FirstOrderAllPass>>,
+    /// The last output sample for every channel, fed back into the chain's input.
+    feedback_samples: Vec<f32>,
+
+    rate: f32,
+    depth: f32,
+    min_frequency: f32,
+    max_frequency: f32,
+    feedback: f32,
+    num_stages: usize,
+    /// The LFO phase offset between adjacent channels, as a fraction of a cycle.
+    stereo_offset: f32,
+}
+
+impl Phaser {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        Phaser {
+            sample_rate,
+            lfo_phases: vec![0.0; num_channels],
+            allpass_filters: vec![
+                vec![FirstOrderAllPass::default(); PHASER_MAX_STAGES];
+                num_channels
+            ],
+            feedback_samples: vec![0.0; num_channels],
+
+            rate: 0.5,
+            depth: 1.0,
+            min_frequency: 200.0,
+            max_frequency: 4000.0,
+            feedback: 0.0,
+            num_stages: 4,
+            stereo_offset: 0.0,
+        }
+    }
+
+    /// Set the LFO rate in Hz.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    /// Set how much of the sweep range is used, from 0 (the notches stay at the minimum
+    /// frequency) to 1 (they sweep all the way up to the maximum frequency).
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    /// Set the range the allpasses' break frequencies are swept over. The sweep is exponential,
+    /// so the notches spend as much time in every octave.
+    pub fn set_sweep_range(&mut self, min_frequency: f32, max_frequency: f32) {
+        self.min_frequency = min_frequency.max(1.0);
+        self.max_frequency = max_frequency.max(self.min_frequency);
+    }
+
+    /// Set the amount of feedback from the last stage back to the first. Positive values emphasize
+    /// the peaks between the notches, and negative values shift those peaks down by half a notch
+    /// spacing.
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(-0.95, 0.95);
+    }
+
+    /// Set the number of allpass stages, between 2 and [`PHASER_MAX_STAGES`].
+    pub fn set_num_stages(&mut self, num_stages: usize) {
+        let num_stages = num_stages.clamp(2, PHASER_MAX_STAGES);
+        // Stages that were bypassed still contain old state
+        if num_stages > self.num_stages {
+            for channel_filters in &mut self.allpass_filters {
+                for filter in &mut channel_filters[self.num_stages..num_stages] {
+                    filter.reset();
+                }
+            }
+        }
+
+        self.num_stages = num_stages;
+    }
+
+    /// Set the LFO phase offset between the left and right channels in degrees.
+    pub fn set_stereo_offset(&mut self, degrees: f32) {
+        self.stereo_offset = degrees / 360.0;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        for channel_filters in &mut self.allpass_filters {
+            for filter in channel_filters {
+                filter.reset();
+            }
+        }
+        self.lfo_phases.fill(0.0);
+        self.feedback_samples.fill(0.0);
+    }
+
+    pub fn process(&mut self, input: f32, channel_idx: usize) -> f32 {
+        // This starts at the bottom of the sweep
+        let lfo_phase = self.lfo_phases[channel_idx] + channel_idx as f32 * self.stereo_offset;
+        let lfo_value = 0.5 - 0.5 * (2.0 * PI * lfo_phase).cos();
+        self.lfo_phases[channel_idx] += self.rate / self.sample_rate;
+        if self.lfo_phases[channel_idx] >= 1.0 {
+            self.lfo_phases[channel_idx] -= 1.0;
+        }
+
+        let frequency = self.min_frequency
+            * (self.max_frequency / self.min_frequency).powf(lfo_value * self.depth);
+        let coefficient = FirstOrderAllPass::coefficient(self.sample_rate, frequency);
+
+        let mut processed = input + self.feedback * self.feedback_samples[channel_idx];
+        for filter in &mut self.allpass_filters[channel_idx][..self.num_stages] {
+            filter.set_coefficient(coefficient);
+            processed = filter.process(processed);
+        }
+        self.feedback_samples[channel_idx] = processed;
+
+        processed
+    }
+}
+
+/// The longest manual delay a [`Flanger`] supports, in milliseconds.
+pub const FLANGER_MAX_MANUAL_DELAY_MS: f32 = 10.0;
+/// How far a [`Flanger`] sweeps beyond its manual delay at full depth, in milliseconds.
+const FLANGER_SWEEP_MS: f32 = 8.0;
+
+/// A flanger built from a modulated fractional delay, interpolated the same way as in
+/// [`UniVibe`]. In through-zero mode the dry signal is delayed by the center of the sweep, so the
+/// wet signal moves from behind to ahead of it and back again. The wet signal is inverted in that
+/// mode, which gives the tape-style total cancellation at the crossing points. Because of that
+/// delayed dry path, this does its own dry/wet mixing.
+#[derive(Debug, Clone)]
+pub struct Flanger {
+    sample_rate: f32,
+    lfo_phases: Vec<f32>,
+    delay_lines: Vec<DelayLine>,
+    /// Only used in through-zero mode.
+    dry_delay_lines: Vec<DelayLine>,
+
+    rate: f32,
+    depth: f32,
+    manual_delay_ms: f32,
+    regeneration: f32,
+    through_zero: bool,
+    mix: f32,
+}
+
+impl Flanger {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        let max_delay_samples = Self::max_delay_samples(sample_rate);
+
+        Flanger {
+            sample_rate,
+            lfo_phases: vec![0.0; num_channels],
+            delay_lines: vec![DelayLine::new(max_delay_samples); num_channels],
+            dry_delay_lines: vec![DelayLine::new(max_delay_samples); num_channels],
+
+            rate: 0.2,
+            depth: 0.5,
+            manual_delay_ms: 1.0,
+            regeneration: 0.0,
+            through_zero: false,
+            mix: 0.5,
+        }
+    }
+
+    fn max_delay_samples(sample_rate: f32) -> usize {
+        (sample_rate * (FLANGER_MAX_MANUAL_DELAY_MS + FLANGER_SWEEP_MS) / 1000.0).ceil() as usize
+    }
+
+    /// Set the LFO rate in Hz.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    /// Set the sweep depth, from 0 (a static comb filter) to 1.
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    /// Set the delay at the start of the sweep in milliseconds, up to
+    /// [`FLANGER_MAX_MANUAL_DELAY_MS`].
+    pub fn set_manual_delay(&mut self, delay_ms: f32) {
+        self.manual_delay_ms = delay_ms.clamp(0.0, FLANGER_MAX_MANUAL_DELAY_MS);
+    }
+
+    /// Set the amount of the wet signal that's fed back into the delay line. Negative values
+    /// invert the feedback, which turns the resonant peaks into notches at the odd harmonics.
+    pub fn set_regeneration(&mut self, regeneration: f32) {
+        self.regeneration = regeneration.clamp(-0.95, 0.95);
+    }
+
+    pub fn set_through_zero(&mut self, through_zero: bool) {
+        if through_zero != self.through_zero {
+            for delay_line in &mut self.dry_delay_lines {
+                delay_line.reset();
+            }
+        }
+
+        self.through_zero = through_zero;
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        let max_delay_samples = Self::max_delay_samples(sample_rate);
+        for delay_line in self.delay_lines.iter_mut().chain(&mut self.dry_delay_lines) {
+            delay_line.resize(max_delay_samples);
+        }
+        self.lfo_phases.fill(0.0);
+    }
+
+    pub fn reset(&mut self) {
+        for delay_line in self.delay_lines.iter_mut().chain(&mut self.dry_delay_lines) {
+            delay_line.reset();
+        }
+        self.lfo_phases.fill(0.0);
+    }
+
+    pub fn process(&mut self, input: f32, channel_idx: usize) -> f32 {
+        let lfo_value = 0.5 - 0.5 * (2.0 * PI * self.lfo_phases[channel_idx]).cos();
+        self.lfo_phases[channel_idx] += self.rate / self.sample_rate;
+        if self.lfo_phases[channel_idx] >= 1.0 {
+            self.lfo_phases[channel_idx] -= 1.0;
+        }
+
+        let samples_per_ms = self.sample_rate / 1000.0;
+        let sweep_ms = self.depth * FLANGER_SWEEP_MS;
+        let wet_delay = (self.manual_delay_ms + sweep_ms * lfo_value) * samples_per_ms;
+
+        let delay_line = &mut self.delay_lines[channel_idx];
+        let wet = delay_line.read(wet_delay);
+        delay_line.write(input + self.regeneration * wet);
+
+        if self.through_zero {
+            let dry_delay = (self.manual_delay_ms + sweep_ms * 0.5) * samples_per_ms;
+            let dry_delay_line = &mut self.dry_delay_lines[channel_idx];
+            let dry = dry_delay_line.read(dry_delay);
+            dry_delay_line.write(input);
+
+            dry * (1.0 - self.mix) - wet * self.mix
+        } else {
+            input * (1.0 - self.mix) + wet * self.mix
+        }
+    }
+}
+
+/// The maximum number of voices in a [`Chorus`].
+pub const CHORUS_MAX_VOICES: usize = 8;
+/// The delay every chorus voice is modulated around, in milliseconds.
+const CHORUS_BASE_DELAY_MS: f32 = 12.0;
+/// How far the LFO moves the delay in either direction at full depth, in milliseconds.
+const CHORUS_MODULATION_MS: f32 = 4.0;
+/// How far the random drift moves the delay in either direction at full drift, in milliseconds.
+const CHORUS_DRIFT_MS: f32 = 1.5;
+/// How often the random drift picks a new target, in Hz.
+const CHORUS_DRIFT_RATE: f32 = 4.0;
+/// The cutoff of the anti-aliasing and anti-imaging filters around the bucket brigade in BBD mode.
+const BBD_FILTER_FREQUENCY: f32 = 9000.0;
+
+/// The modulation state of a single chorus voice.
+#[derive(Debug, Clone)]
+struct ChorusVoice {
+    lfo_phase: f32,
+    drift: RandomDrift,
+}
+
+impl ChorusVoice {
+    fn new(voice_idx: usize) -> Self {
+        ChorusVoice {
+            lfo_phase: 0.0,
+            // Every voice gets its own sequence, but the same voice on different channels drifts
+            // in lockstep
+            drift: RandomDrift::new(voice_idx as u32),
+        }
+    }
+}
+
+/// The companding and filtering around a bucket brigade delay line, as found in most analog
+/// chorus pedals. The compressor and expander each have their own detector, so transients briefly
+/// over- and undershoot the way the real thing does.
+#[derive(Debug, Clone)]
+struct BbdStage {
+    anti_aliasing_filter: Biquad<f32>,
+    anti_imaging_filter: Biquad<f32>,
+    compressor_envelope: f32,
+    expander_envelope: f32,
+}
+
+impl BbdStage {
+    fn new(sample_rate: f32) -> Self {
+        let mut stage = BbdStage {
+            anti_aliasing_filter: Biquad::default(),
+            anti_imaging_filter: Biquad::default(),
+            compressor_envelope: 0.0,
+            expander_envelope: 0.0,
+        };
+        stage.set_sample_rate(sample_rate);
+
+        stage
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        let coefficients = BiquadCoefficients::lowpass(
+            sample_rate,
+            BBD_FILTER_FREQUENCY.min(sample_rate * 0.45),
+            0.707,
+        );
+        self.anti_aliasing_filter.coefficients = coefficients;
+        self.anti_imaging_filter.coefficients = coefficients;
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.anti_aliasing_filter.reset();
+        self.anti_imaging_filter.reset();
+        self.compressor_envelope = 0.0;
+        self.expander_envelope = 0.0;
+    }
+
+    /// Band limit, compress and saturate the signal going into the delay line.
+    fn process_input(&mut self, input: f32, envelope_coefficient: f32) -> f32 {
+        let filtered = self.anti_aliasing_filter.process(input);
+        self.compressor_envelope +=
+            (filtered.abs() - self.compressor_envelope) * envelope_coefficient;
+
+        // A 2:1 compressor, followed by the bucket brigade's soft clipping
+        let compressed = filtered / self.compressor_envelope.max(1e-4).sqrt();
+        compressed.tanh()
+    }
+
+    /// Expand and filter the signal coming out of the delay line.
+    fn process_output(&mut self, output: f32, envelope_coefficient: f32) -> f32 {
+        self.expander_envelope += (output.abs() - self.expander_envelope) * envelope_coefficient;
+        let expanded = output * self.expander_envelope;
+
+        self.anti_imaging_filter.process(expanded)
+    }
+}
+
+/// A multi-voice chorus. Every voice reads from the same delay line with its own LFO phase and
+/// random drift, and the voices are panned across the stereo field. In BBD mode the delay line is
+/// surrounded by the companding and filtering of an analog bucket brigade chorus. Like
+/// [`Phaser`], this only outputs the wet signal.
+#[derive(Debug, Clone)]
+pub struct Chorus {
+    sample_rate: f32,
+    delay_lines: Vec<DelayLine>,
+    voices: Vec<Vec<ChorusVoice>>,
+    bbd_stages: Vec<BbdStage>,
+
+    rate: f32,
+    depth: f32,
+    num_voices: usize,
+    spread: f32,
+    drift_amount: f32,
+    bbd: bool,
+}
+
+impl Chorus {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        let max_delay_samples = Self::max_delay_samples(sample_rate);
+
+        Chorus {
+            sample_rate,
+            delay_lines: vec![DelayLine::new(max_delay_samples); num_channels],
+            voices: vec![(0..CHORUS_MAX_VOICES).map(ChorusVoice::new).collect(); num_channels],
+            bbd_stages: vec![BbdStage::new(sample_rate); num_channels],
+
+            rate: 0.8,
+            depth: 0.5,
+            num_voices: 2,
+            spread: 1.0,
+            drift_amount: 0.0,
+            bbd: false,
+        }
+    }
+
+    fn max_delay_samples(sample_rate: f32) -> usize {
+        let max_delay_ms = CHORUS_BASE_DELAY_MS + CHORUS_MODULATION_MS + CHORUS_DRIFT_MS;
+        (sample_rate * max_delay_ms / 1000.0).ceil() as usize + 1
+    }
+
+    /// Set the LFO rate in Hz.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    /// Set the number of voices, between 1 and [`CHORUS_MAX_VOICES`]. The voices' LFOs are spread
+    /// evenly over a cycle.
+    pub fn set_num_voices(&mut self, num_voices: usize) {
+        self.num_voices = num_voices.clamp(1, CHORUS_MAX_VOICES);
+    }
+
+    /// Set how far the voices are panned apart, from 0 (all in the center) to 1 (the outer voices
+    /// are panned hard left and right).
+    pub fn set_spread(&mut self, spread: f32) {
+        self.spread = spread.clamp(0.0, 1.0);
+    }
+
+    /// Set the amount of slow random variation added to every voice's delay.
+    pub fn set_drift(&mut self, drift: f32) {
+        self.drift_amount = drift.clamp(0.0, 1.0);
+    }
+
+    pub fn set_bbd(&mut self, bbd: bool) {
+        if bbd != self.bbd {
+            for stage in &mut self.bbd_stages {
+                stage.reset();
+            }
+        }
+
+        self.bbd = bbd;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        let max_delay_samples = Self::max_delay_samples(sample_rate);
+        for delay_line in &mut self.delay_lines {
+            delay_line.resize(max_delay_samples);
+        }
+        for stage in &mut self.bbd_stages {
+            stage.set_sample_rate(sample_rate);
+        }
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        for delay_line in &mut self.delay_lines {
+            delay_line.reset();
+        }
+        for channel_voices in &mut self.voices {
+            for (voice_idx, voice) in channel_voices.iter_mut().enumerate() {
+                *voice = ChorusVoice::new(voice_idx);
+            }
+        }
+        for stage in &mut self.bbd_stages {
+            stage.reset();
+        }
+    }
+
+    pub fn process(&mut self, input: f32, channel_idx: usize) -> f32 {
+        // Roughly 1 ms attack and 20 ms release would be more accurate, but a single time constant
+        // keeps the compressor and expander symmetrical
+        let envelope_coefficient = 1.0 - (-1.0 / (0.01 * self.sample_rate)).exp();
+        let samples_per_ms = self.sample_rate / 1000.0;
+        let num_voices = self.num_voices;
+
+        let delay_line = &mut self.delay_lines[channel_idx];
+        let bbd_stage = &mut self.bbd_stages[channel_idx];
+        let mut wet = 0.0;
+        for (voice_idx, voice) in self.voices[channel_idx][..num_voices]
+            .iter_mut()
+            .enumerate()
+        {
+            let lfo_phase = voice.lfo_phase + voice_idx as f32 / num_voices as f32;
+            let lfo_value = (2.0 * PI * lfo_phase).sin();
+            voice.lfo_phase += self.rate / self.sample_rate;
+            if voice.lfo_phase >= 1.0 {
+                voice.lfo_phase -= 1.0;
+            }
+
+            let drift = voice.drift.next(CHORUS_DRIFT_RATE, self.sample_rate);
+
+            let delay_ms = CHORUS_BASE_DELAY_MS
+                + lfo_value * self.depth * CHORUS_MODULATION_MS
+                + drift * self.drift_amount * CHORUS_DRIFT_MS;
+
+            // Equal power panning, with the outer voices at `spread`
+            let pan = if num_voices > 1 {
+                self.spread * (2.0 * voice_idx as f32 / (num_voices - 1) as f32 - 1.0)
+            } else {
+                0.0
+            };
+            let pan_angle = (pan + 1.0) * PI / 4.0;
+            let gain = match channel_idx {
+                0 => pan_angle.cos() * 2.0f32.sqrt(),
+                1 => pan_angle.sin() * 2.0f32.sqrt(),
+                _ => 1.0,
+            };
+
+            wet += delay_line.read(delay_ms * samples_per_ms) * gain;
+        }
+        wet /= (num_voices as f32).sqrt();
+
+        if self.bbd {
+            delay_line.write(bbd_stage.process_input(input, envelope_coefficient));
+            bbd_stage.process_output(wet, envelope_coefficient)
+        } else {
+            delay_line.write(input);
+            wet
+        }
+    }
+}
+
+/// The default crossover frequency for [`TremoloMode::Harmonic`], in Hz.
+pub const TREMOLO_DEFAULT_CROSSOVER_FREQUENCY: f32 = 800.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TremoloMode {
+    /// Both channels get the same amplitude modulation.
+    #[default]
+    Mono,
+    /// The signal is moved between the left and right channels with equal power panning.
+    AutoPan,
+    /// The signal is split into a low and a high band that are modulated in opposite phase, like
+    /// in the brownface era Fender amps.
+    Harmonic,
+}
+
+/// A tremolo with mono, auto-pan and harmonic modes. The harmonic mode splits the signal with a
+/// fourth order Linkwitz-Riley crossover, so the bands sum back to a flat response when the depth
+/// is zero.
+#[derive(Debug, Clone)]
+pub struct Tremolo {
+    sample_rate: f32,
+    lfo_phases: Vec<f32>,
+    /// Two cascaded Butterworth sections per channel for each band.
+    low_pass_filters: Vec<[Biquad<f32>; 2]>,
+    high_pass_filters: Vec<[Biquad<f32>; 2]>,
+
+    rate: f32,
+    depth: f32,
+    lfo_shape: LfoShape,
+    mode: TremoloMode,
+    crossover_frequency: f32,
+}
+
+impl Tremolo {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        let mut tremolo = Tremolo {
+            sample_rate,
+            lfo_phases: vec![0.0; num_channels],
+            low_pass_filters: vec![[Biquad::default(); 2]; num_channels],
+            high_pass_filters: vec![[Biquad::default(); 2]; num_channels],
+
+            rate: 5.0,
+            depth: 0.5,
+            lfo_shape: LfoShape::Sine,
+            mode: TremoloMode::Mono,
+            crossover_frequency: TREMOLO_DEFAULT_CROSSOVER_FREQUENCY,
+        };
+        tremolo.update_crossover();
+
+        tremolo
+    }
+
+    /// Set the LFO rate in Hz.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    pub fn set_lfo_shape(&mut self, lfo_shape: LfoShape) {
+        self.lfo_shape = lfo_shape;
+    }
+
+    pub fn set_mode(&mut self, mode: TremoloMode) {
+        if mode != self.mode && mode == TremoloMode::Harmonic {
+            self.reset_crossover();
+        }
+
+        self.mode = mode;
+    }
+
+    /// Set the frequency the harmonic mode splits the signal at, in Hz.
+    pub fn set_crossover_frequency(&mut self, frequency: f32) {
+        if frequency != self.crossover_frequency {
+            self.crossover_frequency = frequency;
+            self.update_crossover();
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.update_crossover();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.lfo_phases.fill(0.0);
+        self.reset_crossover();
+    }
+
+    fn update_crossover(&mut self) {
+        let frequency = self.crossover_frequency.min(self.sample_rate * 0.45);
+        let low_pass = BiquadCoefficients::lowpass(self.sample_rate, frequency, 0.707);
+        let high_pass = BiquadCoefficients::highpass(self.sample_rate, frequency, 0.707);
+        for filters in &mut self.low_pass_filters {
+            for filter in filters {
+                filter.coefficients = low_pass;
+            }
+        }
+        for filters in &mut self.high_pass_filters {
+            for filter in filters {
+                filter.coefficients = high_pass;
+            }
+        }
+    }
+
+    fn reset_crossover(&mut self) {
+        for filter in self
+            .low_pass_filters
+            .iter_mut()
+            .chain(self.high_pass_filters.iter_mut())
+            .flatten()
+        {
+            filter.reset();
+        }
+    }
+
+    pub fn process(&mut self, input: f32, channel_idx: usize) -> f32 {
+        let lfo_value = self.lfo_shape.value(self.lfo_phases[channel_idx]);
+        self.lfo_phases[channel_idx] += self.rate / self.sample_rate;
+        if self.lfo_phases[channel_idx] >= 1.0 {
+            self.lfo_phases[channel_idx] -= 1.0;
+        }
+
+        // The gain dips to `1 - depth` at the bottom of the LFO's cycle
+        let gain = |lfo_value: f32| 1.0 - self.depth * (0.5 - 0.5 * lfo_value);
+        match self.mode {
+            TremoloMode::Mono => input * gain(lfo_value),
+            TremoloMode::AutoPan => {
+                let pan_angle = (self.depth * lfo_value + 1.0) * PI / 4.0;
+                match channel_idx {
+                    0 => input * pan_angle.cos() * 2.0f32.sqrt(),
+                    1 => input * pan_angle.sin() * 2.0f32.sqrt(),
+                    _ => input * gain(lfo_value),
+                }
+            }
+            TremoloMode::Harmonic => {
+                let low = self.low_pass_filters[channel_idx]
+                    .iter_mut()
+                    .fold(input, |sample, filter| filter.process(sample));
+                let high = self.high_pass_filters[channel_idx]
+                    .iter_mut()
+                    .fold(input, |sample, filter| filter.process(sample));
+
+                low * gain(lfo_value) + high * gain(-lfo_value)
+            }
+        }
+    }
+}
+
+/// The sample rate the delay lengths in Dattorro's paper are specified at.
+const DATTORRO_SAMPLE_RATE: f32 = 29761.0;
+/// The lengths of the four input diffusers at [`DATTORRO_SAMPLE_RATE`].
+const PLATE_INPUT_DIFFUSER_LENGTHS: [f32; 4] = [142.0, 107.0, 379.0, 277.0];
+/// The lengths of the left and right tank's modulated allpass, first delay, second allpass and
+/// second delay at [`DATTORRO_SAMPLE_RATE`].
+const PLATE_TANK_LENGTHS: [[f32; 4]; 2] = [
+    [672.0, 4453.0, 1800.0, 3720.0],
+    [908.0, 4217.0, 2656.0, 3163.0],
+];
+/// The peak excursion of the tank's modulated allpasses at [`DATTORRO_SAMPLE_RATE`].
+const PLATE_EXCURSION: f32 = 16.0;
+const PLATE_LFO_RATE: f32 = 1.0;
+/// The output taps for the left and right outputs at [`DATTORRO_SAMPLE_RATE`], as (gain, tank
+/// half, element within the tank half, delay).
+const PLATE_OUTPUT_TAPS: [[(f32, usize, PlateTap, f32); 7]; 2] = [
+    [
+        (1.0, 1, PlateTap::FirstDelay, 266.0),
+        (1.0, 1, PlateTap::FirstDelay, 2974.0),
+        (-1.0, 1, PlateTap::Allpass, 1913.0),
+        (1.0, 1, PlateTap::SecondDelay, 1996.0),
+        (-1.0, 0, PlateTap::FirstDelay, 1990.0),
+        (-1.0, 0, PlateTap::Allpass, 187.0),
+        (-1.0, 0, PlateTap::SecondDelay, 1066.0),
+    ],
+    [
+        (1.0, 0, PlateTap::FirstDelay, 353.0),
+        (1.0, 0, PlateTap::FirstDelay, 3627.0),
+        (-1.0, 0, PlateTap::Allpass, 1228.0),
+        (1.0, 0, PlateTap::SecondDelay, 2673.0),
+        (-1.0, 1, PlateTap::FirstDelay, 2111.0),
+        (-1.0, 1, PlateTap::Allpass, 335.0),
+        (-1.0, 1, PlateTap::SecondDelay, 121.0),
+    ],
+];
+const PLATE_OUTPUT_GAIN: f32 = 0.6;
+
+pub const PLATE_MAX_PRE_DELAY_MS: f32 = 250.0;
+pub const PLATE_MIN_SIZE: f32 = 0.25;
+pub const PLATE_MAX_SIZE: f32 = 2.0;
+/// Decays above this would let the tank ring forever.
+pub const PLATE_MAX_DECAY: f32 = 0.99;
+/// The level, relative to the input, below which the tail is considered to have ended.
+const PLATE_TAIL_THRESHOLD: f32 = 0.001;
+
+/// The elements of a tank half the output taps read from.
+#[derive(Debug, Clone, Copy)]
+enum PlateTap {
+    FirstDelay,
+    Allpass,
+    SecondDelay,
+}
+
+/// One half of the plate's figure-eight tank.
+#[derive(Debug, Clone)]
+struct PlateTankHalf {
+    modulated_allpass: AllPassFilter,
+    first_delay: DelayLine,
+    damping_state: f32,
+    allpass: AllPassFilter,
+    second_delay: DelayLine,
+}
+
+/// A plate reverb using the topology from Jon Dattorro's "Effect Design, Part 1". The mono sum of
+/// the input goes through a pre-delay and four [`AllPassFilter`] diffusers into a figure-eight
+/// tank, and the stereo output is taken from taps spread throughout the tank. All lengths are
+/// scaled from the paper's sample rate and by the size, with buffers allocated for the largest
+/// size so it can be changed while running. This only outputs the wet signal.
+#[derive(Debug, Clone)]
+pub struct PlateReverb {
+    sample_rate: f32,
+    pre_delay: DelayLine,
+    input_diffusers: [AllPassFilter; 4],
+    tank: [PlateTankHalf; 2],
+    /// The outputs of both tank halves from the previous sample, which feed the other half.
+    tank_outputs: [f32; 2],
+    lfo_phase: f32,
+
+    pre_delay_ms: f32,
+    /// The target size, and the size the delay lengths are currently scaled by. The latter glides
+    /// towards the former to avoid clicks.
+    size: f32,
+    current_size: f32,
+    decay: f32,
+    damping: f32,
+}
+
+impl PlateReverb {
+    pub fn new(sample_rate: f32) -> Self {
+        let max_scale = sample_rate / DATTORRO_SAMPLE_RATE * PLATE_MAX_SIZE;
+        let allpass = |length: f32, feedback: f32| {
+            let mut filter = AllPassFilter::new((length * max_scale).ceil() as usize + 2);
+            filter.set_feedback(feedback);
+            filter
+        };
+        let delay = |length: f32| DelayLine::new((length * max_scale).ceil() as usize + 1);
+        let excursion = PLATE_EXCURSION * sample_rate / DATTORRO_SAMPLE_RATE;
+        let tank_half = |lengths: [f32; 4]| {
+            let mut modulated_allpass =
+                AllPassFilter::new((lengths[0] * max_scale + excursion).ceil() as usize + 2);
+            modulated_allpass.set_feedback(0.7);
+
+            PlateTankHalf {
+                modulated_allpass,
+                first_delay: delay(lengths[1]),
+                damping_state: 0.0,
+                allpass: allpass(lengths[2], -0.5),
+                second_delay: delay(lengths[3]),
+            }
+        };
+
+        PlateReverb {
+            sample_rate,
+            pre_delay: DelayLine::new(
+                (sample_rate * PLATE_MAX_PRE_DELAY_MS / 1000.0).ceil() as usize
+            ),
+            input_diffusers: [
+                allpass(PLATE_INPUT_DIFFUSER_LENGTHS[0], -0.75),
+                allpass(PLATE_INPUT_DIFFUSER_LENGTHS[1], -0.75),
+                allpass(PLATE_INPUT_DIFFUSER_LENGTHS[2], -0.625),
+                allpass(PLATE_INPUT_DIFFUSER_LENGTHS[3], -0.625),
+            ],
+            tank: [
+                tank_half(PLATE_TANK_LENGTHS[0]),
+                tank_half(PLATE_TANK_LENGTHS[1]),
+            ],
+            tank_outputs: [0.0; 2],
+            lfo_phase: 0.0,
+
+            pre_delay_ms: 0.0,
+            size: 1.0,
+            current_size: 1.0,
+            decay: 0.5,
+            damping: 0.3,
+        }
+    }
+
+    pub fn set_pre_delay(&mut self, pre_delay_ms: f32) {
+        self.pre_delay_ms = pre_delay_ms.clamp(0.0, PLATE_MAX_PRE_DELAY_MS);
+    }
+
+    /// Scale all delays in the reverb, between [`PLATE_MIN_SIZE`] and [`PLATE_MAX_SIZE`]. A size
+    /// of 1 matches the paper.
+    pub fn set_size(&mut self, size: f32) {
+        self.size = size.clamp(PLATE_MIN_SIZE, PLATE_MAX_SIZE);
+    }
+
+    /// Set the gain applied after each of the tank's delays, up to [`PLATE_MAX_DECAY`].
+    pub fn set_decay(&mut self, decay: f32) {
+        self.decay = decay.clamp(0.0, PLATE_MAX_DECAY);
+
+        // The second diffuser in each tank half follows the decay, as suggested in the paper
+        let decay_diffusion = (self.decay + 0.15).clamp(0.25, 0.5);
+        for half in &mut self.tank {
+            half.allpass.set_feedback(-decay_diffusion);
+        }
+    }
+
+    /// Set the high frequency damping in the tank, from 0 (none) to 1 (a 200 Hz low-pass).
+    pub fn set_damping(&mut self, damping: f32) {
+        self.damping = damping.clamp(0.0, 1.0);
+    }
+
+    /// Rebuilds the reverb for a new sample rate. This allocates.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let pre_delay_ms = self.pre_delay_ms;
+        let size = self.size;
+        let decay = self.decay;
+        let damping = self.damping;
+
+        *self = Self::new(sample_rate);
+        self.set_pre_delay(pre_delay_ms);
+        self.set_size(size);
+        self.current_size = self.size;
+        self.set_decay(decay);
+        self.set_damping(damping);
+    }
+
+    pub fn reset(&mut self) {
+        self.pre_delay.reset();
+        for diffuser in &mut self.input_diffusers {
+            diffuser.reset();
+        }
+        for half in &mut self.tank {
+            half.modulated_allpass.reset();
+            half.first_delay.reset();
+            half.damping_state = 0.0;
+            half.allpass.reset();
+            half.second_delay.reset();
+        }
+        self.tank_outputs = [0.0; 2];
+        self.lfo_phase = 0.0;
+        self.current_size = self.size;
+    }
+
+    /// The time it takes for the tail to decay by 60 dB after the input stops, in samples. This
+    /// ignores the damping, so it's an upper bound.
+    pub fn tail_length_samples(&self) -> u32 {
+        let scale = self.sample_rate / DATTORRO_SAMPLE_RATE * self.size;
+        let input_length: f32 = PLATE_INPUT_DIFFUSER_LENGTHS.iter().sum::<f32>() * scale;
+        let half_loop_length = PLATE_TANK_LENGTHS
+            .iter()
+            .map(|lengths| lengths.iter().sum::<f32>())
+            .fold(0.0f32, f32::max)
+            * scale;
+        // The decay is applied twice in every tank half
+        let num_half_loops = if self.decay > 0.0 {
+            PLATE_TAIL_THRESHOLD.ln() / (2.0 * self.decay.ln())
+        } else {
+            1.0
+        };
+
+        let pre_delay = self.pre_delay_ms * self.sample_rate / 1000.0;
+        (pre_delay + input_length + (num_half_loops + 1.0) * half_loop_length).ceil() as u32
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        // Roughly 50 ms to reach a new size
+        let size_coefficient = 1.0 - (-1.0 / (0.05 * self.sample_rate)).exp();
+        self.current_size += (self.size - self.current_size) * size_coefficient;
+        let scale = self.sample_rate / DATTORRO_SAMPLE_RATE * self.current_size;
+
+        let damping_frequency = 20000.0 * 0.01f32.powf(self.damping);
+        let damping_coefficient =
+            (-2.0 * PI * damping_frequency.min(self.sample_rate * 0.49) / self.sample_rate).exp();
+
+        let excursion = PLATE_EXCURSION * self.sample_rate / DATTORRO_SAMPLE_RATE;
+        let lfo_values = [
+            (2.0 * PI * self.lfo_phase).sin(),
+            (2.0 * PI * self.lfo_phase).cos(),
+        ];
+        self.lfo_phase += PLATE_LFO_RATE / self.sample_rate;
+        if self.lfo_phase >= 1.0 {
+            self.lfo_phase -= 1.0;
+        }
+
+        let pre_delayed = self
+            .pre_delay
+            .read(self.pre_delay_ms * self.sample_rate / 1000.0);
+        self.pre_delay.write((left + right) * 0.5);
+        let diffused = self
+            .input_diffusers
+            .iter_mut()
+            .zip(PLATE_INPUT_DIFFUSER_LENGTHS)
+            .fold(pre_delayed, |sample, (diffuser, length)| {
+                process_modulated_allpass(diffuser, sample, length * scale)
+            });
+
+        let previous_outputs = self.tank_outputs;
+        for (half_idx, half) in self.tank.iter_mut().enumerate() {
+            let lengths = PLATE_TANK_LENGTHS[half_idx];
+            let mut sample = diffused + previous_outputs[1 - half_idx];
+
+            sample = process_modulated_allpass(
+                &mut half.modulated_allpass,
+                sample,
+                lengths[0] * scale + lfo_values[half_idx] * excursion,
+            );
+            let delayed = half.first_delay.read(lengths[1] * scale);
+            half.first_delay.write(sample);
+
+            half.damping_state =
+                delayed * (1.0 - damping_coefficient) + half.damping_state * damping_coefficient;
+            sample = half.damping_state * self.decay;
+
+            sample = process_modulated_allpass(&mut half.allpass, sample, lengths[2] * scale);
+            let delayed = half.second_delay.read(lengths[3] * scale);
+            half.second_delay.write(sample);
+
+            self.tank_outputs[half_idx] = delayed * self.decay;
+        }
+
+        let mut outputs = [0.0; 2];
+        for (output, taps) in outputs.iter_mut().zip(&PLATE_OUTPUT_TAPS) {
+            for &(gain, half_idx, tap, delay) in taps {
+                let half = &self.tank[half_idx];
+                let delay = delay * scale;
+                *output += gain
+                    * match tap {
+                        PlateTap::FirstDelay => half.first_delay.read(delay),
+                        PlateTap::Allpass => read_interpolated(
+                            &half.allpass.delay_buffer,
+                            half.allpass.delay_index,
+                            delay.clamp(1.0, (half.allpass.delay_buffer.len() - 1) as f32),
+                        ),
+                        PlateTap::SecondDelay => half.second_delay.read(delay),
+                    };
+            }
+            *output *= PLATE_OUTPUT_GAIN;
+        }
+
+        (outputs[0], outputs[1])
+    }
+}
+
+/// The number of stretched allpasses in every spring's dispersion chain.
+const SPRING_NUM_STAGES: usize = 80;
+/// The coefficient of the stretched allpasses. Higher values spread the chirp out further.
+const SPRING_ALLPASS_COEFFICIENT: f32 = 0.75;
+/// The range of the chirp's transition frequency at zero and full tension, in Hz.
+const SPRING_MIN_TRANSITION_FREQUENCY: f32 = 2500.0;
+const SPRING_MAX_TRANSITION_FREQUENCY: f32 = 5500.0;
+/// How much the spring delays shrink at full tension.
+const SPRING_TENSION_DELAY_SCALE: f32 = 0.7;
+/// How far the drip modulation moves the spring's delay in either direction, in milliseconds, and
+/// how fast it wanders, in Hz.
+const SPRING_DRIP_MODULATION_MS: f32 = 0.25;
+const SPRING_DRIP_RATE: f32 = 3.0;
+pub const SPRING_MIN_DECAY_SECONDS: f32 = 0.3;
+pub const SPRING_MAX_DECAY_SECONDS: f32 = 8.0;
+/// The tone control's low-pass cutoff at 0 and 1, in Hz.
+const SPRING_MIN_TONE_FREQUENCY: f32 = 1500.0;
+const SPRING_MAX_TONE_FREQUENCY: f32 = 8000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpringTank {
+    /// A short two-spring tank, like the ones in most combo amps.
+    #[default]
+    TwoSpring,
+    /// A long three-spring tank, like the ones in outboard reverb units.
+    ThreeSpring,
+}
+
+impl SpringTank {
+    /// The delay of each spring in milliseconds at zero tension, and how its transition frequency
+    /// relates to the others'. The slight differences keep the springs from ringing together.
+    fn springs(self) -> &'static [(f32, f32)] {
+        match self {
+            SpringTank::TwoSpring => &[(33.0, 1.0), (41.0, 0.92)],
+            SpringTank::ThreeSpring => &[(37.0, 1.0), (43.0, 0.95), (51.0, 0.9)],
+        }
+    }
+}
+
+/// The longest spring delay in any [`SpringTank`], in milliseconds.
+const SPRING_MAX_DELAY_MS: f32 = 51.0;
+const SPRING_MAX_SPRINGS: usize = 3;
+
+/// A single spring in a [`SpringReverb`].
+#[derive(Debug, Clone)]
+struct Spring {
+    /// [`AllPassFilter`]s with a delay of `K` samples, which turns them into first-order
+    /// allpasses stretched by a factor of `K`. The buffers are sized for the lowest transition
+    /// frequency.
+    dispersion_filters: Vec<AllPassFilter>,
+    /// Removes the images above the transition frequency that the stretching creates.
+    image_filter: Biquad<f32>,
+    delay_line: DelayLine,
+    drip: RandomDrift,
+    feedback_sample: f32,
+}
+
+impl Spring {
+    fn new(sample_rate: f32, spring_idx: usize) -> Self {
+        let max_stretch = Self::stretch(sample_rate, SPRING_MIN_TRANSITION_FREQUENCY * 0.9);
+        let mut filter = AllPassFilter::new(max_stretch + 2);
+        filter.set_feedback(SPRING_ALLPASS_COEFFICIENT);
+        let max_delay_ms = SPRING_MAX_DELAY_MS + SPRING_DRIP_MODULATION_MS;
+
+        Spring {
+            dispersion_filters: vec![filter; SPRING_NUM_STAGES],
+            image_filter: Biquad::default(),
+            delay_line: DelayLine::new((sample_rate * max_delay_ms / 1000.0).ceil() as usize),
+            drip: RandomDrift::new(spring_idx as u32),
+            feedback_sample: 0.0,
+        }
+    }
+
+    /// The stretch factor that puts the chirp's transition frequency at `frequency`.
+    fn stretch(sample_rate: f32, frequency: f32) -> usize {
+        ((sample_rate / (2.0 * frequency)).round() as usize).max(1)
+    }
+
+    fn reset(&mut self) {
+        for filter in &mut self.dispersion_filters {
+            filter.reset();
+        }
+        self.image_filter.reset();
+        self.delay_line.reset();
+        self.feedback_sample = 0.0;
+    }
+}
+
+/// A spring reverb following Välimäki, Parker and Abel's "Parametric Spring Reverberation
+/// Effect". Every spring is a feedback loop around a long cascade of stretched first-order
+/// allpasses, which gives the dispersion that makes springs chirp, and a delay line whose length
+/// slowly wanders for the drip. The springs run in parallel, so the presets for two- and
+/// three-spring tanks only differ in the number of springs and their lengths. This does its own
+/// dry/wet mixing.
+#[derive(Debug, Clone)]
+pub struct SpringReverb {
+    sample_rate: f32,
+    /// The springs for every channel. Only the first `tank.springs().len()` are used.
+    springs: Vec<Vec<Spring>>,
+    tone_filters: Vec<Biquad<f32>>,
+
+    tank: SpringTank,
+    tension: f32,
+    decay_seconds: f32,
+    tone: f32,
+    mix: f32,
+}
+
+impl SpringReverb {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        let mut reverb = SpringReverb {
+            sample_rate,
+            springs: vec![
+                (0..SPRING_MAX_SPRINGS)
+                    .map(|spring_idx| Spring::new(sample_rate, spring_idx))
+                    .collect();
+                num_channels
+            ],
+            tone_filters: vec![Biquad::default(); num_channels],
+
+            tank: SpringTank::TwoSpring,
+            tension: 0.5,
+            decay_seconds: 2.0,
+            tone: 0.5,
+            mix: 0.3,
+        };
+        reverb.update_filters();
+
+        reverb
+    }
+
+    pub fn set_tank(&mut self, tank: SpringTank) {
+        if tank != self.tank {
+            self.tank = tank;
+            self.update_filters();
+            for channel_springs in &mut self.springs {
+                for spring in channel_springs {
+                    spring.reset();
+                }
+            }
+        }
+    }
+
+    /// Set the spring tension, from 0 to 1. Tighter springs have shorter delays and a higher
+    /// chirp transition frequency.
+    pub fn set_tension(&mut self, tension: f32) {
+        let tension = tension.clamp(0.0, 1.0);
+        if tension != self.tension {
+            self.tension = tension;
+            self.update_filters();
+        }
+    }
+
+    /// Set the time it takes for the reverb to decay by 60 dB, between [`SPRING_MIN_DECAY_SECONDS`]
+    /// and [`SPRING_MAX_DECAY_SECONDS`].
+    pub fn set_decay(&mut self, decay_seconds: f32) {
+        self.decay_seconds =
+            decay_seconds.clamp(SPRING_MIN_DECAY_SECONDS, SPRING_MAX_DECAY_SECONDS);
+    }
+
+    /// Set the brightness of the reverb, from 0 to 1.
+    pub fn set_tone(&mut self, tone: f32) {
+        let tone = tone.clamp(0.0, 1.0);
+        if tone != self.tone {
+            self.tone = tone;
+            self.update_filters();
+        }
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Rebuilds the springs for a new sample rate. This allocates.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let num_channels = self.springs.len();
+        let mut reverb = Self::new(sample_rate, num_channels);
+        reverb.tank = self.tank;
+        reverb.tension = self.tension;
+        reverb.decay_seconds = self.decay_seconds;
+        reverb.tone = self.tone;
+        reverb.mix = self.mix;
+        reverb.update_filters();
+
+        *self = reverb;
+    }
+
+    pub fn reset(&mut self) {
+        for channel_springs in &mut self.springs {
+            for spring in channel_springs {
+                spring.reset();
+            }
+        }
+        for filter in &mut self.tone_filters {
+            filter.reset();
+        }
+    }
+
+    /// The time it takes for the tail to decay by 60 dB after the input stops, in samples.
+    pub fn tail_length_samples(&self) -> u32 {
+        ((self.decay_seconds + SPRING_MAX_DELAY_MS / 1000.0) * self.sample_rate).ceil() as u32
+    }
+
+    /// The chirp transition frequency for a spring at the current tension.
+    fn transition_frequency(sample_rate: f32, tension: f32, relative_frequency: f32) -> f32 {
+        let frequency = SPRING_MIN_TRANSITION_FREQUENCY
+            + (SPRING_MAX_TRANSITION_FREQUENCY - SPRING_MIN_TRANSITION_FREQUENCY) * tension;
+        (frequency * relative_frequency).min(sample_rate * 0.45)
+    }
+
+    fn update_filters(&mut self) {
+        let tone_frequency = SPRING_MIN_TONE_FREQUENCY
+            * (SPRING_MAX_TONE_FREQUENCY / SPRING_MIN_TONE_FREQUENCY).powf(self.tone);
+        let tone_coefficients = BiquadCoefficients::lowpass(
+            self.sample_rate,
+            tone_frequency.min(self.sample_rate * 0.45),
+            0.707,
+        );
+        for filter in &mut self.tone_filters {
+            filter.coefficients = tone_coefficients;
+        }
+
+        for (spring_idx, &(_, relative_frequency)) in self.tank.springs().iter().enumerate() {
+            let image_coefficients = BiquadCoefficients::lowpass(
+                self.sample_rate,
+                Self::transition_frequency(self.sample_rate, self.tension, relative_frequency),
+                0.707,
+            );
+            for channel_springs in &mut self.springs {
+                channel_springs[spring_idx].image_filter.coefficients = image_coefficients;
+            }
+        }
+    }
+
+    pub fn process(&mut self, input: f32, channel_idx: usize) -> f32 {
+        let springs = self.tank.springs();
+        let delay_scale = 1.0 - (1.0 - SPRING_TENSION_DELAY_SCALE) * self.tension;
+        let samples_per_ms = self.sample_rate / 1000.0;
+
+        let mut wet = 0.0;
+        for (spring, &(delay_ms, relative_frequency)) in
+            self.springs[channel_idx].iter_mut().zip(springs)
+        {
+            let stretch = Spring::stretch(
+                self.sample_rate,
+                Self::transition_frequency(self.sample_rate, self.tension, relative_frequency),
+            ) as f32;
+            let mut sample = input + spring.feedback_sample;
+            for filter in &mut spring.dispersion_filters {
+                sample = process_modulated_allpass(filter, sample, stretch);
+            }
+            sample = spring.image_filter.process(sample);
+            wet += sample;
+
+            // The dispersion chain's delay at DC is part of the spring's total delay, and the
+            // loop gain is chosen so a full round trip decays by the right amount
+            let loop_delay = delay_ms * delay_scale * samples_per_ms;
+            let chain_delay =
+                SPRING_NUM_STAGES as f32 * stretch * (1.0 - SPRING_ALLPASS_COEFFICIENT)
+                    / (1.0 + SPRING_ALLPASS_COEFFICIENT);
+            let drip = spring.drip.next(SPRING_DRIP_RATE, self.sample_rate)
+                * SPRING_DRIP_MODULATION_MS
+                * samples_per_ms;
+            let delayed = spring
+                .delay_line
+                .read((loop_delay - chain_delay).max(1.0) + drip);
+            spring.delay_line.write(sample);
+
+            let gain = 10.0f32.powf(-3.0 * loop_delay / (self.decay_seconds * self.sample_rate));
+            spring.feedback_sample = delayed * gain;
+        }
+        wet = self.tone_filters[channel_idx].process(wet / (springs.len() as f32).sqrt());
+
+        input * (1.0 - self.mix) + wet * self.mix
+    }
+}
+
+pub const DELAY_MAX_TIME_MS: f32 = 2000.0;
+/// The time constant of the delay time glide, in seconds.
+const DELAY_GLIDE_SECONDS: f32 = 0.15;
+const DELAY_CROSSFADE_MS: f32 = 50.0;
+/// The rates in Hz and the maximum delay modulation in milliseconds of the wow and flutter.
+const DELAY_WOW_RATE: f32 = 0.7;
+const DELAY_WOW_MS: f32 = 1.5;
+const DELAY_FLUTTER_RATE: f32 = 7.5;
+const DELAY_FLUTTER_MS: f32 = 0.08;
+/// Anything written to the delay line below this level, -100 dB, counts as silence.
+const DELAY_SILENT_LEVEL: f32 = 1e-5;
+
+/// How a [`Delay`] moves to a new delay time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DelayTimeMode {
+    /// The read head slides to the new time, which bends the pitch like a tape delay would.
+    #[default]
+    Glide,
+    /// A second read head is started at the new time, and the output crossfades to it.
+    Crossfade,
+}
+
+/// A stereo tape-style delay. The feedback path is band limited and saturated with one of the
+/// [`WaveShaper`] curves, and the delay time is modulated with wow and flutter. In ping-pong mode
+/// the mono sum of the input enters the left channel and the repeats alternate between the
+/// channels. Like [`PlateReverb`], this processes both channels at once and only outputs the wet
+/// signal.
+#[derive(Debug, Clone)]
+pub struct Delay {
+    sample_rate: f32,
+    delay_lines: [DelayLine; 2],
+    low_cut_filters: [Biquad<f32>; 2],
+    high_cut_filters: [Biquad<f32>; 2],
+    feedback_samples: [f32; 2],
+    /// How many samples in a row only silence has been written to the delay lines.
+    num_silent_samples: usize,
+    /// The delays of the two read heads in samples. Only the active head is used while gliding.
+    head_delays: [f32; 2],
+    /// The head that's being faded to, and how far along that fade is. The fade is done at 1.
+    active_head: usize,
+    crossfade_position: f32,
+    wow_phase: f32,
+    flutter_phase: f32,
+    wow_drift: RandomDrift,
+
+    time_ms: f32,
+    time_mode: DelayTimeMode,
+    feedback: f32,
+    low_cut_frequency: f32,
+    high_cut_frequency: f32,
+    saturation: Option<WaveShaper>,
+    drive: f32,
+    wow_flutter: f32,
+    ping_pong: bool,
+}
+
+impl Delay {
+    pub fn new(sample_rate: f32) -> Self {
+        let max_delay_ms = DELAY_MAX_TIME_MS + DELAY_WOW_MS + DELAY_FLUTTER_MS;
+        let max_delay_samples = (sample_rate * max_delay_ms / 1000.0).ceil() as usize + 1;
+        let time_ms = 350.0;
+
+        let mut delay = Delay {
+            sample_rate,
+            delay_lines: [
+                DelayLine::new(max_delay_samples),
+                DelayLine::new(max_delay_samples),
+            ],
+            low_cut_filters: [Biquad::default(); 2],
+            high_cut_filters: [Biquad::default(); 2],
+            feedback_samples: [0.0; 2],
+            num_silent_samples: usize::MAX,
+            head_delays: [time_ms * sample_rate / 1000.0; 2],
+            active_head: 0,
+            crossfade_position: 1.0,
+            wow_phase: 0.0,
+            flutter_phase: 0.0,
+            wow_drift: RandomDrift::new(0),
+
+            time_ms,
+            time_mode: DelayTimeMode::Glide,
+            feedback: 0.4,
+            low_cut_frequency: 80.0,
+            high_cut_frequency: 5000.0,
+            saturation: Some(WaveShaper::default()),
+            drive: 1.0,
+            wow_flutter: 0.2,
+            ping_pong: false,
+        };
+        delay.update_filters();
+
+        delay
+    }
+
+    /// Set the delay time in milliseconds, up to [`DELAY_MAX_TIME_MS`]. Tempo synced times can be
+    /// computed with [`NoteDivision::length_ms()`][super::modulation::NoteDivision::length_ms].
+    pub fn set_time(&mut self, time_ms: f32) {
+        self.time_ms = time_ms.clamp(1.0, DELAY_MAX_TIME_MS);
+    }
+
+    pub fn set_time_mode(&mut self, time_mode: DelayTimeMode) {
+        if time_mode != self.time_mode {
+            // Gliding only uses the active head, so any fade in progress is cut short
+            self.crossfade_position = 1.0;
+            self.time_mode = time_mode;
+        }
+    }
+
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(0.0, 1.0);
+    }
+
+    /// Set the cutoff frequencies of the high-pass and low-pass filters in the feedback path.
+    pub fn set_filters(&mut self, low_cut_frequency: f32, high_cut_frequency: f32) {
+        if low_cut_frequency != self.low_cut_frequency
+            || high_cut_frequency != self.high_cut_frequency
+        {
+            self.low_cut_frequency = low_cut_frequency;
+            self.high_cut_frequency = high_cut_frequency;
+            self.update_filters();
+        }
+    }
+
+    /// Set the curve that saturates the feedback path, or `None` to keep it clean.
+    pub fn set_saturation(&mut self, saturation: Option<WaveShaper>) {
+        self.saturation = saturation;
+    }
+
+    /// Set the gain going into the saturation curve. The curve's output is scaled back down by the
+    /// same amount.
+    pub fn set_drive(&mut self, drive: f32) {
+        self.drive = drive.max(1.0);
+    }
+
+    /// Set the amount of wow and flutter, from 0 to 1.
+    pub fn set_wow_flutter(&mut self, wow_flutter: f32) {
+        self.wow_flutter = wow_flutter.clamp(0.0, 1.0);
+    }
+
+    pub fn set_ping_pong(&mut self, ping_pong: bool) {
+        self.ping_pong = ping_pong;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        let max_delay_ms = DELAY_MAX_TIME_MS + DELAY_WOW_MS + DELAY_FLUTTER_MS;
+        let max_delay_samples = (sample_rate * max_delay_ms / 1000.0).ceil() as usize + 1;
+        for delay_line in &mut self.delay_lines {
+            delay_line.resize(max_delay_samples);
+        }
+        self.update_filters();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        for delay_line in &mut self.delay_lines {
+            delay_line.reset();
+        }
+        for filter in self
+            .low_cut_filters
+            .iter_mut()
+            .chain(self.high_cut_filters.iter_mut())
+        {
+            filter.reset();
+        }
+        self.feedback_samples = [0.0; 2];
+        self.num_silent_samples = usize::MAX;
+        self.head_delays = [self.time_ms * self.sample_rate / 1000.0; 2];
+        self.crossfade_position = 1.0;
+        self.wow_phase = 0.0;
+        self.flutter_phase = 0.0;
+        self.wow_drift = RandomDrift::new(0);
+    }
+
+    fn update_filters(&mut self) {
+        let nyquist_limit = self.sample_rate * 0.45;
+        let low_cut = BiquadCoefficients::highpass(
+            self.sample_rate,
+            self.low_cut_frequency.min(nyquist_limit),
+            0.707,
+        );
+        let high_cut = BiquadCoefficients::lowpass(
+            self.sample_rate,
+            self.high_cut_frequency.min(nyquist_limit),
+            0.707,
+        );
+        for filter in &mut self.low_cut_filters {
+            filter.coefficients = low_cut;
+        }
+        for filter in &mut self.high_cut_filters {
+            filter.coefficients = high_cut;
+        }
+    }
+
+    /// The time it takes for the repeats to decay by 60 dB after the input stops, in samples, or
+    /// `None` if that can't be bounded. The bound ignores the filters and uses the saturation
+    /// curve's steepest slope. The green clipper's negative half is much steeper than what the
+    /// filtered repeats usually get to see, so with saturation enabled this can be `None` at
+    /// moderate feedback settings even though the repeats do die away. Once nothing but silence
+    /// has been written to the delay line for its full length there's nothing left to repeat, and
+    /// the tail is 0 regardless.
+    pub fn tail_length_samples(&self) -> Option<u32> {
+        if self.num_silent_samples > self.delay_lines[0].max_delay_samples() {
+            return Some(0);
+        }
+
+        let loop_gain = match self.saturation {
+            Some(wave_shaper) => self.feedback * wave_shaper.small_signal_gain(),
+            None => self.feedback,
+        };
+        if loop_gain >= 1.0 {
+            return None;
+        }
+
+        let num_repeats = if loop_gain > 0.0 {
+            0.001f32.ln() / loop_gain.ln()
+        } else {
+            0.0
+        };
+        let delay_samples = (self.time_ms + DELAY_WOW_MS) * self.sample_rate / 1000.0;
+        Some(((num_repeats + 1.0) * delay_samples).ceil() as u32)
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let target_delay = self.time_ms * self.sample_rate / 1000.0;
+        match self.time_mode {
+            DelayTimeMode::Glide => {
+                let glide_coefficient =
+                    1.0 - (-1.0 / (DELAY_GLIDE_SECONDS * self.sample_rate)).exp();
+                let head_delay = &mut self.head_delays[self.active_head];
+                *head_delay += (target_delay - *head_delay) * glide_coefficient;
+            }
+            DelayTimeMode::Crossfade => {
+                // A new fade only starts once the previous one has finished
+                if self.crossfade_position >= 1.0
+                    && self.head_delays[self.active_head] != target_delay
+                {
+                    self.active_head = 1 - self.active_head;
+                    self.head_delays[self.active_head] = target_delay;
+                    self.crossfade_position = 0.0;
+                }
+            }
+        }
+
+        let wow = 0.5 * (2.0 * PI * self.wow_phase).sin()
+            + 0.5 * self.wow_drift.next(DELAY_WOW_RATE, self.sample_rate);
+        let flutter = (2.0 * PI * self.flutter_phase).sin();
+        let modulation =
+            self.wow_flutter * (wow * DELAY_WOW_MS + flutter * DELAY_FLUTTER_MS) * self.sample_rate
+                / 1000.0;
+        self.wow_phase += DELAY_WOW_RATE / self.sample_rate;
+        if self.wow_phase >= 1.0 {
+            self.wow_phase -= 1.0;
+        }
+        self.flutter_phase += DELAY_FLUTTER_RATE / self.sample_rate;
+        if self.flutter_phase >= 1.0 {
+            self.flutter_phase -= 1.0;
+        }
+
+        // Equal power, since the two heads are not correlated
+        let fade_angle = self.crossfade_position * PI / 2.0;
+        let (active_gain, inactive_gain) = (fade_angle.sin(), fade_angle.cos());
+        let active_delay = self.head_delays[self.active_head] + modulation;
+        let inactive_delay = self.head_delays[1 - self.active_head] + modulation;
+        let mut outputs = [0.0; 2];
+        for (channel_idx, output) in outputs.iter_mut().enumerate() {
+            let delay_line = &self.delay_lines[channel_idx];
+            *output = if self.crossfade_position < 1.0 {
+                delay_line.read(active_delay) * active_gain
+                    + delay_line.read(inactive_delay) * inactive_gain
+            } else {
+                delay_line.read(active_delay)
+            };
+
+            let filtered = self.high_cut_filters[channel_idx]
+                .process(self.low_cut_filters[channel_idx].process(*output));
+            let saturated = match self.saturation {
+                Some(wave_shaper) => wave_shaper.apply(filtered * self.drive) / self.drive,
+                None => filtered,
+            };
+            self.feedback_samples[channel_idx] = saturated * self.feedback;
+        }
+        if self.crossfade_position < 1.0 {
+            self.crossfade_position += 1000.0 / (DELAY_CROSSFADE_MS * self.sample_rate);
+        }
+
+        let written = if self.ping_pong {
+            [
+                (left + right) * 0.5 + self.feedback_samples[1],
+                self.feedback_samples[0],
+            ]
+        } else {
+            [
+                left + self.feedback_samples[0],
+                right + self.feedback_samples[1],
+            ]
+        };
+        for (delay_line, sample) in self.delay_lines.iter_mut().zip(written) {
+            delay_line.write(sample);
+        }
+        if written.iter().all(|sample| sample.abs() < DELAY_SILENT_LEVEL) {
+            self.num_silent_samples = self.num_silent_samples.saturating_add(1);
+        } else {
+            self.num_silent_samples = 0;
+        }
+
+        (outputs[0], outputs[1])
+    }
+}
+
+/// The default lowest and highest resonant frequencies of a [`Wah`], roughly those of the classic
+/// inductor wahs.
+pub const WAH_DEFAULT_MIN_FREQUENCY: f32 = 350.0;
+pub const WAH_DEFAULT_MAX_FREQUENCY: f32 = 2200.0;
+/// The attack and release times of the wah's envelope follower. These are kept fast, and the
+/// response time smooths the resulting sweep instead.
+const WAH_ENVELOPE_ATTACK_MS: f32 = 2.0;
+const WAH_ENVELOPE_RELEASE_MS: f32 = 30.0;
+
+/// What moves a [`Wah`]'s filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WahMode {
+    /// The pedal position set with [`Wah::set_position()`].
+    #[default]
+    Manual,
+    /// The input's envelope, like an auto-wah or envelope filter.
+    Envelope,
+    /// An LFO that sweeps the whole range.
+    Lfo,
+}
+
+/// A wah pedal. In the classic circuits an inductor and a capacitor form a resonant band-pass
+/// whose frequency is swept by the pedal's potentiometer. This models that resonance with a
+/// topology-preserving state variable filter, so the frequency can be changed every sample
+/// without artifacts. The band-pass peaks at unity gain, and the pedal position is mapped
+/// exponentially between the minimum and maximum frequencies.
+#[derive(Debug, Clone)]
+pub struct Wah {
+    sample_rate: f32,
+    /// The two integrator states of the state variable filter for every channel.
+    filter_states: Vec<[f32; 2]>,
+    envelope_followers: Vec<EnvelopeFollower>,
+    lfo_phases: Vec<f32>,
+    /// The smoothed position for every channel, in `[0, 1]`.
+    positions: Vec<f32>,
+
+    mode: WahMode,
+    position: f32,
+    min_frequency: f32,
+    max_frequency: f32,
+    resonance: f32,
+    response_ms: f32,
+    sensitivity: f32,
+    rate: f32,
+    lfo_shape: LfoShape,
+}
+
+impl Wah {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        let mut envelope_follower = EnvelopeFollower::new(sample_rate);
+        envelope_follower.set_attack(WAH_ENVELOPE_ATTACK_MS);
+        envelope_follower.set_release(WAH_ENVELOPE_RELEASE_MS);
+
+        Wah {
+            sample_rate,
+            filter_states: vec![[0.0; 2]; num_channels],
+            envelope_followers: vec![envelope_follower; num_channels],
+            lfo_phases: vec![0.0; num_channels],
+            positions: vec![0.5; num_channels],
+
+            mode: WahMode::Manual,
+            position: 0.5,
+            min_frequency: WAH_DEFAULT_MIN_FREQUENCY,
+            max_frequency: WAH_DEFAULT_MAX_FREQUENCY,
+            resonance: 5.0,
+            response_ms: 20.0,
+            sensitivity: 4.0,
+            rate: 1.0,
+            lfo_shape: LfoShape::Sine,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: WahMode) {
+        self.mode = mode;
+    }
+
+    /// Set the pedal position in manual mode, from 0 (heel down) to 1 (toe down).
+    pub fn set_position(&mut self, position: f32) {
+        self.position = position.clamp(0.0, 1.0);
+    }
+
+    /// Set the frequencies in Hz the filter sweeps between.
+    pub fn set_range(&mut self, min_frequency: f32, max_frequency: f32) {
+        self.min_frequency = min_frequency.max(20.0);
+        self.max_frequency = max_frequency.max(self.min_frequency);
+    }
+
+    /// Set the filter's Q.
+    pub fn set_resonance(&mut self, resonance: f32) {
+        self.resonance = resonance.max(0.5);
+    }
+
+    /// Set how quickly the filter follows the pedal, the envelope or the LFO. This is the time
+    /// constant of the smoothing applied to the position.
+    pub fn set_response(&mut self, response_ms: f32) {
+        self.response_ms = response_ms.max(0.0);
+    }
+
+    /// Set the gain applied to the envelope in envelope mode. The position is the envelope times
+    /// this gain, clamped to the top of the range.
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity.max(0.0);
+    }
+
+    /// Set the LFO rate in Hz.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    pub fn set_lfo_shape(&mut self, lfo_shape: LfoShape) {
+        self.lfo_shape = lfo_shape;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for envelope_follower in &mut self.envelope_followers {
+            envelope_follower.set_sample_rate(sample_rate);
+        }
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.filter_states.fill([0.0; 2]);
+        for envelope_follower in &mut self.envelope_followers {
+            envelope_follower.reset();
+        }
+        self.lfo_phases.fill(0.0);
+        self.positions.fill(self.position);
+    }
+
+    pub fn process(&mut self, input: f32, channel_idx: usize) -> f32 {
+        // The follower keeps running in the other modes so switching to envelope mode doesn't
+        // start from silence
+        let envelope = self.envelope_followers[channel_idx].process(input);
+        let lfo_value = self.lfo_shape.value(self.lfo_phases[channel_idx]);
+        self.lfo_phases[channel_idx] += self.rate / self.sample_rate;
+        if self.lfo_phases[channel_idx] >= 1.0 {
+            self.lfo_phases[channel_idx] -= 1.0;
+        }
+
+        let target_position = match self.mode {
+            WahMode::Manual => self.position,
+            WahMode::Envelope => (envelope * self.sensitivity).min(1.0),
+            WahMode::Lfo => 0.5 + 0.5 * lfo_value,
+        };
+        let coefficient = smoothing_coefficient(self.sample_rate, self.response_ms);
+        let position = &mut self.positions[channel_idx];
+        *position = target_position * (1.0 - coefficient) + *position * coefficient;
+
+        let frequency = (self.min_frequency
+            * (self.max_frequency / self.min_frequency).powf(*position))
+        .min(self.sample_rate * 0.45);
+        let g = (PI * frequency / self.sample_rate).tan();
+        let k = 1.0 / self.resonance;
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        let [ic1eq, ic2eq] = &mut self.filter_states[channel_idx];
+        let v3 = input - *ic2eq;
+        let v1 = a1 * *ic1eq + a2 * v3;
+        let v2 = *ic2eq + a2 * *ic1eq + a3 * v3;
+        *ic1eq = 2.0 * v1 - *ic1eq;
+        *ic2eq = 2.0 * v2 - *ic2eq;
+
+        // The band-pass output peaks at Q, so this normalizes it back to unity
+        k * v1
+    }
+}
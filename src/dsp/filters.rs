@@ -1,5 +1,8 @@
 use std::f32::consts::PI;
 
+use super::fir_design::FirDesignError;
+use super::response::{self, FrequencyResponse, Response};
+
 pub struct IIRLowPass {
     a1: f32,
     b0: f32,
@@ -39,6 +42,17 @@ impl IIRLowPass {
     }
 }
 
+impl FrequencyResponse for IIRLowPass {
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        response::transfer_function_response(
+            &[self.b0 as f64],
+            &[1.0, -self.a1 as f64],
+            frequency,
+            sample_rate,
+        )
+    }
+}
+
 pub fn remove_dc_offset(input: &[f32]) -> Vec<f32> {
     if input.is_empty() {
         return Vec::new();
@@ -61,20 +75,25 @@ pub struct FIRLowPass {
 }
 
 impl FIRLowPass {
-    pub fn new(cutoff_freq: f32, sample_rate: f32, order: usize) -> Self {
-        let coefficients = Self::design_lowpass(cutoff_freq, sample_rate, order);
-        let history = vec![0.0; order];
-        FIRLowPass {
+    /// Create a windowed sinc low-pass filter. The order needs to be even and at least 2. For
+    /// sharper filters with exact ripple and attenuation targets, see
+    /// [`super::fir_design`].
+    pub fn new(cutoff_freq: f32, sample_rate: f32, order: usize) -> Result<Self, FirDesignError> {
+        let coefficients = Self::design_lowpass(cutoff_freq, sample_rate, order)?;
+        let history = vec![0.0; order + 1];
+        Ok(FIRLowPass {
             coefficients,
             history,
             sample_rate,
             order,
-        }
+        })
     }
 
-    pub fn set_cutoff(&mut self, cutoff_freq: f32) {
-        self.coefficients = Self::design_lowpass(cutoff_freq, self.sample_rate, self.order);
-        self.history.resize(self.order, 0.0);
+    /// Redesign the filter for a new cutoff frequency. The filter is left unchanged if the
+    /// frequency is invalid.
+    pub fn set_cutoff(&mut self, cutoff_freq: f32) -> Result<(), FirDesignError> {
+        self.coefficients = Self::design_lowpass(cutoff_freq, self.sample_rate, self.order)?;
+        Ok(())
     }
 
     pub fn process(&mut self, input: f32) -> f32 {
@@ -88,35 +107,43 @@ impl FIRLowPass {
         output
     }
 
-    fn design_lowpass(cutoff_freq: f32, sample_rate: f32, order: usize) -> Vec<f32> {
-        if order % 2 != 0 || order < 2 {
-            eprintln!(
-                "Warning: FIR filter order should be an even number >= 2 for this simple design."
-            );
+    fn design_lowpass(
+        cutoff_freq: f32,
+        sample_rate: f32,
+        order: usize,
+    ) -> Result<Vec<f32>, FirDesignError> {
+        let num_taps = order + 1;
+        if !order.is_multiple_of(2) || order < 2 {
+            return Err(FirDesignError::InvalidNumTaps(num_taps));
         }
 
-        let num_taps = order + 1;
         let mut coefficients = vec![0.0; num_taps];
         let normalized_cutoff = cutoff_freq / (sample_rate / 2.0); // Nyquist frequency
+        if !(normalized_cutoff > 0.0 && normalized_cutoff <= 1.0) {
+            return Err(FirDesignError::InvalidCutoff(cutoff_freq));
+        }
 
-        if normalized_cutoff >= 0.0 && normalized_cutoff <= 1.0 {
-            for i in 0..num_taps {
-                if i == order / 2 {
-                    coefficients[i] = normalized_cutoff;
-                } else {
-                    let m = i as f32 - order as f32 / 2.0;
-                    coefficients[i] = normalized_cutoff * (m * PI).sin() / (m * PI);
-                }
-
-                // Apply a window function (Hamming window for better stopband attenuation)
-                let window_value = 0.54 - 0.46 * (2.0 * PI * i as f32 / order as f32).cos();
-                coefficients[i] *= window_value;
+        for i in 0..num_taps {
+            if i == order / 2 {
+                coefficients[i] = normalized_cutoff;
+            } else {
+                let m = i as f32 - order as f32 / 2.0;
+                coefficients[i] = (normalized_cutoff * m * PI).sin() / (m * PI);
             }
-        } else {
-            eprintln!("Warning: Invalid normalized cutoff frequency.");
+
+            // Apply a window function (Hamming window for better stopband attenuation)
+            let window_value = 0.54 - 0.46 * (2.0 * PI * i as f32 / order as f32).cos();
+            coefficients[i] *= window_value;
         }
 
-        coefficients
+        Ok(coefficients)
+    }
+}
+
+impl FrequencyResponse for FIRLowPass {
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        let coefficients: Vec<f64> = self.coefficients.iter().map(|c| *c as f64).collect();
+        response::transfer_function_response(&coefficients, &[1.0], frequency, sample_rate)
     }
 }
 
@@ -155,3 +182,325 @@ impl AllPassFilter {
         self.delay_index = 0;
     }
 }
+
+impl FrequencyResponse for AllPassFilter {
+    /// The response of `H(z) = (g + z^-M) / (1 + g z^-M)`, where `M` is the delay buffer's length.
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        let delay = self.delay_buffer.len();
+        let mut numerator = vec![0.0; delay + 1];
+        let mut denominator = vec![0.0; delay + 1];
+        numerator[0] = self.feedback as f64;
+        numerator[delay] = 1.0;
+        denominator[0] = 1.0;
+        denominator[delay] = self.feedback as f64;
+
+        response::transfer_function_response(&numerator, &denominator, frequency, sample_rate)
+    }
+}
+
+/// A first-order allpass with a phase shift of -90 degrees at its break frequency, going from 0
+/// degrees at DC to -180 degrees at the Nyquist frequency. Unlike [`AllPassFilter`] this has no
+/// delay line, which makes it cheap to modulate.
+#[derive(Debug, Clone, Default)]
+pub struct FirstOrderAllPass {
+    coefficient: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl FirstOrderAllPass {
+    pub fn new(sample_rate: f32, frequency: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_frequency(sample_rate, frequency);
+
+        filter
+    }
+
+    pub fn set_frequency(&mut self, sample_rate: f32, frequency: f32) {
+        self.coefficient = Self::coefficient(sample_rate, frequency);
+    }
+
+    /// The coefficient for a break frequency. Useful when many allpasses share the same
+    /// frequency, see [`Self::set_coefficient`].
+    pub fn coefficient(sample_rate: f32, frequency: f32) -> f32 {
+        let t = (PI * frequency.clamp(1.0, sample_rate * 0.49) / sample_rate).tan();
+        (t - 1.0) / (t + 1.0)
+    }
+
+    pub fn set_coefficient(&mut self, coefficient: f32) {
+        self.coefficient = coefficient;
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = self.coefficient * input + self.x1 - self.coefficient * self.y1;
+        self.x1 = input;
+        self.y1 = output;
+        output
+    }
+
+    pub fn reset(&mut self) {
+        self.x1 = 0.0;
+        self.y1 = 0.0;
+    }
+}
+
+impl FrequencyResponse for FirstOrderAllPass {
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        response::transfer_function_response(
+            &[self.coefficient as f64, 1.0],
+            &[1.0, self.coefficient as f64],
+            frequency,
+            sample_rate,
+        )
+    }
+}
+
+/// The passive bass/mid/treble network found in most Fender, Marshall and Vox style amps, modelled
+/// as a third-order IIR filter following Yeh and Smith, "Discretization of the '59 Fender Bassman
+/// Tone Stack". The continuous-time transfer function is derived directly from the component
+/// values and the knob positions, and then discretized with the bilinear transform.
+pub mod tone_stack {
+    use super::response::{self, FrequencyResponse, Response};
+
+    /// Component values for the tone stack, using the names from Yeh's paper. `R1`, `R2` and `R3`
+    /// are the treble, bass and mid pots respectively.
+    #[derive(Debug, Clone, Copy)]
+    pub struct ToneStackComponents {
+        pub r1: f64,
+        pub r2: f64,
+        pub r3: f64,
+        pub r4: f64,
+        pub c1: f64,
+        pub c2: f64,
+        pub c3: f64,
+    }
+
+    impl ToneStackComponents {
+        pub const FENDER_BASSMAN: Self = Self {
+            r1: 250e3,
+            r2: 1e6,
+            r3: 25e3,
+            r4: 56e3,
+            c1: 250e-12,
+            c2: 20e-9,
+            c3: 20e-9,
+        };
+
+        pub const MARSHALL_JCM800: Self = Self {
+            r1: 220e3,
+            r2: 1e6,
+            r3: 22e3,
+            r4: 33e3,
+            c1: 470e-12,
+            c2: 22e-9,
+            c3: 22e-9,
+        };
+
+        /// The AC30's top boost circuit doesn't have a mid control. These are its values mapped
+        /// onto the same three-knob network, with the mid pot acting as a fixed-ish scoop.
+        pub const VOX_AC30: Self = Self {
+            r1: 1e6,
+            r2: 1e6,
+            r3: 10e3,
+            r4: 100e3,
+            c1: 50e-12,
+            c2: 22e-9,
+            c3: 22e-9,
+        };
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ToneStackModel {
+        Fender,
+        Marshall,
+        Vox,
+    }
+
+    impl ToneStackModel {
+        pub fn components(self) -> ToneStackComponents {
+            match self {
+                ToneStackModel::Fender => ToneStackComponents::FENDER_BASSMAN,
+                ToneStackModel::Marshall => ToneStackComponents::MARSHALL_JCM800,
+                ToneStackModel::Vox => ToneStackComponents::VOX_AC30,
+            }
+        }
+    }
+
+    /// How long it takes for the knobs to (mostly) reach a new position, in milliseconds.
+    const KNOB_SMOOTHING_MS: f32 = 10.0;
+
+    /// A tone stack with bass, mid and treble knobs ranging from 0.0 to 1.0. Knob changes are
+    /// smoothed, and the coefficients are recomputed every sample while the knobs are still moving.
+    #[derive(Debug, Clone)]
+    pub struct ToneStack {
+        sample_rate: f32,
+        components: ToneStackComponents,
+        /// The knob positions as `[bass, mid, treble]`.
+        target_knobs: [f32; 3],
+        current_knobs: [f32; 3],
+        smoothing_coef: f32,
+        b: [f64; 4],
+        a: [f64; 4],
+        /// Transposed direct form II state.
+        state: [f64; 3],
+    }
+
+    impl ToneStack {
+        pub fn new(sample_rate: f32, model: ToneStackModel) -> Self {
+            let mut tone_stack = ToneStack {
+                sample_rate,
+                components: model.components(),
+                target_knobs: [0.5; 3],
+                current_knobs: [0.5; 3],
+                smoothing_coef: 0.0,
+                b: [0.0; 4],
+                a: [1.0, 0.0, 0.0, 0.0],
+                state: [0.0; 3],
+            };
+            tone_stack.set_sample_rate(sample_rate);
+
+            tone_stack
+        }
+
+        pub fn set_model(&mut self, model: ToneStackModel) {
+            self.components = model.components();
+            self.update_coefficients();
+        }
+
+        pub fn set_components(&mut self, components: ToneStackComponents) {
+            self.components = components;
+            self.update_coefficients();
+        }
+
+        pub fn set_sample_rate(&mut self, sample_rate: f32) {
+            self.sample_rate = sample_rate;
+            self.smoothing_coef = (-1.0 / (KNOB_SMOOTHING_MS / 1000.0 * sample_rate)).exp();
+            self.update_coefficients();
+            self.reset();
+        }
+
+        /// Set the knob positions. These will be approached smoothly over the next couple of
+        /// milliseconds.
+        pub fn set_knobs(&mut self, bass: f32, mid: f32, treble: f32) {
+            self.target_knobs = [
+                bass.clamp(0.0, 1.0),
+                mid.clamp(0.0, 1.0),
+                treble.clamp(0.0, 1.0),
+            ];
+        }
+
+        /// Jump to the target knob positions and clear the filter state.
+        pub fn reset(&mut self) {
+            self.current_knobs = self.target_knobs;
+            self.update_coefficients();
+            self.state = [0.0; 3];
+        }
+
+        pub fn process(&mut self, input: f32) -> f32 {
+            if self.current_knobs != self.target_knobs {
+                for (current, target) in self.current_knobs.iter_mut().zip(self.target_knobs) {
+                    *current = target + (*current - target) * self.smoothing_coef;
+                    if (*current - target).abs() < 1e-4 {
+                        *current = target;
+                    }
+                }
+                self.update_coefficients();
+            }
+
+            let input = input as f64;
+            let output = self.b[0] * input + self.state[0];
+            self.state[0] = self.b[1] * input - self.a[1] * output + self.state[1];
+            self.state[1] = self.b[2] * input - self.a[2] * output + self.state[2];
+            self.state[2] = self.b[3] * input - self.a[3] * output;
+
+            output as f32
+        }
+
+        fn update_coefficients(&mut self) {
+            let ToneStackComponents {
+                r1,
+                r2,
+                r3,
+                r4,
+                c1,
+                c2,
+                c3,
+            } = self.components;
+            // Only the bass pot is log taper, so its knob position is mapped onto an exponential
+            // curve. The mid and treble pots are linear.
+            let l = ((self.current_knobs[0] as f64 - 1.0) * 3.4).exp();
+            let m = self.current_knobs[1] as f64;
+            let t = self.current_knobs[2] as f64;
+
+            // The analog transfer function coefficients, straight from the paper
+            let b1 = t * c1 * r1 + m * c3 * r3 + l * (c1 * r2 + c2 * r2) + (c1 * r3 + c2 * r3);
+            let b2 = t * (c1 * c2 * r1 * r4 + c1 * c3 * r1 * r4)
+                - m * m * (c1 * c3 * r3 * r3 + c2 * c3 * r3 * r3)
+                + m * (c1 * c3 * r1 * r3 + c1 * c3 * r3 * r3 + c2 * c3 * r3 * r3)
+                + l * (c1 * c2 * r1 * r2 + c1 * c2 * r2 * r4 + c1 * c3 * r2 * r4)
+                + l * m * (c1 * c3 * r2 * r3 + c2 * c3 * r2 * r3)
+                + (c1 * c2 * r1 * r3 + c1 * c2 * r3 * r4 + c1 * c3 * r3 * r4);
+            let b3 = l * m * (c1 * c2 * c3 * r1 * r2 * r3 + c1 * c2 * c3 * r2 * r3 * r4)
+                - m * m * (c1 * c2 * c3 * r1 * r3 * r3 + c1 * c2 * c3 * r3 * r3 * r4)
+                + m * (c1 * c2 * c3 * r1 * r3 * r3 + c1 * c2 * c3 * r3 * r3 * r4)
+                + t * c1 * c2 * c3 * r1 * r3 * r4
+                - t * m * c1 * c2 * c3 * r1 * r3 * r4
+                + t * l * c1 * c2 * c3 * r1 * r2 * r4;
+            let a0 = 1.0;
+            let a1 = (c1 * r1 + c1 * r3 + c2 * r3 + c2 * r4 + c3 * r4)
+                + m * c3 * r3
+                + l * (c1 * r2 + c2 * r2);
+            let a2 = m
+                * (c1 * c3 * r1 * r3 - c2 * c3 * r3 * r4 + c1 * c3 * r3 * r3 + c2 * c3 * r3 * r3)
+                + l * m * (c1 * c3 * r2 * r3 + c2 * c3 * r2 * r3)
+                - m * m * (c1 * c3 * r3 * r3 + c2 * c3 * r3 * r3)
+                + l * (c1 * c2 * r2 * r4
+                    + c1 * c2 * r1 * r2
+                    + c1 * c3 * r2 * r4
+                    + c2 * c3 * r2 * r4)
+                + (c1 * c2 * r1 * r4
+                    + c1 * c3 * r1 * r4
+                    + c1 * c2 * r3 * r4
+                    + c1 * c2 * r1 * r3
+                    + c1 * c3 * r3 * r4
+                    + c2 * c3 * r3 * r4);
+            let a3 = l * m * (c1 * c2 * c3 * r1 * r2 * r3 + c1 * c2 * c3 * r2 * r3 * r4)
+                - m * m * (c1 * c2 * c3 * r1 * r3 * r3 + c1 * c2 * c3 * r3 * r3 * r4)
+                + m * (c1 * c2 * c3 * r3 * r3 * r4 + c1 * c2 * c3 * r1 * r3 * r3
+                    - c1 * c2 * c3 * r1 * r3 * r4)
+                + l * c1 * c2 * c3 * r1 * r2 * r4
+                + c1 * c2 * c3 * r1 * r3 * r4;
+
+            // Bilinear transform without prewarping, the interesting parts of the response are
+            // far enough below Nyquist for that not to matter
+            let k = 2.0 * self.sample_rate as f64;
+            let k2 = k * k;
+            let k3 = k2 * k;
+            let bz = [
+                -b1 * k - b2 * k2 - b3 * k3,
+                -b1 * k + b2 * k2 + 3.0 * b3 * k3,
+                b1 * k + b2 * k2 - 3.0 * b3 * k3,
+                b1 * k - b2 * k2 + b3 * k3,
+            ];
+            let az = [
+                -a0 - a1 * k - a2 * k2 - a3 * k3,
+                -3.0 * a0 - a1 * k + a2 * k2 + 3.0 * a3 * k3,
+                -3.0 * a0 + a1 * k + a2 * k2 - 3.0 * a3 * k3,
+                -a0 + a1 * k - a2 * k2 + a3 * k3,
+            ];
+
+            let a0_recip = az[0].recip();
+            for i in 0..4 {
+                self.b[i] = bz[i] * a0_recip;
+                self.a[i] = az[i] * a0_recip;
+            }
+        }
+    }
+
+    impl FrequencyResponse for ToneStack {
+        /// The response for the current, possibly still moving, knob positions.
+        fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+            response::transfer_function_response(&self.b, &self.a, frequency, sample_rate)
+        }
+    }
+}
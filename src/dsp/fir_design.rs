@@ -0,0 +1,655 @@
+//! Optimal linear-phase FIR design for multi-band specifications. [`design_parks_mcclellan()`]
+//! minimizes the maximum weighted error (equiripple) using the Remez exchange algorithm, and
+//! [`design_least_squares()`] minimizes the integrated squared weighted error. Both produce
+//! symmetric impulse responses, so odd lengths result in type I filters and even lengths result in
+//! type II filters. The latter always have a zero at the Nyquist frequency.
+
+use std::f64::consts::PI;
+use std::fmt;
+
+/// The longest filter either design method will produce.
+pub const MAX_NUM_TAPS: usize = 4096;
+/// The number of grid points per extremal frequency (or per basis function for the least-squares
+/// design) used to sample the bands.
+const GRID_DENSITY: usize = 16;
+/// The Remez exchange gives up after this many iterations.
+const MAX_REMEZ_ITERATIONS: usize = 100;
+/// The Remez exchange has converged when the maximum error on the grid is within this fraction of
+/// the deviation at the extremal frequencies.
+const REMEZ_TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FirDesignError {
+    /// The number of taps is not supported by the design method. The optimal designs need between
+    /// 3 and `MAX_NUM_TAPS` taps, and the windowed sinc needs an odd number of taps.
+    InvalidNumTaps(usize),
+    /// The specification did not contain any bands.
+    NoBands,
+    /// A band's edges are not in `[0, sample_rate / 2]`, are not in ascending order, or overlap
+    /// with the previous band.
+    InvalidBand(usize),
+    /// A band's weight is not positive, or its gain is not finite.
+    InvalidWeight(usize),
+    /// Even length filters cannot have a nonzero gain at the Nyquist frequency.
+    NonzeroNyquistGain,
+    /// The Remez exchange did not converge. This happens when the filter is far too short for the
+    /// transition bands, or when it's so long that the optimal error is below the numerical
+    /// precision of the design.
+    NotConverged,
+    /// The least-squares system could not be solved, which usually means that the bands cover too
+    /// little of the spectrum for the filter length.
+    SingularSystem,
+    /// No filter up to `MAX_NUM_TAPS` taps meets the ripple and attenuation targets.
+    SpecificationNotMet,
+    /// A cutoff frequency was not in `(0, sample_rate / 2]`.
+    InvalidCutoff(f32),
+}
+
+impl fmt::Display for FirDesignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FirDesignError::InvalidNumTaps(num_taps) => {
+                write!(f, "{num_taps} taps is not supported by this design method")
+            }
+            FirDesignError::NoBands => {
+                write!(f, "The filter specification does not contain any bands")
+            }
+            FirDesignError::InvalidBand(band_idx) => write!(
+                f,
+                "Band {band_idx} is out of range, reversed, or overlaps with the previous band"
+            ),
+            FirDesignError::InvalidWeight(band_idx) => {
+                write!(f, "Band {band_idx} has an invalid gain or weight")
+            }
+            FirDesignError::NonzeroNyquistGain => write!(
+                f,
+                "Even length filters cannot have a nonzero gain at the Nyquist frequency"
+            ),
+            FirDesignError::NotConverged => write!(f, "The Remez exchange did not converge"),
+            FirDesignError::SingularSystem => {
+                write!(f, "The least-squares system could not be solved")
+            }
+            FirDesignError::SpecificationNotMet => write!(
+                f,
+                "No filter up to {MAX_NUM_TAPS} taps meets the ripple and attenuation targets"
+            ),
+            FirDesignError::InvalidCutoff(frequency) => {
+                write!(f, "{frequency} Hz is not a valid cutoff frequency")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FirDesignError {}
+
+/// A single band in a multi-band filter specification. The gain is the desired linear amplitude
+/// over the entire band, and the weight scales the approximation error within the band relative
+/// to the other bands. Frequencies between the bands are transition bands and are left
+/// unconstrained.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FirBand {
+    pub start_frequency: f32,
+    pub end_frequency: f32,
+    pub gain: f32,
+    pub weight: f32,
+}
+
+impl FirBand {
+    /// A band from `start_frequency` to `end_frequency` Hz with a weight of one.
+    pub fn new(start_frequency: f32, end_frequency: f32, gain: f32) -> Self {
+        Self {
+            start_frequency,
+            end_frequency,
+            gain,
+            weight: 1.0,
+        }
+    }
+
+    pub fn with_weight(self, weight: f32) -> Self {
+        Self { weight, ..self }
+    }
+}
+
+/// The maximum deviation from unity gain for a peak-to-peak passband ripple in decibels.
+pub fn passband_deviation(ripple_db: f32) -> f32 {
+    let ripple = 10.0f32.powf(ripple_db / 20.0);
+    (ripple - 1.0) / (ripple + 1.0)
+}
+
+/// The maximum stopband gain for a stopband attenuation in decibels.
+pub fn stopband_deviation(attenuation_db: f32) -> f32 {
+    10.0f32.powf(-attenuation_db / 20.0)
+}
+
+/// Kaiser's estimate for the number of taps an equiripple low-pass or high-pass filter needs to
+/// meet the ripple and attenuation targets with a transition band `transition_width` Hz wide.
+pub fn estimate_num_taps(
+    sample_rate: f32,
+    transition_width: f32,
+    passband_ripple_db: f32,
+    stopband_attenuation_db: f32,
+) -> usize {
+    let deviation = (passband_deviation(passband_ripple_db)
+        * stopband_deviation(stopband_attenuation_db))
+    .sqrt();
+    let normalized_width = transition_width / sample_rate;
+    let num_taps = (-20.0 * deviation.log10() - 13.0) / (14.6 * normalized_width) + 1.0;
+
+    (num_taps.ceil().max(3.0) as usize).min(MAX_NUM_TAPS)
+}
+
+/// Design the equiripple filter with `num_taps` taps that minimizes the maximum weighted error
+/// over `bands` using the Parks-McClellan algorithm.
+pub fn design_parks_mcclellan(
+    num_taps: usize,
+    sample_rate: f32,
+    bands: &[FirBand],
+) -> Result<Vec<f32>, FirDesignError> {
+    remez(num_taps, sample_rate, bands).map(|(coefficients, _)| coefficients)
+}
+
+/// Design the shortest equiripple low-pass filter with at most `passband_ripple_db` dB of
+/// peak-to-peak ripple up to `passband_edge` Hz, and at least `stopband_attenuation_db` dB of
+/// attenuation from `stopband_edge` Hz onwards. The length is estimated first and then increased
+/// until the targets are met.
+pub fn design_low_pass_to_spec(
+    sample_rate: f32,
+    passband_edge: f32,
+    stopband_edge: f32,
+    passband_ripple_db: f32,
+    stopband_attenuation_db: f32,
+) -> Result<Vec<f32>, FirDesignError> {
+    let passband_deviation = passband_deviation(passband_ripple_db);
+    let stopband_deviation = stopband_deviation(stopband_attenuation_db);
+    // With these weights, the weighted error equals the error in the passband and the target
+    // deviation is the same for both bands
+    let bands = [
+        FirBand::new(0.0, passband_edge, 1.0),
+        FirBand::new(stopband_edge, sample_rate / 2.0, 0.0)
+            .with_weight(passband_deviation / stopband_deviation),
+    ];
+
+    let mut num_taps = estimate_num_taps(
+        sample_rate,
+        stopband_edge - passband_edge,
+        passband_ripple_db,
+        stopband_attenuation_db,
+    );
+    // Odd lengths are used so the group delay is an integer number of samples
+    if num_taps.is_multiple_of(2) {
+        num_taps += 1;
+    }
+    while num_taps <= MAX_NUM_TAPS {
+        match remez(num_taps, sample_rate, &bands) {
+            Ok((coefficients, deviation)) if deviation <= passband_deviation => {
+                return Ok(coefficients)
+            }
+            Ok(_) | Err(FirDesignError::NotConverged) => num_taps += 2,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(FirDesignError::SpecificationNotMet)
+}
+
+/// Design the filter with `num_taps` taps that minimizes the integrated squared weighted error
+/// over `bands`. Compared to an equiripple design of the same length this has slightly larger
+/// peak errors, but much less energy in the stopbands.
+pub fn design_least_squares(
+    num_taps: usize,
+    sample_rate: f32,
+    bands: &[FirBand],
+) -> Result<Vec<f32>, FirDesignError> {
+    validate(num_taps, sample_rate, bands)?;
+
+    // The amplitude response is `sum_k a[k] * cos((k + offset) * omega)`, with an offset of 0.5 for
+    // type II filters
+    let is_type_2 = num_taps.is_multiple_of(2);
+    let num_basis_functions = num_taps.div_ceil(2);
+    let offset = if is_type_2 { 0.5 } else { 0.0 };
+    let grid = Grid::new(sample_rate, bands, num_basis_functions, false);
+
+    // The integrals of the products of two basis functions only depend on the sum and the
+    // difference of their indices, so they can be computed from a single set of cosine sums
+    let squared_weights: Vec<f64> = grid
+        .weights
+        .iter()
+        .zip(&grid.spacings)
+        .map(|(weight, spacing)| weight * weight * spacing)
+        .collect();
+    let cosine_sums: Vec<f64> = (0..2 * num_basis_functions)
+        .map(|m| {
+            grid.frequencies
+                .iter()
+                .zip(&squared_weights)
+                .map(|(omega, weight)| weight * (m as f64 * omega).cos())
+                .sum()
+        })
+        .collect();
+
+    let n = num_basis_functions;
+    let mut matrix = vec![0.0; n * n];
+    let mut rhs = vec![0.0; n];
+    for k in 0..n {
+        for l in 0..n {
+            let sum_idx = k + l + usize::from(is_type_2);
+            matrix[k * n + l] = 0.5 * (cosine_sums[k.abs_diff(l)] + cosine_sums[sum_idx]);
+        }
+        rhs[k] = grid
+            .frequencies
+            .iter()
+            .zip(&grid.desired)
+            .zip(&squared_weights)
+            .map(|((omega, desired), weight)| {
+                weight * desired * ((k as f64 + offset) * omega).cos()
+            })
+            .sum();
+    }
+    solve_cholesky(&mut matrix, &mut rhs, n)?;
+    let amplitudes = rhs;
+
+    // Each cosine term corresponds to a symmetric pair of taps, or to the center tap
+    let mut coefficients = vec![0.0; num_taps];
+    let center = num_taps / 2;
+    for (k, amplitude) in amplitudes.iter().enumerate() {
+        if is_type_2 {
+            coefficients[center + k] = (amplitude * 0.5) as f32;
+            coefficients[center - 1 - k] = (amplitude * 0.5) as f32;
+        } else if k == 0 {
+            coefficients[center] = *amplitude as f32;
+        } else {
+            coefficients[center + k] = (amplitude * 0.5) as f32;
+            coefficients[center - k] = (amplitude * 0.5) as f32;
+        }
+    }
+
+    Ok(coefficients)
+}
+
+fn validate(num_taps: usize, sample_rate: f32, bands: &[FirBand]) -> Result<(), FirDesignError> {
+    if !(3..=MAX_NUM_TAPS).contains(&num_taps) {
+        return Err(FirDesignError::InvalidNumTaps(num_taps));
+    }
+    if bands.is_empty() {
+        return Err(FirDesignError::NoBands);
+    }
+
+    let nyquist = sample_rate / 2.0;
+    let mut previous_end = 0.0;
+    for (band_idx, band) in bands.iter().enumerate() {
+        if !(band.start_frequency >= previous_end
+            && band.start_frequency < band.end_frequency
+            && band.end_frequency <= nyquist)
+        {
+            return Err(FirDesignError::InvalidBand(band_idx));
+        }
+        if !(band.weight > 0.0 && band.weight.is_finite() && band.gain.is_finite()) {
+            return Err(FirDesignError::InvalidWeight(band_idx));
+        }
+        if num_taps.is_multiple_of(2) && band.end_frequency == nyquist && band.gain != 0.0 {
+            return Err(FirDesignError::NonzeroNyquistGain);
+        }
+
+        previous_end = band.end_frequency;
+    }
+
+    Ok(())
+}
+
+/// The bands sampled on a dense frequency grid, in radians per sample.
+struct Grid {
+    frequencies: Vec<f64>,
+    desired: Vec<f64>,
+    weights: Vec<f64>,
+    /// The distance between the grid points in each point's band.
+    spacings: Vec<f64>,
+    /// The index range of each band within the grid.
+    band_ranges: Vec<std::ops::Range<usize>>,
+}
+
+impl Grid {
+    /// Sample the bands with `GRID_DENSITY` points per basis function. If `avoid_nyquist` is set,
+    /// the grid stops just short of the Nyquist frequency.
+    fn new(
+        sample_rate: f32,
+        bands: &[FirBand],
+        num_basis_functions: usize,
+        avoid_nyquist: bool,
+    ) -> Self {
+        let target_spacing = PI / (GRID_DENSITY * num_basis_functions) as f64;
+        let mut grid = Grid {
+            frequencies: Vec::new(),
+            desired: Vec::new(),
+            weights: Vec::new(),
+            spacings: Vec::new(),
+            band_ranges: Vec::with_capacity(bands.len()),
+        };
+
+        for band in bands {
+            let start = 2.0 * PI * band.start_frequency as f64 / sample_rate as f64;
+            let mut end = 2.0 * PI * band.end_frequency as f64 / sample_rate as f64;
+            if avoid_nyquist {
+                end = end.min(PI - target_spacing);
+            }
+            let num_points = (((end - start) / target_spacing).ceil() as usize + 1).max(2);
+            let spacing = (end - start) / (num_points - 1) as f64;
+
+            let band_start = grid.frequencies.len();
+            for idx in 0..num_points {
+                grid.frequencies.push(start + idx as f64 * spacing);
+                grid.desired.push(band.gain as f64);
+                grid.weights.push(band.weight as f64);
+                grid.spacings.push(spacing);
+            }
+            grid.band_ranges.push(band_start..grid.frequencies.len());
+        }
+
+        grid
+    }
+}
+
+/// The Parks-McClellan design, also returning the achieved maximum weighted deviation.
+fn remez(
+    num_taps: usize,
+    sample_rate: f32,
+    bands: &[FirBand],
+) -> Result<(Vec<f32>, f32), FirDesignError> {
+    validate(num_taps, sample_rate, bands)?;
+
+    // Type II filters are written as `cos(omega / 2) * P(omega)`, where `P` is again a cosine
+    // polynomial. The `cos(omega / 2)` factor is folded into the desired response and the weights
+    // so the exchange only ever deals with cosine polynomials.
+    let is_type_2 = num_taps.is_multiple_of(2);
+    let num_basis_functions = num_taps.div_ceil(2);
+    let mut grid = Grid::new(sample_rate, bands, num_basis_functions, is_type_2);
+    if is_type_2 {
+        for ((omega, desired), weight) in grid
+            .frequencies
+            .iter()
+            .zip(grid.desired.iter_mut())
+            .zip(grid.weights.iter_mut())
+        {
+            let factor = (omega / 2.0).cos();
+            *desired /= factor;
+            *weight *= factor;
+        }
+    }
+    let num_extrema = num_basis_functions + 1;
+    if grid.frequencies.len() < num_extrema {
+        return Err(FirDesignError::NotConverged);
+    }
+
+    // Start with the extremal frequencies spread out evenly over the grid
+    let last_idx = grid.frequencies.len() - 1;
+    let mut extrema: Vec<usize> = (0..num_extrema)
+        .map(|idx| idx * last_idx / (num_extrema - 1))
+        .collect();
+    let mut error = vec![0.0; grid.frequencies.len()];
+    let mut interpolator = Interpolator::default();
+
+    for _ in 0..MAX_REMEZ_ITERATIONS {
+        interpolator.update(&grid, &extrema);
+        for (idx, error) in error.iter_mut().enumerate() {
+            let amplitude = interpolator.evaluate(grid.frequencies[idx].cos());
+            *error = grid.weights[idx] * (grid.desired[idx] - amplitude);
+        }
+
+        let deviation = interpolator.deviation.abs();
+        let max_error = error.iter().fold(0.0f64, |max, error| max.max(error.abs()));
+        if max_error - deviation <= REMEZ_TOLERANCE * max_error {
+            let coefficients = impulse_response(&interpolator, num_taps);
+            return Ok((coefficients, max_error as f32));
+        }
+
+        extrema = find_extrema(&error, &grid.band_ranges, deviation, num_extrema)
+            .ok_or(FirDesignError::NotConverged)?;
+    }
+
+    Err(FirDesignError::NotConverged)
+}
+
+/// Evaluates the cosine polynomial passing through the extremal points using barycentric Lagrange
+/// interpolation in `x = cos(omega)`.
+#[derive(Default)]
+struct Interpolator {
+    nodes: Vec<f64>,
+    barycentric_weights: Vec<f64>,
+    values: Vec<f64>,
+    /// The signed weighted deviation at the extremal points.
+    deviation: f64,
+}
+
+impl Interpolator {
+    fn update(&mut self, grid: &Grid, extrema: &[usize]) {
+        self.nodes.clear();
+        self.nodes
+            .extend(extrema.iter().map(|&idx| grid.frequencies[idx].cos()));
+
+        // The factor of two keeps the products from under- or overflowing for long filters. It
+        // cancels out in both the deviation and the interpolation.
+        self.barycentric_weights.clear();
+        self.barycentric_weights
+            .extend(self.nodes.iter().enumerate().map(|(k, x_k)| {
+                let product: f64 = self
+                    .nodes
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != k)
+                    .map(|(_, x_i)| 2.0 * (x_k - x_i))
+                    .product();
+                1.0 / product
+            }));
+
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        let mut sign = 1.0;
+        for (&idx, gamma) in extrema.iter().zip(&self.barycentric_weights) {
+            numerator += gamma * grid.desired[idx];
+            denominator += sign * gamma / grid.weights[idx];
+            sign = -sign;
+        }
+        self.deviation = numerator / denominator;
+
+        self.values.clear();
+        let mut sign = 1.0;
+        for &idx in extrema {
+            self.values
+                .push(grid.desired[idx] - sign * self.deviation / grid.weights[idx]);
+            sign = -sign;
+        }
+    }
+
+    fn evaluate(&self, x: f64) -> f64 {
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        for ((node, gamma), value) in self
+            .nodes
+            .iter()
+            .zip(&self.barycentric_weights)
+            .zip(&self.values)
+        {
+            let difference = x - node;
+            if difference.abs() < 1e-14 {
+                return *value;
+            }
+
+            let term = gamma / difference;
+            numerator += term * value;
+            denominator += term;
+        }
+
+        numerator / denominator
+    }
+}
+
+/// Find the new set of `num_extrema` alternating extremal points of the weighted error. Returns
+/// `None` if there are not enough of them.
+fn find_extrema(
+    error: &[f64],
+    band_ranges: &[std::ops::Range<usize>],
+    deviation: f64,
+    num_extrema: usize,
+) -> Option<Vec<usize>> {
+    // Only extrema at least as large as the current deviation are considered. When the deviation
+    // is close to the numerical precision, rounding errors can hide some of those, so the search
+    // is then repeated without the threshold.
+    let mut extrema = alternating_extrema(error, band_ranges, deviation * (1.0 - 1e-9));
+    if extrema.len() < num_extrema {
+        extrema = alternating_extrema(error, band_ranges, 0.0);
+    }
+    if extrema.len() < num_extrema {
+        return None;
+    }
+
+    // Removing an extremum from the middle would leave two neighbours with the same sign, so the
+    // smaller of those is removed along with it. A single surplus extremum is removed from
+    // whichever end has the smaller error.
+    while extrema.len() > num_extrema {
+        if extrema.len() == num_extrema + 1 {
+            if error[extrema[0]].abs() < error[extrema[extrema.len() - 1]].abs() {
+                extrema.remove(0);
+            } else {
+                extrema.pop();
+            }
+            break;
+        }
+
+        let smallest_idx = (0..extrema.len())
+            .min_by(|&a, &b| error[extrema[a]].abs().total_cmp(&error[extrema[b]].abs()))
+            .unwrap();
+        if smallest_idx == 0 || smallest_idx == extrema.len() - 1 {
+            extrema.remove(smallest_idx);
+        } else {
+            let previous = error[extrema[smallest_idx - 1]].abs();
+            let next = error[extrema[smallest_idx + 1]].abs();
+            let neighbour_idx = if previous < next {
+                smallest_idx - 1
+            } else {
+                smallest_idx + 1
+            };
+            extrema.remove(smallest_idx.max(neighbour_idx));
+            extrema.remove(smallest_idx.min(neighbour_idx));
+        }
+    }
+
+    Some(extrema)
+}
+
+/// The local extrema of the error within each band, including the band edges, that are at least
+/// `threshold` large. Of each run of extrema with the same sign only the largest one is kept, so
+/// the signs alternate.
+fn alternating_extrema(
+    error: &[f64],
+    band_ranges: &[std::ops::Range<usize>],
+    threshold: f64,
+) -> Vec<usize> {
+    let mut extrema: Vec<usize> = Vec::new();
+    for range in band_ranges {
+        for idx in range.clone() {
+            let value = error[idx];
+            let previous = if idx > range.start {
+                error[idx - 1]
+            } else {
+                value
+            };
+            let next = if idx + 1 < range.end {
+                error[idx + 1]
+            } else {
+                value
+            };
+            let is_extremum = (value > 0.0 && value >= previous && value >= next)
+                || (value < 0.0 && value <= previous && value <= next);
+            if !is_extremum || value.abs() < threshold {
+                continue;
+            }
+
+            match extrema.last_mut() {
+                Some(last) if error[*last].signum() == value.signum() => {
+                    if value.abs() > error[*last].abs() {
+                        *last = idx;
+                    }
+                }
+                _ => extrema.push(idx),
+            }
+        }
+    }
+
+    extrema
+}
+
+/// Convert the amplitude response to a symmetric impulse response by sampling it at `num_taps`
+/// evenly spaced frequencies and taking the inverse DFT. Since the amplitude response is a cosine
+/// polynomial of the right degree, this is exact.
+fn impulse_response(interpolator: &Interpolator, num_taps: usize) -> Vec<f32> {
+    let is_type_2 = num_taps.is_multiple_of(2);
+    let amplitudes: Vec<f64> = (0..num_taps.div_ceil(2))
+        .map(|idx| {
+            let omega = 2.0 * PI * idx as f64 / num_taps as f64;
+            let amplitude = interpolator.evaluate(omega.cos());
+            if is_type_2 {
+                amplitude * (omega / 2.0).cos()
+            } else {
+                amplitude
+            }
+        })
+        .collect();
+
+    let center = (num_taps - 1) as f64 / 2.0;
+    (0..num_taps)
+        .map(|tap_idx| {
+            let offset = tap_idx as f64 - center;
+            let sum: f64 = amplitudes
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(idx, amplitude)| {
+                    let omega = 2.0 * PI * idx as f64 / num_taps as f64;
+                    2.0 * amplitude * (omega * offset).cos()
+                })
+                .sum();
+
+            ((amplitudes[0] + sum) / num_taps as f64) as f32
+        })
+        .collect()
+}
+
+/// Solve the symmetric positive definite `n` by `n` system in `matrix` using a Cholesky
+/// decomposition. The solution is written to `rhs`, and `matrix` is overwritten with the
+/// decomposition.
+fn solve_cholesky(matrix: &mut [f64], rhs: &mut [f64], n: usize) -> Result<(), FirDesignError> {
+    for col in 0..n {
+        for row in col..n {
+            let mut sum = matrix[row * n + col];
+            for k in 0..col {
+                sum -= matrix[row * n + k] * matrix[col * n + k];
+            }
+
+            if row == col {
+                if sum <= 0.0 || !sum.is_finite() {
+                    return Err(FirDesignError::SingularSystem);
+                }
+                matrix[col * n + col] = sum.sqrt();
+            } else {
+                matrix[row * n + col] = sum / matrix[col * n + col];
+            }
+        }
+    }
+
+    // Forward substitution with `L`, and then back substitution with `L^T`
+    for row in 0..n {
+        let mut sum = rhs[row];
+        for k in 0..row {
+            sum -= matrix[row * n + k] * rhs[k];
+        }
+        rhs[row] = sum / matrix[row * n + row];
+    }
+    for row in (0..n).rev() {
+        let mut sum = rhs[row];
+        for k in row + 1..n {
+            sum -= matrix[k * n + row] * rhs[k];
+        }
+        rhs[row] = sum / matrix[row * n + row];
+    }
+
+    Ok(())
+}
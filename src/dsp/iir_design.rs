@@ -0,0 +1,638 @@
+//! Classic IIR filter design from analog prototypes. The normalized analog low-pass prototype is
+//! first transformed to the requested low-pass, high-pass, band-pass or band-stop response at the
+//! prewarped frequencies, then discretized with the bilinear transform, and finally split into
+//! second-order sections that can be run with a
+//! [`BiquadCascade`][super::crossover::iir::biquad::BiquadCascade]. Everything is computed in
+//! double precision using zeros, poles and a gain, which keeps high orders numerically stable.
+
+use realfft::num_complex::Complex64;
+use std::f64::consts::PI;
+use std::fmt;
+
+use super::crossover::iir::biquad::BiquadCoefficients;
+
+/// Roots with an imaginary part smaller than this are considered to be real.
+const REAL_ROOT_TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IirDesignError {
+    /// The order needs to be at least one.
+    InvalidOrder(usize),
+    /// A frequency was not in `(0, sample_rate / 2)`, or a band's edges were reversed.
+    InvalidFrequency(f32),
+    /// The passband ripple needs to be positive, and the stopband attenuation needs to be larger
+    /// than the passband ripple.
+    InvalidRipple,
+}
+
+impl fmt::Display for IirDesignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IirDesignError::InvalidOrder(order) => {
+                write!(f, "{order} is not a valid filter order")
+            }
+            IirDesignError::InvalidFrequency(frequency) => {
+                write!(f, "{frequency} Hz is not a valid frequency for this filter")
+            }
+            IirDesignError::InvalidRipple => write!(
+                f,
+                "The passband ripple and stopband attenuation need to be positive, with the \
+                 attenuation larger than the ripple"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IirDesignError {}
+
+/// The analog low-pass prototype a filter is derived from. This also determines what the filter's
+/// frequency means: the -3 dB point for Butterworth and Bessel filters, the end of the passband
+/// ripple for Chebyshev type I and elliptic filters, and the start of the stopband for Chebyshev
+/// type II filters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IirPrototype {
+    /// Maximally flat passband.
+    Butterworth,
+    /// Equiripple passband with the given peak-to-peak ripple, monotonic stopband.
+    ChebyshevI { passband_ripple_db: f32 },
+    /// Monotonic passband, equiripple stopband with at least the given attenuation.
+    ChebyshevII { stopband_attenuation_db: f32 },
+    /// Equiripple passband and stopband, giving the steepest transition for a given order.
+    Elliptic {
+        passband_ripple_db: f32,
+        stopband_attenuation_db: f32,
+    },
+    /// Maximally flat group delay in the passband. Since the bilinear transform warps the
+    /// frequency axis, this only holds well below the Nyquist frequency.
+    Bessel,
+}
+
+/// The response type and frequencies in Hz. Band-pass and band-stop filters have twice the order
+/// of the prototype they're derived from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IirResponse {
+    LowPass {
+        frequency: f32,
+    },
+    HighPass {
+        frequency: f32,
+    },
+    BandPass {
+        low_frequency: f32,
+        high_frequency: f32,
+    },
+    BandStop {
+        low_frequency: f32,
+        high_frequency: f32,
+    },
+}
+
+/// Design a filter of the given prototype order and return it as a cascade of second-order
+/// sections. Odd orders result in one first-order section with `b2` and `a2` set to zero.
+pub fn design_iir(
+    prototype: IirPrototype,
+    response: IirResponse,
+    order: usize,
+    sample_rate: f32,
+) -> Result<Vec<BiquadCoefficients<f32>>, IirDesignError> {
+    if order == 0 {
+        return Err(IirDesignError::InvalidOrder(order));
+    }
+
+    let sample_rate = sample_rate as f64;
+    let prewarp = |frequency: f32| -> Result<f64, IirDesignError> {
+        if !(frequency > 0.0 && (frequency as f64) < sample_rate / 2.0) {
+            return Err(IirDesignError::InvalidFrequency(frequency));
+        }
+
+        Ok(2.0 * sample_rate * (PI * frequency as f64 / sample_rate).tan())
+    };
+    let band_edges = |low_frequency: f32, high_frequency: f32| {
+        if low_frequency >= high_frequency {
+            return Err(IirDesignError::InvalidFrequency(low_frequency));
+        }
+
+        let low = prewarp(low_frequency)?;
+        let high = prewarp(high_frequency)?;
+        Ok(((low * high).sqrt(), high - low))
+    };
+
+    let prototype = analog_prototype(prototype, order)?;
+    let analog = match response {
+        IirResponse::LowPass { frequency } => prototype.to_low_pass(prewarp(frequency)?),
+        IirResponse::HighPass { frequency } => prototype.to_high_pass(prewarp(frequency)?),
+        IirResponse::BandPass {
+            low_frequency,
+            high_frequency,
+        } => {
+            let (center, bandwidth) = band_edges(low_frequency, high_frequency)?;
+            prototype.to_band_pass(center, bandwidth)
+        }
+        IirResponse::BandStop {
+            low_frequency,
+            high_frequency,
+        } => {
+            let (center, bandwidth) = band_edges(low_frequency, high_frequency)?;
+            prototype.to_band_stop(center, bandwidth)
+        }
+    };
+
+    Ok(analog.bilinear(sample_rate).to_sections())
+}
+
+/// A transfer function described by its zeros, poles and gain.
+#[derive(Debug, Clone)]
+struct Zpk {
+    zeros: Vec<Complex64>,
+    poles: Vec<Complex64>,
+    gain: f64,
+}
+
+/// The normalized analog low-pass prototype with a cutoff of 1 rad/s.
+fn analog_prototype(prototype: IirPrototype, order: usize) -> Result<Zpk, IirDesignError> {
+    let n = order as f64;
+    let zpk = match prototype {
+        IirPrototype::Butterworth => {
+            let poles = (0..order)
+                .map(|k| Complex64::from_polar(1.0, PI * (2 * k + order + 1) as f64 / (2.0 * n)))
+                .collect();
+
+            Zpk {
+                zeros: Vec::new(),
+                poles,
+                gain: 1.0,
+            }
+        }
+        IirPrototype::ChebyshevI { passband_ripple_db } => {
+            if !(passband_ripple_db > 0.0 && passband_ripple_db.is_finite()) {
+                return Err(IirDesignError::InvalidRipple);
+            }
+
+            let epsilon = (10.0f64.powf(passband_ripple_db as f64 / 10.0) - 1.0).sqrt();
+            let mu = (1.0 / epsilon).asinh() / n;
+            let poles: Vec<Complex64> = (0..order)
+                .map(|k| {
+                    let theta = PI * (2 * k + 1) as f64 / (2.0 * n);
+                    Complex64::new(-mu.sinh() * theta.sin(), mu.cosh() * theta.cos())
+                })
+                .collect();
+
+            // Even orders start at the bottom of the ripple at DC
+            let mut gain = product(poles.iter().map(|pole| -pole)).re;
+            if order.is_multiple_of(2) {
+                gain /= (1.0 + epsilon * epsilon).sqrt();
+            }
+
+            Zpk {
+                zeros: Vec::new(),
+                poles,
+                gain,
+            }
+        }
+        IirPrototype::ChebyshevII {
+            stopband_attenuation_db,
+        } => {
+            if !(stopband_attenuation_db > 0.0 && stopband_attenuation_db.is_finite()) {
+                return Err(IirDesignError::InvalidRipple);
+            }
+
+            let epsilon = 1.0 / (10.0f64.powf(stopband_attenuation_db as f64 / 10.0) - 1.0).sqrt();
+            let mu = (1.0 / epsilon).asinh() / n;
+            // The zeros lie on the imaginary axis. For odd orders the middle one would be at
+            // infinity, so it's left out.
+            let zeros = (0..order)
+                .filter(|k| 2 * k + 1 != order)
+                .map(|k| {
+                    let theta = PI * (2 * k + 1) as f64 / (2.0 * n);
+                    Complex64::new(0.0, 1.0 / theta.cos())
+                })
+                .collect::<Vec<_>>();
+            let poles = (0..order)
+                .map(|k| {
+                    let theta = PI * (2 * k + 1) as f64 / (2.0 * n);
+                    Complex64::new(-mu.sinh() * theta.sin(), mu.cosh() * theta.cos()).inv()
+                })
+                .collect::<Vec<_>>();
+            let gain = (product(poles.iter().map(|pole| -pole))
+                / product(zeros.iter().map(|zero| -zero)))
+            .re;
+
+            Zpk { zeros, poles, gain }
+        }
+        IirPrototype::Elliptic {
+            passband_ripple_db,
+            stopband_attenuation_db,
+        } => {
+            if !(passband_ripple_db > 0.0 && stopband_attenuation_db > passband_ripple_db) {
+                return Err(IirDesignError::InvalidRipple);
+            }
+
+            elliptic_prototype(
+                order,
+                passband_ripple_db as f64,
+                stopband_attenuation_db as f64,
+            )
+        }
+        IirPrototype::Bessel => bessel_prototype(order),
+    };
+
+    Ok(zpk)
+}
+
+impl Zpk {
+    /// The number of zeros at infinity.
+    fn relative_degree(&self) -> usize {
+        self.poles.len() - self.zeros.len()
+    }
+
+    fn to_low_pass(&self, frequency: f64) -> Zpk {
+        Zpk {
+            zeros: self.zeros.iter().map(|zero| zero * frequency).collect(),
+            poles: self.poles.iter().map(|pole| pole * frequency).collect(),
+            gain: self.gain * frequency.powi(self.relative_degree() as i32),
+        }
+    }
+
+    fn to_high_pass(&self, frequency: f64) -> Zpk {
+        // The zeros at infinity end up at DC
+        let mut zeros: Vec<Complex64> = self.zeros.iter().map(|zero| frequency / zero).collect();
+        zeros.resize(self.poles.len(), Complex64::new(0.0, 0.0));
+
+        Zpk {
+            zeros,
+            poles: self.poles.iter().map(|pole| frequency / pole).collect(),
+            gain: self.gain
+                * (product(self.zeros.iter().map(|zero| -zero))
+                    / product(self.poles.iter().map(|pole| -pole)))
+                .re,
+        }
+    }
+
+    fn to_band_pass(&self, center: f64, bandwidth: f64) -> Zpk {
+        let transform = |roots: &[Complex64]| -> Vec<Complex64> {
+            let scaled: Vec<Complex64> = roots.iter().map(|root| root * bandwidth / 2.0).collect();
+            let offsets: Vec<Complex64> = scaled
+                .iter()
+                .map(|root| (root * root - center * center).sqrt())
+                .collect();
+
+            scaled
+                .iter()
+                .zip(&offsets)
+                .map(|(root, offset)| root + offset)
+                .chain(
+                    scaled
+                        .iter()
+                        .zip(&offsets)
+                        .map(|(root, offset)| root - offset),
+                )
+                .collect()
+        };
+
+        // Half of the zeros at infinity end up at DC
+        let mut zeros = transform(&self.zeros);
+        zeros.resize(
+            zeros.len() + self.relative_degree(),
+            Complex64::new(0.0, 0.0),
+        );
+
+        Zpk {
+            zeros,
+            poles: transform(&self.poles),
+            gain: self.gain * bandwidth.powi(self.relative_degree() as i32),
+        }
+    }
+
+    fn to_band_stop(&self, center: f64, bandwidth: f64) -> Zpk {
+        let transform = |roots: &[Complex64]| -> Vec<Complex64> {
+            let scaled: Vec<Complex64> = roots.iter().map(|root| bandwidth / 2.0 / root).collect();
+            let offsets: Vec<Complex64> = scaled
+                .iter()
+                .map(|root| (root * root - center * center).sqrt())
+                .collect();
+
+            scaled
+                .iter()
+                .zip(&offsets)
+                .map(|(root, offset)| root + offset)
+                .chain(
+                    scaled
+                        .iter()
+                        .zip(&offsets)
+                        .map(|(root, offset)| root - offset),
+                )
+                .collect()
+        };
+
+        // The zeros at infinity end up at the center frequency
+        let mut zeros = transform(&self.zeros);
+        for _ in 0..self.relative_degree() {
+            zeros.push(Complex64::new(0.0, center));
+            zeros.push(Complex64::new(0.0, -center));
+        }
+
+        Zpk {
+            zeros,
+            poles: transform(&self.poles),
+            gain: self.gain
+                * (product(self.zeros.iter().map(|zero| -zero))
+                    / product(self.poles.iter().map(|pole| -pole)))
+                .re,
+        }
+    }
+
+    /// Discretize the analog filter with the bilinear transform. The frequencies should already
+    /// have been prewarped.
+    fn bilinear(&self, sample_rate: f64) -> Zpk {
+        let fs2 = 2.0 * sample_rate;
+
+        // The zeros at infinity end up at the Nyquist frequency
+        let mut zeros: Vec<Complex64> = self
+            .zeros
+            .iter()
+            .map(|zero| (fs2 + zero) / (fs2 - zero))
+            .collect();
+        zeros.resize(self.poles.len(), Complex64::new(-1.0, 0.0));
+
+        Zpk {
+            zeros,
+            poles: self
+                .poles
+                .iter()
+                .map(|pole| (fs2 + pole) / (fs2 - pole))
+                .collect(),
+            gain: self.gain
+                * (product(self.zeros.iter().map(|zero| fs2 - zero))
+                    / product(self.poles.iter().map(|pole| fs2 - pole)))
+                .re,
+        }
+    }
+
+    /// Split a digital filter into second-order sections. Poles are paired with their nearest
+    /// zeros, and the sections are ordered so that the poles closest to the unit circle come last.
+    /// This keeps the internal gain of the cascade reasonable.
+    fn to_sections(&self) -> Vec<BiquadCoefficients<f32>> {
+        let mut pole_groups = group_roots(&self.poles);
+        let mut zero_groups = group_roots(&self.zeros);
+        pole_groups.sort_by(|a, b| {
+            let radius =
+                |group: &Vec<Complex64>| group.iter().map(|root| root.norm()).fold(0.0, f64::max);
+            radius(b).total_cmp(&radius(a))
+        });
+
+        let mut sections = Vec::with_capacity(pole_groups.len());
+        for poles in pole_groups {
+            // Prefer zero groups of the same order so first-order sections stay first-order
+            let distance = |zeros: &Vec<Complex64>| {
+                let order_penalty = if zeros.len() == poles.len() {
+                    0.0
+                } else {
+                    f64::INFINITY
+                };
+                zeros
+                    .iter()
+                    .flat_map(|zero| poles.iter().map(move |pole| (zero - pole).norm()))
+                    .fold(f64::INFINITY, f64::min)
+                    + order_penalty
+            };
+            let zeros = (0..zero_groups.len())
+                .min_by(|&a, &b| distance(&zero_groups[a]).total_cmp(&distance(&zero_groups[b])))
+                .map(|idx| zero_groups.swap_remove(idx))
+                .unwrap_or_default();
+
+            let (b1, b2) = polynomial(&zeros);
+            let (a1, a2) = polynomial(&poles);
+            sections.push([1.0, b1, b2, a1, a2]);
+        }
+        sections.reverse();
+
+        if let Some(section) = sections.first_mut() {
+            for coefficient in &mut section[..3] {
+                *coefficient *= self.gain;
+            }
+        }
+
+        sections
+            .into_iter()
+            .map(|[b0, b1, b2, a1, a2]| {
+                BiquadCoefficients::from_f32s(b0 as f32, b1 as f32, b2 as f32, a1 as f32, a2 as f32)
+            })
+            .collect()
+    }
+}
+
+/// Split roots into complex conjugate pairs and pairs of real roots, with at most one real root
+/// left on its own.
+fn group_roots(roots: &[Complex64]) -> Vec<Vec<Complex64>> {
+    let mut groups: Vec<Vec<Complex64>> = roots
+        .iter()
+        .filter(|root| root.im > REAL_ROOT_TOLERANCE)
+        .map(|root| vec![*root, root.conj()])
+        .collect();
+
+    let mut real_roots: Vec<Complex64> = roots
+        .iter()
+        .filter(|root| root.im.abs() <= REAL_ROOT_TOLERANCE)
+        .map(|root| Complex64::new(root.re, 0.0))
+        .collect();
+    real_roots.sort_by(|a, b| a.re.total_cmp(&b.re));
+    groups.extend(real_roots.chunks(2).map(|chunk| chunk.to_vec()));
+
+    groups
+}
+
+/// The coefficients `[c1, c2]` of the monic polynomial `1 + c1 z^-1 + c2 z^-2` with the given
+/// (at most two) roots.
+fn polynomial(roots: &[Complex64]) -> (f64, f64) {
+    match roots {
+        [] => (0.0, 0.0),
+        [root] => (-root.re, 0.0),
+        [root1, root2] => (-(root1 + root2).re, (root1 * root2).re),
+        _ => unreachable!("Roots are grouped in pairs"),
+    }
+}
+
+fn product(values: impl Iterator<Item = Complex64>) -> Complex64 {
+    values.fold(Complex64::new(1.0, 0.0), |acc, value| acc * value)
+}
+
+/// The elliptic low-pass prototype with its passband edge at 1 rad/s, following Orfanidis,
+/// "Lecture Notes on Elliptic Filter Design". The Jacobi elliptic functions are evaluated with
+/// Landen transformations.
+fn elliptic_prototype(order: usize, passband_ripple_db: f64, stopband_attenuation_db: f64) -> Zpk {
+    let passband_epsilon = (10.0f64.powf(passband_ripple_db / 10.0) - 1.0).sqrt();
+    let stopband_epsilon = (10.0f64.powf(stopband_attenuation_db / 10.0) - 1.0).sqrt();
+    let k1 = passband_epsilon / stopband_epsilon;
+
+    // Solve the degree equation for the selectivity modulus
+    let num_pairs = order / 2;
+    let u: Vec<f64> = (1..=num_pairs)
+        .map(|i| (2 * i - 1) as f64 / order as f64)
+        .collect();
+    let k1_complement = (1.0 - k1 * k1).sqrt();
+    let k_complement = k1_complement.powi(order as i32)
+        * product(
+            u.iter()
+                .map(|&u| sne(Complex64::new(u, 0.0), k1_complement)),
+        )
+        .re
+        .powi(4);
+    let k = (1.0 - k_complement * k_complement).sqrt();
+
+    let v0 = -Complex64::i() * asne(Complex64::new(0.0, 1.0 / passband_epsilon), k1) / order as f64;
+
+    let mut zeros = Vec::with_capacity(num_pairs * 2);
+    let mut poles = Vec::with_capacity(order);
+    for &u in &u {
+        let zero = Complex64::i() / (k * cde(Complex64::new(u, 0.0), k));
+        let pole = Complex64::i() * cde(Complex64::new(u, 0.0) - Complex64::i() * v0, k);
+        zeros.extend([zero, zero.conj()]);
+        poles.extend([pole, pole.conj()]);
+    }
+    if !order.is_multiple_of(2) {
+        let pole = Complex64::i() * sne(Complex64::i() * v0, k);
+        poles.push(Complex64::new(pole.re, 0.0));
+    }
+
+    // Even orders start at the bottom of the passband ripple at DC
+    let mut gain =
+        (product(poles.iter().map(|pole| -pole)) / product(zeros.iter().map(|zero| -zero))).re;
+    if order.is_multiple_of(2) {
+        gain /= (1.0 + passband_epsilon * passband_epsilon).sqrt();
+    }
+
+    Zpk { zeros, poles, gain }
+}
+
+/// The descending sequence of moduli from repeated Landen transformations of `k`.
+fn landen(mut k: f64) -> Vec<f64> {
+    let mut moduli = Vec::new();
+    while k > 1e-15 && moduli.len() < 32 {
+        k = (k / (1.0 + (1.0 - k * k).sqrt())).powi(2);
+        moduli.push(k);
+    }
+
+    moduli
+}
+
+/// The Jacobi elliptic function `cd(u K, k)`, with `u` normalized to the quarter period.
+fn cde(u: Complex64, k: f64) -> Complex64 {
+    let mut w = (u * PI / 2.0).cos();
+    for modulus in landen(k).into_iter().rev() {
+        w = (1.0 + modulus) * w / (1.0 + modulus * w * w);
+    }
+
+    w
+}
+
+/// The Jacobi elliptic function `sn(u K, k)`, with `u` normalized to the quarter period.
+fn sne(u: Complex64, k: f64) -> Complex64 {
+    let mut w = (u * PI / 2.0).sin();
+    for modulus in landen(k).into_iter().rev() {
+        w = (1.0 + modulus) * w / (1.0 + modulus * w * w);
+    }
+
+    w
+}
+
+/// The inverse of [`sne()`].
+fn asne(mut w: Complex64, k: f64) -> Complex64 {
+    let mut previous_modulus = k;
+    for modulus in landen(k) {
+        w = w / (1.0 + (1.0 - w * w * previous_modulus * previous_modulus).sqrt()) * 2.0
+            / (1.0 + modulus);
+        previous_modulus = modulus;
+    }
+
+    Complex64::new(1.0, 0.0) - w.acos() * 2.0 / PI
+}
+
+/// The Bessel low-pass prototype, normalized so its -3 dB point is at 1 rad/s. The poles are the
+/// roots of the reverse Bessel polynomial.
+fn bessel_prototype(order: usize) -> Zpk {
+    // The coefficients of the reverse Bessel polynomial, from the highest power down. The
+    // polynomial is rescaled so the first and last coefficients are both one, which keeps the
+    // root finding well conditioned.
+    let mut coefficients = vec![1.0f64; order + 1];
+    for k in (1..=order).rev() {
+        coefficients[order - k + 1] = coefficients[order - k] * ((2 * order - k + 1) * k) as f64
+            / (2 * (order - k + 1)) as f64;
+    }
+    let scale = coefficients[order].powf(1.0 / order as f64);
+    for (power_idx, coefficient) in coefficients.iter_mut().enumerate() {
+        *coefficient /= scale.powi(power_idx as i32);
+    }
+
+    let mut poles: Vec<Complex64> = polynomial_roots(&coefficients)
+        .into_iter()
+        .map(|root| root * scale)
+        .collect();
+    for pole in &mut poles {
+        if pole.im.abs() <= REAL_ROOT_TOLERANCE {
+            pole.im = 0.0;
+        }
+    }
+
+    // Find the -3 dB point by bisection, and then move it to 1 rad/s
+    let magnitude_squared = |omega: f64| {
+        let response = product(poles.iter().map(|pole| -pole))
+            / product(poles.iter().map(|pole| Complex64::new(0.0, omega) - pole));
+        response.norm_sqr()
+    };
+    let (mut low, mut high) = (1e-3f64, 1e3f64);
+    for _ in 0..100 {
+        let mid = (low * high).sqrt();
+        if magnitude_squared(mid) > 0.5 {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    let cutoff = (low * high).sqrt();
+    for pole in &mut poles {
+        *pole /= cutoff;
+    }
+
+    Zpk {
+        zeros: Vec::new(),
+        gain: product(poles.iter().map(|pole| -pole)).re,
+        poles,
+    }
+}
+
+/// Find all roots of the monic polynomial with the given coefficients, ordered from the highest
+/// power down, using the Durand-Kerner method.
+fn polynomial_roots(coefficients: &[f64]) -> Vec<Complex64> {
+    let degree = coefficients.len() - 1;
+    let evaluate = |x: Complex64| {
+        coefficients
+            .iter()
+            .fold(Complex64::new(0.0, 0.0), |acc, coefficient| {
+                acc * x + coefficient
+            })
+    };
+
+    let seed = Complex64::new(0.4, 0.9);
+    let mut roots: Vec<Complex64> = (0..degree).map(|idx| seed.powi(idx as i32)).collect();
+    for _ in 0..1000 {
+        let mut max_step = 0.0f64;
+        for idx in 0..degree {
+            let denominator = product(
+                roots
+                    .iter()
+                    .enumerate()
+                    .filter(|(other_idx, _)| *other_idx != idx)
+                    .map(|(_, other)| roots[idx] - other),
+            );
+            let step = evaluate(roots[idx]) / denominator;
+            roots[idx] -= step;
+            max_step = max_step.max(step.norm());
+        }
+
+        if max_step < 1e-14 {
+            break;
+        }
+    }
+
+    roots
+}
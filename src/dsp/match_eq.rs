@@ -0,0 +1,231 @@
+use realfft::num_complex::Complex32;
+use realfft::{RealFftPlanner, RealToComplex};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use super::crossover::fir::{FirCoefficients, FirPhase};
+
+/// The FFT size used for the spectral analysis. At 44.1 kHz this gives a bin spacing of about
+/// 10 Hz, which is plenty for the smoothed curves a match EQ needs.
+pub const ANALYSIS_FFT_SIZE: usize = 4096;
+/// The number of bins in the analysed power spectra.
+pub const ANALYSIS_NUM_BINS: usize = ANALYSIS_FFT_SIZE / 2 + 1;
+/// Analysis frames overlap by half a window.
+const ANALYSIS_HOP_SIZE: usize = ANALYSIS_FFT_SIZE / 2;
+/// Correction gains are limited to this many decibels in either direction.
+pub const MAX_MATCH_GAIN_DB: f32 = 24.0;
+
+/// Accumulates the long-term average power spectrum of a signal using Hann windowed, 50%
+/// overlapping frames. Everything is allocated up front so this can run on the audio thread.
+pub struct SpectrumAccumulator {
+    window: Vec<f32>,
+    /// The last `ANALYSIS_FFT_SIZE` input samples, as a ring buffer.
+    input_buffer: Vec<f32>,
+    input_pos: usize,
+    /// The number of samples until the next frame is analysed.
+    samples_until_hop: usize,
+
+    /// The summed power spectra of all analysed frames.
+    power_sum: Vec<f32>,
+    num_frames: u64,
+
+    r2c_plan: Arc<dyn RealToComplex<f32>>,
+    real_scratch_buffer: Vec<f32>,
+    complex_scratch_buffer: Vec<Complex32>,
+}
+
+impl Default for SpectrumAccumulator {
+    fn default() -> Self {
+        let window = (0..ANALYSIS_FFT_SIZE)
+            .map(|idx| 0.5 - 0.5 * (2.0 * PI * idx as f32 / ANALYSIS_FFT_SIZE as f32).cos())
+            .collect();
+
+        SpectrumAccumulator {
+            window,
+            input_buffer: vec![0.0; ANALYSIS_FFT_SIZE],
+            input_pos: 0,
+            samples_until_hop: ANALYSIS_FFT_SIZE,
+
+            power_sum: vec![0.0; ANALYSIS_NUM_BINS],
+            num_frames: 0,
+
+            r2c_plan: RealFftPlanner::new().plan_fft_forward(ANALYSIS_FFT_SIZE),
+            real_scratch_buffer: vec![0.0; ANALYSIS_FFT_SIZE],
+            complex_scratch_buffer: vec![Complex32::default(); ANALYSIS_NUM_BINS],
+        }
+    }
+}
+
+impl SpectrumAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compute the long-term average spectrum of an entire signal at once.
+    pub fn analyze(samples: &[f32]) -> Vec<f32> {
+        let mut accumulator = Self::new();
+        for sample in samples {
+            accumulator.process(*sample);
+        }
+
+        let mut average_power = vec![0.0; ANALYSIS_NUM_BINS];
+        accumulator.average_power(&mut average_power);
+        average_power
+    }
+
+    pub fn reset(&mut self) {
+        self.input_buffer.fill(0.0);
+        self.input_pos = 0;
+        self.samples_until_hop = ANALYSIS_FFT_SIZE;
+        self.power_sum.fill(0.0);
+        self.num_frames = 0;
+    }
+
+    /// The number of frames that have been analysed so far.
+    pub fn num_frames(&self) -> u64 {
+        self.num_frames
+    }
+
+    /// Write the average power per bin to `output`, which needs to contain `ANALYSIS_NUM_BINS`
+    /// elements. This is all zeroes if no frames have been analysed yet.
+    pub fn average_power(&self, output: &mut [f32]) {
+        let scale = if self.num_frames > 0 {
+            1.0 / self.num_frames as f32
+        } else {
+            0.0
+        };
+        for (output_bin, power) in output.iter_mut().zip(&self.power_sum) {
+            *output_bin = power * scale;
+        }
+    }
+
+    pub fn process(&mut self, input: f32) {
+        self.input_buffer[self.input_pos] = input;
+        self.input_pos = (self.input_pos + 1) % ANALYSIS_FFT_SIZE;
+
+        self.samples_until_hop -= 1;
+        if self.samples_until_hop == 0 {
+            self.samples_until_hop = ANALYSIS_HOP_SIZE;
+            self.analyze_frame();
+        }
+    }
+
+    fn analyze_frame(&mut self) {
+        // `input_pos` now points at the oldest sample in the ring buffer
+        let (older, newer) = self.input_buffer.split_at(self.input_pos);
+        for ((target, sample), window) in self
+            .real_scratch_buffer
+            .iter_mut()
+            .zip(newer.iter().chain(older))
+            .zip(&self.window)
+        {
+            *target = sample * window;
+        }
+
+        self.r2c_plan
+            .process_with_scratch(
+                &mut self.real_scratch_buffer,
+                &mut self.complex_scratch_buffer,
+                &mut [],
+            )
+            .unwrap();
+        for (power, bin) in self.power_sum.iter_mut().zip(&self.complex_scratch_buffer) {
+            *power += bin.norm_sqr();
+        }
+        self.num_frames += 1;
+    }
+}
+
+/// Compute the linear correction gains per analysis bin that make `input_power` sound like
+/// `reference_power`. Both spectra are first smoothed over `smoothing_octaves` wide bands centered
+/// around every bin. The curve is normalized so the input's overall power stays the same, which
+/// means only the tonal balance is matched and not the loudness, and the gains are then limited to
+/// [`MAX_MATCH_GAIN_DB`].
+pub fn match_curve(
+    input_power: &[f32],
+    reference_power: &[f32],
+    smoothing_octaves: f32,
+) -> Vec<f32> {
+    assert_eq!(input_power.len(), reference_power.len());
+
+    let smoothed_input = smooth_fractional_octave(input_power, smoothing_octaves);
+    let smoothed_reference = smooth_fractional_octave(reference_power, smoothing_octaves);
+
+    let mut curve: Vec<f32> = smoothed_input
+        .iter()
+        .zip(&smoothed_reference)
+        .map(|(input, reference)| {
+            if *input > 1e-20 && *reference > 1e-20 {
+                (reference / input).sqrt()
+            } else {
+                1.0
+            }
+        })
+        .collect();
+
+    let input_total: f32 = smoothed_input.iter().sum();
+    let corrected_total: f32 = smoothed_input
+        .iter()
+        .zip(&curve)
+        .map(|(power, gain)| power * gain * gain)
+        .sum();
+    let normalization = if corrected_total > 0.0 {
+        (input_total / corrected_total).sqrt()
+    } else {
+        1.0
+    };
+
+    let max_gain = 10.0f32.powf(MAX_MATCH_GAIN_DB / 20.0);
+    for gain in &mut curve {
+        *gain = (*gain * normalization).clamp(max_gain.recip(), max_gain);
+    }
+
+    curve
+}
+
+/// Turn a correction curve from [`match_curve()`] into a linear-phase FIR filter.
+/// `analysis_sample_rate` is the sample rate the spectra were analysed at, which may differ from
+/// the `sample_rate` the filter will run at.
+pub fn design_match_filter<const N: usize>(
+    curve: &[f32],
+    analysis_sample_rate: f32,
+    sample_rate: f32,
+) -> FirCoefficients<N> {
+    let points: Vec<(f32, f32)> = curve
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(bin_idx, gain)| {
+            let frequency = bin_idx as f32 * analysis_sample_rate / ANALYSIS_FFT_SIZE as f32;
+            (frequency, *gain)
+        })
+        .collect();
+
+    FirCoefficients::design_from_magnitude_points(sample_rate, &points, FirPhase::Linear)
+}
+
+/// Average a power spectrum over a band of `octaves` octaves around every bin. The DC bin is left
+/// as is.
+fn smooth_fractional_octave(power: &[f32], octaves: f32) -> Vec<f32> {
+    let mut prefix_sums = Vec::with_capacity(power.len() + 1);
+    prefix_sums.push(0.0f64);
+    for bin in power {
+        prefix_sums.push(prefix_sums.last().unwrap() + *bin as f64);
+    }
+
+    let half_width = 2.0f32.powf(octaves / 2.0);
+    power
+        .iter()
+        .enumerate()
+        .map(|(bin_idx, bin)| {
+            if bin_idx == 0 {
+                return *bin;
+            }
+
+            let low_idx = ((bin_idx as f32 / half_width).floor() as usize).max(1);
+            let high_idx = ((bin_idx as f32 * half_width).ceil() as usize).min(power.len() - 1);
+            let sum = prefix_sums[high_idx + 1] - prefix_sums[low_idx];
+            (sum / (high_idx + 1 - low_idx) as f64) as f32
+        })
+        .collect()
+}
@@ -1,3 +1,14 @@
 pub mod filters;
 pub mod effects;
-pub mod drives;
\ No newline at end of file
+pub mod drives;
+pub mod wdf;
+pub mod convolution;
+pub mod cabinet;
+pub mod crossover;
+pub mod match_eq;
+pub mod fir_design;
+pub mod iir_design;
+pub mod response;
+pub mod modulation;
+pub mod dynamics;
+pub mod multiband;
\ No newline at end of file
@@ -0,0 +1,224 @@
+//! Modulation sources shared between the effects.
+
+use std::f32::consts::PI;
+
+use super::dynamics::{smoothing_coefficient, DetectorMode};
+
+/// The fraction of a cycle the square wave takes to move between its two levels. A hard square
+/// would click when used for amplitude modulation.
+const SQUARE_TRANSITION_WIDTH: f32 = 0.02;
+
+/// The waveform of a low frequency oscillator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    Square,
+    SawUp,
+    SawDown,
+}
+
+impl LfoShape {
+    /// The value of the waveform at `phase`, where one cycle spans `[0, 1)`. The result lies in
+    /// `[-1, 1]`, and all shapes start at zero on their way up except for the saws, which start at
+    /// the bottom and top of their ramps.
+    pub fn value(self, phase: f32) -> f32 {
+        let phase = phase - phase.floor();
+        match self {
+            LfoShape::Sine => (2.0 * PI * phase).sin(),
+            LfoShape::Triangle => {
+                if phase < 0.25 {
+                    4.0 * phase
+                } else if phase < 0.75 {
+                    2.0 - 4.0 * phase
+                } else {
+                    4.0 * phase - 4.0
+                }
+            }
+            LfoShape::Square => {
+                // A steepened triangle, which ramps over `SQUARE_TRANSITION_WIDTH` around the
+                // zero crossings
+                let steepness = 2.0 / SQUARE_TRANSITION_WIDTH;
+                (LfoShape::Triangle.value(phase) * steepness / 4.0).clamp(-1.0, 1.0)
+            }
+            LfoShape::SawUp => 2.0 * phase - 1.0,
+            LfoShape::SawDown => 1.0 - 2.0 * phase,
+        }
+    }
+}
+
+/// A slowly wandering random value in `[-1, 1]`. A new random target is picked at a fixed rate,
+/// and the value glides towards it with a one-pole low-pass at that same rate. The sequence only
+/// depends on the seed, so two instances with the same seed move in lockstep.
+#[derive(Debug, Clone)]
+pub struct RandomDrift {
+    value: f32,
+    target: f32,
+    samples_until_new_target: usize,
+    /// Xorshift state for the targets.
+    rng_state: u32,
+}
+
+impl RandomDrift {
+    pub fn new(seed: u32) -> Self {
+        RandomDrift {
+            value: 0.0,
+            target: 0.0,
+            samples_until_new_target: 0,
+            // Xorshift gets stuck at zero, and similar seeds should still give unrelated sequences
+            rng_state: 0x9E37_79B9 ^ seed.wrapping_add(1).wrapping_mul(0x85EB_CA6B),
+        }
+    }
+
+    /// Advance by one sample and return the new value. `rate` is how often a new target is picked
+    /// in Hz.
+    pub fn next(&mut self, rate: f32, sample_rate: f32) -> f32 {
+        if self.samples_until_new_target == 0 {
+            self.samples_until_new_target = ((sample_rate / rate) as usize).max(1);
+            self.target = self.next_random();
+        }
+        self.samples_until_new_target -= 1;
+
+        let coefficient = 1.0 - (-2.0 * PI * rate / sample_rate).exp();
+        self.value += (self.target - self.value) * coefficient;
+
+        self.value
+    }
+
+    fn next_random(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+        (self.rng_state as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+/// A note length for tempo synced modulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteDivision {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    DottedQuarter,
+    DottedEighth,
+    QuarterTriplet,
+    EighthTriplet,
+}
+
+impl NoteDivision {
+    /// The length of the note in quarter note beats.
+    pub fn beats(self) -> f32 {
+        match self {
+            NoteDivision::Whole => 4.0,
+            NoteDivision::Half => 2.0,
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::DottedQuarter => 1.5,
+            NoteDivision::DottedEighth => 0.75,
+            NoteDivision::QuarterTriplet => 2.0 / 3.0,
+            NoteDivision::EighthTriplet => 1.0 / 3.0,
+        }
+    }
+
+    /// The frequency in Hz at which one cycle lasts exactly this note at `tempo` beats per minute.
+    pub fn frequency(self, tempo: f32) -> f32 {
+        tempo / 60.0 / self.beats()
+    }
+
+    /// The length of the note in milliseconds at `tempo` beats per minute.
+    pub fn length_ms(self, tempo: f32) -> f32 {
+        self.beats() * 60000.0 / tempo
+    }
+}
+
+/// Follows the level of a signal so parameters can be modulated by playing dynamics. In peak mode
+/// the rectified signal is smoothed, and in RMS mode the squared signal is smoothed before taking
+/// the square root. Rising levels are smoothed with the attack time and falling levels with the
+/// release time.
+#[derive(Debug, Clone)]
+pub struct EnvelopeFollower {
+    sample_rate: f32,
+    /// The smoothed rectified or squared signal, depending on the detector mode.
+    state: f32,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+
+    detector_mode: DetectorMode,
+    attack_ms: f32,
+    release_ms: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut follower = EnvelopeFollower {
+            sample_rate,
+            state: 0.0,
+            attack_coefficient: 0.0,
+            release_coefficient: 0.0,
+
+            detector_mode: DetectorMode::Peak,
+            attack_ms: 10.0,
+            release_ms: 200.0,
+        };
+        follower.update_coefficients();
+
+        follower
+    }
+
+    pub fn set_detector_mode(&mut self, detector_mode: DetectorMode) {
+        self.detector_mode = detector_mode;
+    }
+
+    pub fn set_attack(&mut self, attack_ms: f32) {
+        self.attack_ms = attack_ms.max(0.0);
+        self.update_coefficients();
+    }
+
+    pub fn set_release(&mut self, release_ms: f32) {
+        self.release_ms = release_ms.max(0.0);
+        self.update_coefficients();
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.update_coefficients();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.state = 0.0;
+    }
+
+    fn update_coefficients(&mut self) {
+        self.attack_coefficient = smoothing_coefficient(self.sample_rate, self.attack_ms);
+        self.release_coefficient = smoothing_coefficient(self.sample_rate, self.release_ms);
+    }
+
+    /// The current envelope as a linear amplitude.
+    pub fn value(&self) -> f32 {
+        match self.detector_mode {
+            DetectorMode::Peak => self.state,
+            DetectorMode::Rms => self.state.sqrt(),
+        }
+    }
+
+    /// Advance by one sample and return the new envelope as a linear amplitude.
+    pub fn process(&mut self, input: f32) -> f32 {
+        let rectified = match self.detector_mode {
+            DetectorMode::Peak => input.abs(),
+            DetectorMode::Rms => input * input,
+        };
+        let coefficient = if rectified > self.state {
+            self.attack_coefficient
+        } else {
+            self.release_coefficient
+        };
+        self.state = rectified * (1.0 - coefficient) + self.state * coefficient;
+
+        self.value()
+    }
+}
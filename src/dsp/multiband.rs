@@ -0,0 +1,175 @@
+//! Processors that split the signal into bands with the linear-phase crossover and treat every
+//! band separately.
+
+use super::crossover::fir::{BufferedFftFirFilter, FirCoefficients, FFT_SIZE, FILTER_SIZE};
+use super::drives::wave_shapers::WaveShaper;
+
+/// The most bands a [`MultibandSaturation`] can split the signal into.
+pub const MULTIBAND_MAX_BANDS: usize = 4;
+/// The fewest bands a [`MultibandSaturation`] splits the signal into.
+pub const MULTIBAND_MIN_BANDS: usize = 2;
+/// The signal is copied into a scratch buffer of this size for every band, so the block is
+/// processed in chunks of at most this many samples.
+const CHUNK_SIZE: usize = 256;
+
+/// The settings for a single band of a [`MultibandSaturation`].
+#[derive(Debug, Clone, Copy)]
+struct BandSettings {
+    wave_shaper: WaveShaper,
+    drive: f32,
+    output_gain: f32,
+}
+
+impl Default for BandSettings {
+    fn default() -> Self {
+        BandSettings {
+            wave_shaper: WaveShaper::default(),
+            drive: 1.0,
+            output_gain: 1.0,
+        }
+    }
+}
+
+/// Multiband saturation. The signal is split into two to four bands with a linear-phase LR4
+/// crossover, every band goes through its own wave shaper with its own drive and output gain, and
+/// the bands are summed again. The crossover adds [`latency_samples()`][Self::latency_samples()]
+/// of latency.
+pub struct MultibandSaturation {
+    sample_rate: f32,
+    /// One single-channel filter per band and per channel, indexed by `[band_idx][channel_idx]`.
+    band_filters: Vec<Vec<BufferedFftFirFilter<FFT_SIZE>>>,
+    /// Scratch buffers for the band being processed and the summed output.
+    band_buffer: Vec<f32>,
+    output_buffer: Vec<f32>,
+
+    num_bands: usize,
+    /// The sorted crossover frequencies. Only the first `num_bands - 1` are used.
+    crossover_frequencies: [f32; MULTIBAND_MAX_BANDS - 1],
+    bands: [BandSettings; MULTIBAND_MAX_BANDS],
+}
+
+impl MultibandSaturation {
+    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+        let mut saturation = MultibandSaturation {
+            sample_rate,
+            band_filters: (0..MULTIBAND_MAX_BANDS)
+                .map(|_| {
+                    (0..num_channels)
+                        .map(|_| BufferedFftFirFilter::new(1))
+                        .collect()
+                })
+                .collect(),
+            band_buffer: vec![0.0; CHUNK_SIZE],
+            output_buffer: vec![0.0; CHUNK_SIZE],
+
+            num_bands: 3,
+            crossover_frequencies: [200.0, 1000.0, 4000.0],
+            bands: [BandSettings::default(); MULTIBAND_MAX_BANDS],
+        };
+        saturation.update_crossover();
+
+        saturation
+    }
+
+    /// Set the number of bands, between [`MULTIBAND_MIN_BANDS`] and [`MULTIBAND_MAX_BANDS`].
+    /// Changing this redesigns the crossover and clears the filters.
+    pub fn set_num_bands(&mut self, num_bands: usize) {
+        let num_bands = num_bands.clamp(MULTIBAND_MIN_BANDS, MULTIBAND_MAX_BANDS);
+        if num_bands != self.num_bands {
+            self.num_bands = num_bands;
+            self.update_crossover();
+            self.reset();
+        }
+    }
+
+    /// Set the crossover frequencies in Hz. The frequencies are sorted, and only the first
+    /// `num_bands - 1` are used. Changing these redesigns the crossover, which does not allocate.
+    pub fn set_crossover_frequencies(&mut self, frequencies: &[f32]) {
+        let mut new_frequencies = self.crossover_frequencies;
+        for (new_frequency, frequency) in new_frequencies.iter_mut().zip(frequencies) {
+            *new_frequency = frequency.clamp(20.0, self.sample_rate * 0.45);
+        }
+        new_frequencies.sort_by(f32::total_cmp);
+
+        if new_frequencies != self.crossover_frequencies {
+            self.crossover_frequencies = new_frequencies;
+            self.update_crossover();
+        }
+    }
+
+    pub fn set_wave_shaper(&mut self, band_idx: usize, wave_shaper: WaveShaper) {
+        self.bands[band_idx].wave_shaper = wave_shaper;
+    }
+
+    /// Set the linear gain going into a band's wave shaper.
+    pub fn set_drive(&mut self, band_idx: usize, drive: f32) {
+        self.bands[band_idx].drive = drive.max(0.0);
+    }
+
+    /// Set the linear gain applied to a band after its wave shaper. The drive is not compensated
+    /// for, so this is where the band's level is evened out again.
+    pub fn set_output_gain(&mut self, band_idx: usize, output_gain: f32) {
+        self.bands[band_idx].output_gain = output_gain.max(0.0);
+    }
+
+    /// Redesigns the crossover for the new sample rate and clears the filters.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for frequency in &mut self.crossover_frequencies {
+            *frequency = frequency.min(sample_rate * 0.45);
+        }
+        self.update_crossover();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        for filter in self.band_filters.iter_mut().flatten() {
+            filter.reset();
+        }
+    }
+
+    /// The latency of the linear-phase crossover, in samples.
+    pub fn latency_samples(&self) -> u32 {
+        self.band_filters[0][0].latency_samples()
+    }
+
+    fn update_crossover(&mut self) {
+        let frequencies = &self.crossover_frequencies[..self.num_bands - 1];
+        for (band_idx, filters) in self.band_filters[..self.num_bands].iter_mut().enumerate() {
+            let coefficients =
+                FirCoefficients::<FILTER_SIZE>::design_linear_phase_lr4_crossover_band(
+                    self.sample_rate,
+                    frequencies,
+                    band_idx,
+                );
+            for filter in filters {
+                filter.set_coefficients(&coefficients);
+            }
+        }
+    }
+
+    /// Saturate `buffer` in place.
+    pub fn process(&mut self, buffer: &mut [&mut [f32]]) {
+        for (channel_idx, channel) in buffer.iter_mut().enumerate() {
+            for chunk in channel.chunks_mut(CHUNK_SIZE) {
+                let band_buffer = &mut self.band_buffer[..chunk.len()];
+                let output_buffer = &mut self.output_buffer[..chunk.len()];
+                output_buffer.fill(0.0);
+
+                for (filters, band) in self.band_filters[..self.num_bands]
+                    .iter_mut()
+                    .zip(&self.bands)
+                {
+                    band_buffer.copy_from_slice(chunk);
+                    filters[channel_idx].process(&mut [&mut *band_buffer]);
+
+                    for (output, sample) in output_buffer.iter_mut().zip(band_buffer.iter()) {
+                        *output += band.wave_shaper.apply(sample * band.drive) * band.output_gain;
+                    }
+                }
+
+                chunk.copy_from_slice(output_buffer);
+            }
+        }
+    }
+}
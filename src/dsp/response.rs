@@ -0,0 +1,257 @@
+//! Frequency responses of linear processors. Filters with a known transfer function implement
+//! [`FrequencyResponse`] analytically, and anything else can be measured with
+//! [`MeasuredResponse::measure()`] by feeding it an impulse.
+
+use realfft::num_complex::Complex64;
+use realfft::RealFftPlanner;
+use std::f64::consts::PI;
+
+/// A filter's response at a single frequency.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Response {
+    /// The linear gain.
+    pub magnitude: f32,
+    /// The phase shift in radians, wrapped to `[-pi, pi]`.
+    pub phase: f32,
+    /// The group delay in samples. This is not finite at the zeros of the transfer function.
+    pub group_delay: f32,
+}
+
+impl Response {
+    pub fn magnitude_db(&self) -> f32 {
+        20.0 * self.magnitude.log10()
+    }
+}
+
+/// Implemented by linear filters whose response can be computed from their current coefficients.
+pub trait FrequencyResponse {
+    /// The filter's response at `frequency` Hz when running at `sample_rate`.
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response;
+}
+
+/// The response of the transfer function `H(z) = B(z) / A(z)` at `frequency` Hz, where
+/// `numerator` and `denominator` contain the coefficients of `B` and `A` for increasing powers of
+/// `z^-1`.
+pub fn transfer_function_response(
+    numerator: &[f64],
+    denominator: &[f64],
+    frequency: f32,
+    sample_rate: f32,
+) -> Response {
+    let omega = 2.0 * PI * frequency as f64 / sample_rate as f64;
+    let (numerator, numerator_delay) = polynomial_response(numerator, omega);
+    let (denominator, denominator_delay) = polynomial_response(denominator, omega);
+    let response = numerator / denominator;
+
+    Response {
+        magnitude: response.norm() as f32,
+        phase: response.arg() as f32,
+        group_delay: (numerator_delay - denominator_delay) as f32,
+    }
+}
+
+/// Evaluate the polynomial `sum_n p[n] z^-n` on the unit circle, returning its value and its group
+/// delay. The group delay is computed as `Re(sum_n n p[n] z^-n / P(z))`.
+fn polynomial_response(coefficients: &[f64], omega: f64) -> (Complex64, f64) {
+    let mut value = Complex64::new(0.0, 0.0);
+    let mut ramped_value = Complex64::new(0.0, 0.0);
+    for (n, coefficient) in coefficients.iter().enumerate() {
+        let term = Complex64::from_polar(*coefficient, -omega * n as f64);
+        value += term;
+        ramped_value += term * n as f64;
+    }
+
+    (value, (ramped_value / value).re)
+}
+
+/// A response measured from a processor's impulse response, with one entry for every FFT bin from
+/// DC up to and including the Nyquist frequency.
+#[derive(Debug, Clone)]
+pub struct MeasuredResponse {
+    pub sample_rate: f32,
+    pub fft_size: usize,
+    pub bins: Vec<Response>,
+}
+
+impl MeasuredResponse {
+    /// Feed a unit impulse followed by silence to `process`, and compute the response from the
+    /// first `fft_size` output samples. This only gives accurate results for linear, time
+    /// invariant processors whose impulse response has decayed within `fft_size` samples. Block
+    /// based processors with latency should be given enough room for that latency as well.
+    pub fn measure(mut process: impl FnMut(f32) -> f32, sample_rate: f32, fft_size: usize) -> Self {
+        let mut impulse_response: Vec<f32> = (0..fft_size)
+            .map(|sample_idx| process(if sample_idx == 0 { 1.0 } else { 0.0 }))
+            .collect();
+        // The group delay is computed from the spectrum of the time-weighted impulse response,
+        // which avoids having to unwrap the phase
+        let mut ramped_impulse_response: Vec<f32> = impulse_response
+            .iter()
+            .enumerate()
+            .map(|(sample_idx, sample)| sample * sample_idx as f32)
+            .collect();
+
+        let r2c_plan = RealFftPlanner::new().plan_fft_forward(fft_size);
+        let mut spectrum = r2c_plan.make_output_vec();
+        let mut ramped_spectrum = r2c_plan.make_output_vec();
+        r2c_plan
+            .process(&mut impulse_response, &mut spectrum)
+            .unwrap();
+        r2c_plan
+            .process(&mut ramped_impulse_response, &mut ramped_spectrum)
+            .unwrap();
+
+        let bins = spectrum
+            .iter()
+            .zip(&ramped_spectrum)
+            .map(|(bin, ramped_bin)| Response {
+                magnitude: bin.norm(),
+                phase: bin.arg(),
+                group_delay: (ramped_bin / bin).re,
+            })
+            .collect();
+
+        MeasuredResponse {
+            sample_rate,
+            fft_size,
+            bins,
+        }
+    }
+
+    /// The frequency in Hz corresponding to a bin index.
+    pub fn bin_frequency(&self, bin_idx: usize) -> f32 {
+        bin_idx as f32 * self.sample_rate / self.fft_size as f32
+    }
+
+    /// The response in the bin closest to `frequency` Hz.
+    pub fn response_at(&self, frequency: f32) -> Response {
+        let bin_idx = (frequency / self.sample_rate * self.fft_size as f32).round() as usize;
+        self.bins[bin_idx.min(self.bins.len() - 1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::crossover::fir::{
+        BufferedFftFirFilter, FirCoefficients, FFT_SIZE, FILTER_SIZE,
+    };
+    use crate::dsp::crossover::iir::biquad::{Biquad, BiquadCoefficients};
+    use crate::dsp::filters::tone_stack::{ToneStack, ToneStackModel};
+    use crate::dsp::filters::{AllPassFilter, FirstOrderAllPass};
+    use crate::dsp::wdf::circuits::RcToneControl;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    /// Compare the analytic response to the measured one in every bin where the filter isn't
+    /// attenuating by more than 20 dB, since the single precision processing makes the measurement
+    /// less accurate below that.
+    fn assert_responses_match(filter: &impl FrequencyResponse, measured: &MeasuredResponse) {
+        for (bin_idx, measured_bin) in measured.bins.iter().enumerate() {
+            let frequency = measured.bin_frequency(bin_idx);
+            let analytic_bin = filter.frequency_response(frequency, measured.sample_rate);
+            if analytic_bin.magnitude < 0.1 {
+                continue;
+            }
+
+            let magnitude_error = analytic_bin.magnitude_db() - measured_bin.magnitude_db();
+            let phase_error = (analytic_bin.phase - measured_bin.phase + std::f32::consts::PI)
+                .rem_euclid(std::f32::consts::TAU)
+                - std::f32::consts::PI;
+            let group_delay_error = analytic_bin.group_delay - measured_bin.group_delay;
+            assert!(
+                magnitude_error.abs() < 0.01
+                    && phase_error.abs() < 1e-3
+                    && group_delay_error.abs() < 0.01 + analytic_bin.group_delay.abs() * 1e-4,
+                "{frequency} Hz: analytic {analytic_bin:?}, measured {measured_bin:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn biquad_response_matches_measurement() {
+        for coefficients in [
+            BiquadCoefficients::lowpass(SAMPLE_RATE, 1000.0, 0.707),
+            BiquadCoefficients::highpass(SAMPLE_RATE, 200.0, 2.0),
+            BiquadCoefficients::allpass(SAMPLE_RATE, 5000.0, 0.5),
+        ] {
+            let mut biquad = Biquad::default();
+            biquad.coefficients = coefficients;
+            let analytic = biquad;
+            let measured = MeasuredResponse::measure(|x| biquad.process(x), SAMPLE_RATE, 8192);
+
+            assert_responses_match(&analytic, &measured);
+        }
+    }
+
+    #[test]
+    fn fir_response_matches_measurement() {
+        let coefficients =
+            FirCoefficients::<FILTER_SIZE>::design_linear_phase_lr4_low_pass(SAMPLE_RATE, 2000.0);
+        let mut filter = BufferedFftFirFilter::<FFT_SIZE>::new(1);
+        filter.set_coefficients(&coefficients);
+
+        // The impulse response is delayed by the buffering, which needs to fit in the measurement
+        let measured = MeasuredResponse::measure(
+            |x| {
+                let mut sample = [x];
+                filter.process(&mut [&mut sample]);
+                sample[0]
+            },
+            SAMPLE_RATE,
+            8192,
+        );
+        assert_responses_match(&filter, &measured);
+
+        let mut delay_line = vec![0.0; FILTER_SIZE];
+        let measured = MeasuredResponse::measure(
+            |x| {
+                delay_line.rotate_right(1);
+                delay_line[0] = x;
+                delay_line
+                    .iter()
+                    .zip(&coefficients.0)
+                    .map(|(sample, coefficient)| sample * coefficient)
+                    .sum()
+            },
+            SAMPLE_RATE,
+            4096,
+        );
+        assert_responses_match(&coefficients, &measured);
+    }
+
+    #[test]
+    fn allpass_responses_match_measurement() {
+        let mut allpass = AllPassFilter::new(37);
+        allpass.set_feedback(0.6);
+        let analytic = allpass.clone();
+        let measured = MeasuredResponse::measure(|x| allpass.process(x), SAMPLE_RATE, 8192);
+        assert_responses_match(&analytic, &measured);
+
+        let mut allpass = FirstOrderAllPass::new(SAMPLE_RATE, 800.0);
+        let analytic = allpass.clone();
+        let measured = MeasuredResponse::measure(|x| allpass.process(x), SAMPLE_RATE, 8192);
+        assert_responses_match(&analytic, &measured);
+    }
+
+    #[test]
+    fn tone_control_responses_match_measurement() {
+        for model in [
+            ToneStackModel::Fender,
+            ToneStackModel::Marshall,
+            ToneStackModel::Vox,
+        ] {
+            let mut tone_stack = ToneStack::new(SAMPLE_RATE, model);
+            tone_stack.set_knobs(0.3, 0.7, 0.6);
+            tone_stack.reset();
+            let analytic = tone_stack.clone();
+            let measured = MeasuredResponse::measure(|x| tone_stack.process(x), SAMPLE_RATE, 16384);
+            assert_responses_match(&analytic, &measured);
+        }
+
+        let mut tone_control = RcToneControl::new(SAMPLE_RATE);
+        tone_control.set_tone(0.3);
+        let analytic = tone_control.clone();
+        let measured = MeasuredResponse::measure(|x| tone_control.process(x), SAMPLE_RATE, 8192);
+        assert_responses_match(&analytic, &measured);
+    }
+}
@@ -0,0 +1,173 @@
+//! Three-port series and parallel adaptors. The third port faces the parent and is always
+//! adapted, meaning its reflected wave doesn't depend on its incident wave. That's what makes it
+//! possible to compute a whole tree without delay-free loops.
+
+use super::WdfNode;
+
+/// Connects two subtrees in series.
+#[derive(Debug, Clone)]
+pub struct Series<P1: WdfNode, P2: WdfNode> {
+    pub port1: P1,
+    pub port2: P2,
+    impedance: f32,
+    /// `R1 / (R1 + R2)`, the fraction of the scattered wave that goes to the first port.
+    port1_reflection: f32,
+    /// The waves most recently reflected by `port1` and `port2`.
+    child_waves: [f32; 2],
+    a: f32,
+    b: f32,
+}
+
+impl<P1: WdfNode, P2: WdfNode> Series<P1, P2> {
+    pub fn new(port1: P1, port2: P2) -> Self {
+        let mut adaptor = Series {
+            port1,
+            port2,
+            impedance: 0.0,
+            port1_reflection: 0.0,
+            child_waves: [0.0; 2],
+            a: 0.0,
+            b: 0.0,
+        };
+        adaptor.update_impedance();
+
+        adaptor
+    }
+
+    fn update_impedance(&mut self) {
+        self.impedance = self.port1.impedance() + self.port2.impedance();
+        self.port1_reflection = self.port1.impedance() / self.impedance;
+    }
+}
+
+impl<P1: WdfNode, P2: WdfNode> WdfNode for Series<P1, P2> {
+    fn impedance(&self) -> f32 {
+        self.impedance
+    }
+
+    fn calc_impedance(&mut self) {
+        self.port1.calc_impedance();
+        self.port2.calc_impedance();
+        self.update_impedance();
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.port1.set_sample_rate(sample_rate);
+        self.port2.set_sample_rate(sample_rate);
+        self.update_impedance();
+    }
+
+    fn incident(&mut self, a: f32) {
+        let [b1, b2] = self.child_waves;
+        let port1_incident = b1 - self.port1_reflection * (a + b1 + b2);
+        // The voltages around the loop need to sum to zero
+        let port2_incident = -(a + port1_incident);
+
+        self.port1.incident(port1_incident);
+        self.port2.incident(port2_incident);
+        self.a = a;
+    }
+
+    fn reflected(&mut self) -> f32 {
+        self.child_waves = [self.port1.reflected(), self.port2.reflected()];
+        self.b = -(self.child_waves[0] + self.child_waves[1]);
+        self.b
+    }
+
+    fn waves(&self) -> (f32, f32) {
+        (self.a, self.b)
+    }
+
+    fn reset(&mut self) {
+        self.port1.reset();
+        self.port2.reset();
+        self.child_waves = [0.0; 2];
+        self.a = 0.0;
+        self.b = 0.0;
+    }
+}
+
+/// Connects two subtrees in parallel.
+#[derive(Debug, Clone)]
+pub struct Parallel<P1: WdfNode, P2: WdfNode> {
+    pub port1: P1,
+    pub port2: P2,
+    impedance: f32,
+    /// `G1 / (G1 + G2)`, the weight of the first port's wave in the junction voltage.
+    port1_reflection: f32,
+    /// The waves most recently reflected by `port1` and `port2`.
+    child_waves: [f32; 2],
+    a: f32,
+    b: f32,
+}
+
+impl<P1: WdfNode, P2: WdfNode> Parallel<P1, P2> {
+    pub fn new(port1: P1, port2: P2) -> Self {
+        let mut adaptor = Parallel {
+            port1,
+            port2,
+            impedance: 0.0,
+            port1_reflection: 0.0,
+            child_waves: [0.0; 2],
+            a: 0.0,
+            b: 0.0,
+        };
+        adaptor.update_impedance();
+
+        adaptor
+    }
+
+    fn update_impedance(&mut self) {
+        let g1 = self.port1.impedance().recip();
+        let g2 = self.port2.impedance().recip();
+        self.impedance = (g1 + g2).recip();
+        self.port1_reflection = g1 / (g1 + g2);
+    }
+}
+
+impl<P1: WdfNode, P2: WdfNode> WdfNode for Parallel<P1, P2> {
+    fn impedance(&self) -> f32 {
+        self.impedance
+    }
+
+    fn calc_impedance(&mut self) {
+        self.port1.calc_impedance();
+        self.port2.calc_impedance();
+        self.update_impedance();
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.port1.set_sample_rate(sample_rate);
+        self.port2.set_sample_rate(sample_rate);
+        self.update_impedance();
+    }
+
+    fn incident(&mut self, a: f32) {
+        // `a + b` is twice the voltage across the junction, and every port sees that same voltage
+        let [b1, b2] = self.child_waves;
+        let junction = a + self.b;
+
+        self.port1.incident(junction - b1);
+        self.port2.incident(junction - b2);
+        self.a = a;
+    }
+
+    fn reflected(&mut self) -> f32 {
+        self.child_waves = [self.port1.reflected(), self.port2.reflected()];
+        self.b = self.port1_reflection * self.child_waves[0]
+            + (1.0 - self.port1_reflection) * self.child_waves[1];
+        self.b
+    }
+
+    fn waves(&self) -> (f32, f32) {
+        (self.a, self.b)
+    }
+
+    fn reset(&mut self) {
+        self.port1.reset();
+        self.port2.reset();
+        self.child_waves = [0.0; 2];
+        self.a = 0.0;
+        self.b = 0.0;
+    }
+}
@@ -0,0 +1,145 @@
+//! Example circuits built from the WDF primitives.
+
+use super::adaptors::{Parallel, Series};
+use super::elements::{Capacitor, Resistor, ResistiveVoltageSource};
+use super::roots::{DiodePair, IdealVoltageSource};
+use super::{process_tree, WdfNode, WdfRoot};
+use crate::dsp::response::{self, FrequencyResponse, Response};
+
+/// A first-order RC low-pass feeding a pair of antiparallel silicon diodes to ground, as found in
+/// most overdrive and distortion pedals.
+#[derive(Debug, Clone)]
+pub struct DiodeClipper {
+    tree: Parallel<ResistiveVoltageSource, Capacitor>,
+    diodes: DiodePair,
+}
+
+impl DiodeClipper {
+    pub fn new(sample_rate: f32) -> Self {
+        let tree = Parallel::new(
+            ResistiveVoltageSource::new(4.7e3),
+            Capacitor::new(47.0e-9, sample_rate),
+        );
+        // 1N4148, with its ideality factor folded into the thermal voltage
+        let mut diodes = DiodePair::new(2.52e-9, 25.85e-3 * 1.752, 1.0);
+        diodes.set_port_resistance(tree.impedance());
+
+        DiodeClipper { tree, diodes }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.tree.set_sample_rate(sample_rate);
+        self.diodes.set_port_resistance(self.tree.impedance());
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.tree.reset();
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.tree.port1.set_voltage(input);
+        process_tree(&mut self.diodes, &mut self.tree);
+
+        self.tree.port2.voltage()
+    }
+}
+
+/// The component values of the [`RcToneControl`]'s low-pass and high-pass branches.
+const TONE_LOW_PASS_RESISTANCE: f32 = 39.0e3;
+const TONE_LOW_PASS_CAPACITANCE: f32 = 10.0e-9;
+const TONE_HIGH_PASS_CAPACITANCE: f32 = 4.0e-9;
+const TONE_HIGH_PASS_RESISTANCE: f32 = 22.0e3;
+
+/// A passive tone control that blends between an RC low-pass and a CR high-pass, in the style of
+/// the Big Muff tone stack. `tone` goes from fully dark at 0.0 to fully bright at 1.0, with the
+/// usual mid scoop in between.
+#[derive(Debug, Clone)]
+pub struct RcToneControl {
+    source: IdealVoltageSource,
+    tree: Parallel<Series<Resistor, Capacitor>, Series<Capacitor, Resistor>>,
+    tone: f32,
+}
+
+impl RcToneControl {
+    pub fn new(sample_rate: f32) -> Self {
+        let tree = Parallel::new(
+            Series::new(
+                Resistor::new(TONE_LOW_PASS_RESISTANCE),
+                Capacitor::new(TONE_LOW_PASS_CAPACITANCE, sample_rate),
+            ),
+            Series::new(
+                Capacitor::new(TONE_HIGH_PASS_CAPACITANCE, sample_rate),
+                Resistor::new(TONE_HIGH_PASS_RESISTANCE),
+            ),
+        );
+
+        RcToneControl {
+            source: IdealVoltageSource::new(),
+            tree,
+            tone: 0.5,
+        }
+    }
+
+    pub fn set_tone(&mut self, tone: f32) {
+        self.tone = tone.clamp(0.0, 1.0);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.tree.set_sample_rate(sample_rate);
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.tree.reset();
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.source.set_voltage(input);
+        process_tree(&mut self.source, &mut self.tree);
+
+        let low_pass = self.tree.port1.port2.voltage();
+        let high_pass = self.tree.port2.port2.voltage();
+        // The series adaptors make the voltages in a branch sum to minus the source voltage
+        -(low_pass * (1.0 - self.tone) + high_pass * self.tone)
+    }
+}
+
+impl FrequencyResponse for RcToneControl {
+    /// The response of the bilinear transformed circuit, which is what the capacitors' port
+    /// resistances amount to. Both branches are driven by an ideal source, so they don't load each
+    /// other and their responses are simply blended.
+    fn frequency_response(&self, frequency: f32, sample_rate: f32) -> Response {
+        let k = 2.0 * sample_rate as f64;
+        let low_pass_time_constant =
+            TONE_LOW_PASS_RESISTANCE as f64 * TONE_LOW_PASS_CAPACITANCE as f64 * k;
+        let high_pass_time_constant =
+            TONE_HIGH_PASS_RESISTANCE as f64 * TONE_HIGH_PASS_CAPACITANCE as f64 * k;
+
+        // `1 / (1 + s R C)` and `s R C / (1 + s R C)` with `s = k (1 - z^-1) / (1 + z^-1)`
+        let low_pass_numerator = [1.0, 1.0];
+        let low_pass_denominator = [1.0 + low_pass_time_constant, 1.0 - low_pass_time_constant];
+        let high_pass_numerator = [high_pass_time_constant, -high_pass_time_constant];
+        let high_pass_denominator = [1.0 + high_pass_time_constant, 1.0 - high_pass_time_constant];
+
+        let tone = self.tone as f64;
+        let low_pass_part = multiply_polynomials(&low_pass_numerator, &high_pass_denominator);
+        let high_pass_part = multiply_polynomials(&high_pass_numerator, &low_pass_denominator);
+        let numerator: Vec<f64> = low_pass_part
+            .iter()
+            .zip(high_pass_part)
+            .map(|(low_pass, high_pass)| low_pass * (1.0 - tone) + high_pass * tone)
+            .collect();
+        let denominator = multiply_polynomials(&low_pass_denominator, &high_pass_denominator);
+
+        response::transfer_function_response(&numerator, &denominator, frequency, sample_rate)
+    }
+}
+
+fn multiply_polynomials(lhs: &[f64; 2], rhs: &[f64; 2]) -> [f64; 3] {
+    [
+        lhs[0] * rhs[0],
+        lhs[0] * rhs[1] + lhs[1] * rhs[0],
+        lhs[1] * rhs[1],
+    ]
+}
@@ -0,0 +1,231 @@
+//! Adaptable one-port leaves. Reactive elements are discretized with the bilinear transform.
+
+use super::WdfNode;
+
+#[derive(Debug, Clone)]
+pub struct Resistor {
+    resistance: f32,
+    a: f32,
+    b: f32,
+}
+
+impl Resistor {
+    pub fn new(resistance: f32) -> Self {
+        Resistor {
+            resistance,
+            a: 0.0,
+            b: 0.0,
+        }
+    }
+
+    pub fn set_resistance(&mut self, resistance: f32) {
+        self.resistance = resistance;
+    }
+}
+
+impl WdfNode for Resistor {
+    fn impedance(&self) -> f32 {
+        self.resistance
+    }
+
+    fn incident(&mut self, a: f32) {
+        self.a = a;
+    }
+
+    fn reflected(&mut self) -> f32 {
+        // An adapted resistor absorbs everything
+        self.b = 0.0;
+        self.b
+    }
+
+    fn waves(&self) -> (f32, f32) {
+        (self.a, self.b)
+    }
+
+    fn reset(&mut self) {
+        self.a = 0.0;
+        self.b = 0.0;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Capacitor {
+    capacitance: f32,
+    sample_rate: f32,
+    impedance: f32,
+    /// The incident wave from the previous sample, which is the reflected wave for this sample.
+    z: f32,
+    a: f32,
+    b: f32,
+}
+
+impl Capacitor {
+    pub fn new(capacitance: f32, sample_rate: f32) -> Self {
+        Capacitor {
+            capacitance,
+            sample_rate,
+            impedance: 1.0 / (2.0 * sample_rate * capacitance),
+            z: 0.0,
+            a: 0.0,
+            b: 0.0,
+        }
+    }
+
+    pub fn set_capacitance(&mut self, capacitance: f32) {
+        self.capacitance = capacitance;
+        self.calc_impedance();
+    }
+}
+
+impl WdfNode for Capacitor {
+    fn impedance(&self) -> f32 {
+        self.impedance
+    }
+
+    fn calc_impedance(&mut self) {
+        self.impedance = 1.0 / (2.0 * self.sample_rate * self.capacitance);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.calc_impedance();
+    }
+
+    fn incident(&mut self, a: f32) {
+        self.a = a;
+        self.z = a;
+    }
+
+    fn reflected(&mut self) -> f32 {
+        self.b = self.z;
+        self.b
+    }
+
+    fn waves(&self) -> (f32, f32) {
+        (self.a, self.b)
+    }
+
+    fn reset(&mut self) {
+        self.z = 0.0;
+        self.a = 0.0;
+        self.b = 0.0;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Inductor {
+    inductance: f32,
+    sample_rate: f32,
+    impedance: f32,
+    /// The incident wave from the previous sample, which is the inverted reflected wave for this
+    /// sample.
+    z: f32,
+    a: f32,
+    b: f32,
+}
+
+impl Inductor {
+    pub fn new(inductance: f32, sample_rate: f32) -> Self {
+        Inductor {
+            inductance,
+            sample_rate,
+            impedance: 2.0 * sample_rate * inductance,
+            z: 0.0,
+            a: 0.0,
+            b: 0.0,
+        }
+    }
+
+    pub fn set_inductance(&mut self, inductance: f32) {
+        self.inductance = inductance;
+        self.calc_impedance();
+    }
+}
+
+impl WdfNode for Inductor {
+    fn impedance(&self) -> f32 {
+        self.impedance
+    }
+
+    fn calc_impedance(&mut self) {
+        self.impedance = 2.0 * self.sample_rate * self.inductance;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.calc_impedance();
+    }
+
+    fn incident(&mut self, a: f32) {
+        self.a = a;
+        self.z = a;
+    }
+
+    fn reflected(&mut self) -> f32 {
+        self.b = -self.z;
+        self.b
+    }
+
+    fn waves(&self) -> (f32, f32) {
+        (self.a, self.b)
+    }
+
+    fn reset(&mut self) {
+        self.z = 0.0;
+        self.a = 0.0;
+        self.b = 0.0;
+    }
+}
+
+/// A voltage source with a series resistance. Unlike an ideal source this can be adapted, so it
+/// can sit anywhere in the tree.
+#[derive(Debug, Clone)]
+pub struct ResistiveVoltageSource {
+    resistance: f32,
+    voltage: f32,
+    a: f32,
+    b: f32,
+}
+
+impl ResistiveVoltageSource {
+    pub fn new(resistance: f32) -> Self {
+        ResistiveVoltageSource {
+            resistance,
+            voltage: 0.0,
+            a: 0.0,
+            b: 0.0,
+        }
+    }
+
+    pub fn set_resistance(&mut self, resistance: f32) {
+        self.resistance = resistance;
+    }
+
+    pub fn set_voltage(&mut self, voltage: f32) {
+        self.voltage = voltage;
+    }
+}
+
+impl WdfNode for ResistiveVoltageSource {
+    fn impedance(&self) -> f32 {
+        self.resistance
+    }
+
+    fn incident(&mut self, a: f32) {
+        self.a = a;
+    }
+
+    fn reflected(&mut self) -> f32 {
+        self.b = self.voltage;
+        self.b
+    }
+
+    fn waves(&self) -> (f32, f32) {
+        (self.a, self.b)
+    }
+
+    fn reset(&mut self) {
+        self.a = 0.0;
+        self.b = 0.0;
+    }
+}
@@ -0,0 +1,78 @@
+//! Wave digital filter building blocks for modelling analog circuits.
+//!
+//! A circuit is described as a binary tree of one-port elements connected through series and
+//! parallel adaptors, with a single (possibly nonlinear) element at the root. Everything is
+//! generic, so the whole tree is monomorphized into a single struct and the compiler can inline
+//! the wave propagation. Processing a sample is always the same three steps:
+//!
+//! - Set any source voltages for this sample.
+//! - Call [`WdfNode::reflected()`] on the tree to collect waves from the leaves up to the root.
+//! - Pass that wave through the root and call [`WdfNode::incident()`] with the result to push the
+//!   waves back down to the leaves.
+//!
+//! [`process_tree()`] does the last two steps. Voltages and currents can then be read from any
+//! element. See [`circuits`] for complete examples.
+
+pub mod adaptors;
+pub mod circuits;
+pub mod elements;
+pub mod roots;
+
+/// A one-port wave digital element, either a leaf or an adaptor with its own subtree.
+///
+/// Waves follow the usual voltage wave convention where `a` is the wave incident to the port and
+/// `b` is the wave reflected from it, so `v = (a + b) / 2` and `i = (a - b) / (2 * R)`.
+pub trait WdfNode {
+    /// The port resistance seen when looking into this node.
+    fn impedance(&self) -> f32;
+
+    /// Recompute the cached port resistances of this node and everything below it. This needs to
+    /// be called after changing a component value, and the root then needs to be told about the
+    /// new resistance.
+    fn calc_impedance(&mut self) {}
+
+    /// Update any sample rate dependent state, like the port resistances of reactive elements.
+    fn set_sample_rate(&mut self, _sample_rate: f32) {}
+
+    /// Accept the wave coming from the parent node for this sample.
+    fn incident(&mut self, a: f32);
+
+    /// Compute the wave this node reflects back to its parent for this sample.
+    fn reflected(&mut self) -> f32;
+
+    /// The waves `(a, b)` at this port from the last processed sample.
+    fn waves(&self) -> (f32, f32);
+
+    /// Clear any stored state.
+    fn reset(&mut self) {}
+
+    /// The voltage across this port.
+    fn voltage(&self) -> f32 {
+        let (a, b) = self.waves();
+        (a + b) * 0.5
+    }
+
+    /// The current through this port.
+    fn current(&self) -> f32 {
+        let (a, b) = self.waves();
+        (a - b) / (2.0 * self.impedance())
+    }
+}
+
+/// An element that can sit at the root of a WDF tree. These elements don't need to be adapted,
+/// so this is where ideal sources and nonlinearities go.
+pub trait WdfRoot {
+    /// Inform the root about the port resistance of the tree it's connected to.
+    fn set_port_resistance(&mut self, resistance: f32);
+
+    /// Take the wave reflected by the tree and return the wave that should be sent back into it.
+    fn process(&mut self, a: f32) -> f32;
+}
+
+/// Run a single sample through a tree by collecting the waves up to the root and sending the
+/// root's response back down again.
+#[inline]
+pub fn process_tree<R: WdfRoot, T: WdfNode>(root: &mut R, tree: &mut T) {
+    let b = root.process(tree.reflected());
+    tree.incident(b);
+}
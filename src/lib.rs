@@ -1,14 +1,297 @@
 pub mod dsp; // Declare the dsp module
 
-use dsp::effects::UniVibe;
+use dsp::cabinet::{Cabinet, ImpulseResponse};
+use dsp::crossover::fir::{
+    BufferedFftFirFilter, FftFirFilter, FirCoefficients, FFT_SIZE, FILTER_SIZE,
+};
+use dsp::drives::wave_shapers;
+use dsp::dynamics::{
+    self, Compressor, NoiseGate, TruePeakLimiter, COMPRESSOR_MAX_LOOKAHEAD_MS,
+    GATE_MAX_LOOKAHEAD_MS,
+};
+use dsp::effects::{
+    self, Chorus, Delay, Flanger, Phaser, PlateReverb, SpringReverb, Tremolo, UniVibe, Wah,
+    CHORUS_MAX_VOICES, DELAY_MAX_TIME_MS, FLANGER_MAX_MANUAL_DELAY_MS, PHASER_MAX_STAGES,
+    PLATE_MAX_DECAY, PLATE_MAX_PRE_DELAY_MS, PLATE_MAX_SIZE, PLATE_MIN_SIZE,
+    SPRING_MAX_DECAY_SECONDS, SPRING_MIN_DECAY_SECONDS, TREMOLO_DEFAULT_CROSSOVER_FREQUENCY,
+    WAH_DEFAULT_MAX_FREQUENCY, WAH_DEFAULT_MIN_FREQUENCY,
+};
+use dsp::match_eq::{self, SpectrumAccumulator, ANALYSIS_NUM_BINS};
+use dsp::modulation::{self, EnvelopeFollower, NoteDivision};
+use dsp::multiband::{MultibandSaturation, MULTIBAND_MAX_BANDS, MULTIBAND_MIN_BANDS};
 use nih_plug::prelude::*;
+use parking_lot::{Mutex, RwLock};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
+/// The number of audio channels the plugin processes.
+pub const NUM_CHANNELS: u32 = 2;
+
+/// The FFT size for the linear-phase high cut. This results in 768 samples of latency, and the
+/// filter is still long enough to be accurate at the lowest cutoff frequency.
+const HIGH_CUT_FFT_SIZE: usize = 1024;
+const HIGH_CUT_FILTER_SIZE: usize = FftFirFilter::<HIGH_CUT_FFT_SIZE>::FILTER_SIZE;
+
+/// How many octaves a full envelope at an amount of 1 moves the UniVibe's rate.
+const ENVELOPE_RATE_OCTAVES: f32 = 3.0;
+/// How many octaves a full envelope at an amount of 1 moves the high cut.
+const ENVELOPE_HIGH_CUT_OCTAVES: f32 = 4.0;
+/// The envelope modulated high cut frequency is rounded to this many steps per octave, since
+/// every change means designing a new filter.
+const ENVELOPE_HIGH_CUT_STEPS_PER_OCTAVE: f32 = 24.0;
+
+/// The modulation effect at the start of the chain. The rate, depth, feedback and mix parameters
+/// are shared between all of them.
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum Effect {
+    #[name = "UniVibe"]
+    UniVibe,
+    #[name = "Phaser"]
+    Phaser,
+    #[name = "Flanger"]
+    Flanger,
+    #[name = "Chorus"]
+    Chorus,
+    #[name = "Tremolo"]
+    Tremolo,
+}
+
+/// Mirrors [`modulation::LfoShape`] for use in an [`EnumParam`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum LfoShape {
+    #[name = "Sine"]
+    Sine,
+    #[name = "Triangle"]
+    Triangle,
+    #[name = "Square"]
+    Square,
+    #[name = "Saw Up"]
+    SawUp,
+    #[name = "Saw Down"]
+    SawDown,
+}
+
+impl From<LfoShape> for modulation::LfoShape {
+    fn from(shape: LfoShape) -> Self {
+        match shape {
+            LfoShape::Sine => modulation::LfoShape::Sine,
+            LfoShape::Triangle => modulation::LfoShape::Triangle,
+            LfoShape::Square => modulation::LfoShape::Square,
+            LfoShape::SawUp => modulation::LfoShape::SawUp,
+            LfoShape::SawDown => modulation::LfoShape::SawDown,
+        }
+    }
+}
+
+/// Mirrors [`NoteDivision`] for use in an [`EnumParam`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum SyncDivision {
+    #[name = "1/1"]
+    Whole,
+    #[name = "1/2"]
+    Half,
+    #[name = "1/4"]
+    Quarter,
+    #[name = "1/8"]
+    Eighth,
+    #[name = "1/16"]
+    Sixteenth,
+    #[name = "1/4 Dotted"]
+    DottedQuarter,
+    #[name = "1/8 Dotted"]
+    DottedEighth,
+    #[name = "1/4 Triplet"]
+    QuarterTriplet,
+    #[name = "1/8 Triplet"]
+    EighthTriplet,
+}
+
+impl From<SyncDivision> for NoteDivision {
+    fn from(division: SyncDivision) -> Self {
+        match division {
+            SyncDivision::Whole => NoteDivision::Whole,
+            SyncDivision::Half => NoteDivision::Half,
+            SyncDivision::Quarter => NoteDivision::Quarter,
+            SyncDivision::Eighth => NoteDivision::Eighth,
+            SyncDivision::Sixteenth => NoteDivision::Sixteenth,
+            SyncDivision::DottedQuarter => NoteDivision::DottedQuarter,
+            SyncDivision::DottedEighth => NoteDivision::DottedEighth,
+            SyncDivision::QuarterTriplet => NoteDivision::QuarterTriplet,
+            SyncDivision::EighthTriplet => NoteDivision::EighthTriplet,
+        }
+    }
+}
+
+/// Mirrors [`effects::TremoloMode`] for use in an [`EnumParam`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum TremoloMode {
+    #[name = "Mono"]
+    Mono,
+    #[name = "Auto-Pan"]
+    AutoPan,
+    #[name = "Harmonic"]
+    Harmonic,
+}
+
+impl From<TremoloMode> for effects::TremoloMode {
+    fn from(mode: TremoloMode) -> Self {
+        match mode {
+            TremoloMode::Mono => effects::TremoloMode::Mono,
+            TremoloMode::AutoPan => effects::TremoloMode::AutoPan,
+            TremoloMode::Harmonic => effects::TremoloMode::Harmonic,
+        }
+    }
+}
+
+/// Mirrors [`effects::DelayTimeMode`] for use in an [`EnumParam`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum DelayTimeMode {
+    #[name = "Glide"]
+    Glide,
+    #[name = "Crossfade"]
+    Crossfade,
+}
+
+impl From<DelayTimeMode> for effects::DelayTimeMode {
+    fn from(mode: DelayTimeMode) -> Self {
+        match mode {
+            DelayTimeMode::Glide => effects::DelayTimeMode::Glide,
+            DelayTimeMode::Crossfade => effects::DelayTimeMode::Crossfade,
+        }
+    }
+}
+
+/// Mirrors [`wave_shapers::WaveShaper`] for use in an [`EnumParam`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum WaveShaper {
+    #[name = "Green Clipper"]
+    GreenClipper,
+    #[name = "Red Clipper"]
+    RedClipper,
+    #[name = "Hard Clipper"]
+    HardClipper,
+}
+
+impl From<WaveShaper> for wave_shapers::WaveShaper {
+    fn from(wave_shaper: WaveShaper) -> Self {
+        match wave_shaper {
+            WaveShaper::GreenClipper => wave_shapers::WaveShaper::GreenClipper,
+            WaveShaper::RedClipper => wave_shapers::WaveShaper::RedClipper,
+            WaveShaper::HardClipper => wave_shapers::WaveShaper::HardClipper,
+        }
+    }
+}
+
+/// Mirrors [`dynamics::DetectorMode`] for use in an [`EnumParam`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum DetectorMode {
+    #[name = "Peak"]
+    Peak,
+    #[name = "RMS"]
+    Rms,
+}
+
+impl From<DetectorMode> for dynamics::DetectorMode {
+    fn from(mode: DetectorMode) -> Self {
+        match mode {
+            DetectorMode::Peak => dynamics::DetectorMode::Peak,
+            DetectorMode::Rms => dynamics::DetectorMode::Rms,
+        }
+    }
+}
+
+/// Mirrors [`effects::WahMode`] for use in an [`EnumParam`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum WahMode {
+    #[name = "Manual"]
+    Manual,
+    #[name = "Envelope"]
+    Envelope,
+    #[name = "LFO"]
+    Lfo,
+}
+
+impl From<WahMode> for effects::WahMode {
+    fn from(mode: WahMode) -> Self {
+        match mode {
+            WahMode::Manual => effects::WahMode::Manual,
+            WahMode::Envelope => effects::WahMode::Envelope,
+            WahMode::Lfo => effects::WahMode::Lfo,
+        }
+    }
+}
+
+/// Where the noise gate sits in the chain relative to the multiband saturation, which is the
+/// drive. Before the drive the gate only sees the dry signal, while after the drive it also
+/// catches the hiss the drive and the stages in front of it add.
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum GatePosition {
+    /// At the input, before the match EQ, the compressor, the wah and the drive.
+    #[name = "Pre Drive"]
+    PreDrive,
+    /// After the drive, right before the modulation effects.
+    #[name = "Post Drive"]
+    PostDrive,
+}
+
+/// The parameter the envelope follower modulates.
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum EnvelopeTarget {
+    #[name = "UniVibe Rate"]
+    Rate,
+    #[name = "UniVibe Depth"]
+    Depth,
+    /// Only has an effect while the high cut is enabled.
+    #[name = "High Cut"]
+    HighCut,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum ReverbType {
+    #[name = "Plate"]
+    Plate,
+    #[name = "Spring"]
+    Spring,
+}
+
+/// Mirrors [`effects::SpringTank`] for use in an [`EnumParam`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq)]
+enum SpringTank {
+    #[name = "2 Springs"]
+    TwoSpring,
+    #[name = "3 Springs"]
+    ThreeSpring,
+}
+
+impl From<SpringTank> for effects::SpringTank {
+    fn from(tank: SpringTank) -> Self {
+        match tank {
+            SpringTank::TwoSpring => effects::SpringTank::TwoSpring,
+            SpringTank::ThreeSpring => effects::SpringTank::ThreeSpring,
+        }
+    }
+}
+
 #[derive(Params)]
 struct NihPluginParams {
+    #[id = "effect"]
+    pub effect: EnumParam<Effect>,
+
     #[id = "rate"]
     pub rate: FloatParam,
 
+    /// Replaces the rate with the note length set by `sync_division` when the host provides a
+    /// tempo.
+    #[id = "sync"]
+    pub tempo_sync: BoolParam,
+
+    #[id = "sync_division"]
+    pub sync_division: EnumParam<SyncDivision>,
+
+    /// The LFO shape for the UniVibe, the tremolo and the wah.
+    #[id = "lfo_shape"]
+    pub lfo_shape: EnumParam<LfoShape>,
+
     #[id = "depth"]
     pub depth: FloatParam,
 
@@ -20,12 +303,446 @@ struct NihPluginParams {
 
     #[id = "mix"]
     pub mix: FloatParam,
+
+    #[id = "phaser_stages"]
+    pub phaser_stages: IntParam,
+
+    #[id = "phaser_min"]
+    pub phaser_min_frequency: FloatParam,
+
+    #[id = "phaser_max"]
+    pub phaser_max_frequency: FloatParam,
+
+    /// Inverts the phaser's feedback, which moves the resonant peaks to where the notches were.
+    #[id = "phaser_invert"]
+    pub phaser_invert_feedback: BoolParam,
+
+    /// The phase offset between the left and right channels' LFOs.
+    #[id = "phaser_stereo"]
+    pub phaser_stereo_offset: FloatParam,
+
+    #[id = "flanger_manual"]
+    pub flanger_manual_delay: FloatParam,
+
+    /// Inverts the flanger's regeneration. The amount is set with the shared feedback parameter.
+    #[id = "flanger_invert"]
+    pub flanger_invert_regeneration: BoolParam,
+
+    #[id = "flanger_tz"]
+    pub flanger_through_zero: BoolParam,
+
+    #[id = "chorus_voices"]
+    pub chorus_voices: IntParam,
+
+    /// How far the chorus voices are panned apart.
+    #[id = "chorus_spread"]
+    pub chorus_spread: FloatParam,
+
+    /// The amount of random variation in the chorus voices' delay times.
+    #[id = "chorus_drift"]
+    pub chorus_drift: FloatParam,
+
+    /// Adds the companding and filtering of an analog bucket brigade chorus.
+    #[id = "chorus_bbd"]
+    pub chorus_bbd: BoolParam,
+
+    #[id = "tremolo_mode"]
+    pub tremolo_mode: EnumParam<TremoloMode>,
+
+    /// Where the harmonic tremolo splits the signal.
+    #[id = "tremolo_xover"]
+    pub tremolo_crossover_frequency: FloatParam,
+
+    /// Enables the envelope follower, which listens to the signal going into the modulation
+    /// effects. The modulation is applied on top of the target parameter's own value.
+    #[id = "env"]
+    pub envelope_enabled: BoolParam,
+
+    #[id = "env_target"]
+    pub envelope_target: EnumParam<EnvelopeTarget>,
+
+    /// How far and in which direction the envelope moves the target parameter.
+    #[id = "env_amount"]
+    pub envelope_amount: FloatParam,
+
+    /// Gain applied to the envelope follower's input. The envelope is clipped at unity, so this
+    /// sets how hard the strings need to be hit for the full modulation.
+    #[id = "env_sensitivity"]
+    pub envelope_sensitivity: FloatParam,
+
+    #[id = "env_detector"]
+    pub envelope_detector: EnumParam<DetectorMode>,
+
+    #[id = "env_attack"]
+    pub envelope_attack: FloatParam,
+
+    #[id = "env_release"]
+    pub envelope_release: FloatParam,
+
+    #[id = "cabinet"]
+    pub cabinet_enabled: BoolParam,
+
+    /// Enables the delay after the cabinet.
+    #[id = "delay"]
+    pub delay_enabled: BoolParam,
+
+    /// Replaces the delay time with the note length set by `delay_division` when the host
+    /// provides a tempo.
+    #[id = "delay_sync"]
+    pub delay_sync: BoolParam,
+
+    #[id = "delay_division"]
+    pub delay_division: EnumParam<SyncDivision>,
+
+    #[id = "delay_time"]
+    pub delay_time: FloatParam,
+
+    /// How the delay moves to a new time.
+    #[id = "delay_time_mode"]
+    pub delay_time_mode: EnumParam<DelayTimeMode>,
+
+    #[id = "delay_feedback"]
+    pub delay_feedback: FloatParam,
+
+    #[id = "delay_mix"]
+    pub delay_mix: FloatParam,
+
+    /// The high-pass filter in the delay's feedback path.
+    #[id = "delay_low_cut"]
+    pub delay_low_cut: FloatParam,
+
+    /// The low-pass filter in the delay's feedback path.
+    #[id = "delay_high_cut"]
+    pub delay_high_cut: FloatParam,
+
+    /// Saturates the delay's feedback path with `delay_curve`.
+    #[id = "delay_saturation"]
+    pub delay_saturation: BoolParam,
+
+    #[id = "delay_curve"]
+    pub delay_curve: EnumParam<WaveShaper>,
+
+    #[id = "delay_drive"]
+    pub delay_drive: FloatParam,
+
+    #[id = "delay_wow_flutter"]
+    pub delay_wow_flutter: FloatParam,
+
+    #[id = "delay_ping_pong"]
+    pub delay_ping_pong: BoolParam,
+
+    /// Enables the reverb after the delay.
+    #[id = "reverb"]
+    pub reverb_enabled: BoolParam,
+
+    #[id = "reverb_type"]
+    pub reverb_type: EnumParam<ReverbType>,
+
+    #[id = "reverb_mix"]
+    pub reverb_mix: FloatParam,
+
+    #[id = "reverb_predelay"]
+    pub reverb_pre_delay: FloatParam,
+
+    #[id = "reverb_size"]
+    pub reverb_size: FloatParam,
+
+    #[id = "reverb_decay"]
+    pub reverb_decay: FloatParam,
+
+    #[id = "reverb_damping"]
+    pub reverb_damping: FloatParam,
+
+    #[id = "spring_tank"]
+    pub spring_tank: EnumParam<SpringTank>,
+
+    #[id = "spring_tension"]
+    pub spring_tension: FloatParam,
+
+    /// The spring reverb's decay time to -60 dB.
+    #[id = "spring_decay"]
+    pub spring_decay: FloatParam,
+
+    #[id = "spring_tone"]
+    pub spring_tone: FloatParam,
+
+    /// Enables the linear-phase high cut at the end of the chain. This adds latency, so it's off
+    /// by default.
+    #[id = "high_cut"]
+    pub high_cut_enabled: BoolParam,
+
+    #[id = "high_cut_freq"]
+    pub high_cut_frequency: FloatParam,
+
+    /// Applies the learned match EQ curve to the input. Like the high cut, this adds latency.
+    #[id = "match_eq"]
+    pub match_eq_enabled: BoolParam,
+
+    /// While enabled, the long-term spectra of the input and the reference are analysed. The
+    /// match EQ curve is computed when this is disabled again.
+    #[id = "match_learn"]
+    pub match_eq_learn: BoolParam,
+
+    #[id = "match_smooth"]
+    pub match_eq_smoothing: FloatParam,
+
+    /// Enables the compressor after the match EQ. The lookahead adds latency.
+    #[id = "comp"]
+    pub compressor_enabled: BoolParam,
+
+    #[id = "comp_threshold"]
+    pub compressor_threshold: FloatParam,
+
+    #[id = "comp_ratio"]
+    pub compressor_ratio: FloatParam,
+
+    #[id = "comp_knee"]
+    pub compressor_knee: FloatParam,
+
+    #[id = "comp_attack"]
+    pub compressor_attack: FloatParam,
+
+    #[id = "comp_release"]
+    pub compressor_release: FloatParam,
+
+    #[id = "comp_detector"]
+    pub compressor_detector: EnumParam<DetectorMode>,
+
+    #[id = "comp_makeup"]
+    pub compressor_makeup_gain: FloatParam,
+
+    #[id = "comp_lookahead"]
+    pub compressor_lookahead: FloatParam,
+
+    /// Makes the compressor's detector listen to the sidechain input. This is the same aux input
+    /// the match EQ learns its reference from, so the two share whatever is routed there.
+    #[id = "comp_sidechain"]
+    pub compressor_sidechain: BoolParam,
+
+    #[id = "comp_link"]
+    pub compressor_stereo_link: FloatParam,
+
+    /// Enables the wah after the compressor.
+    #[id = "wah"]
+    pub wah_enabled: BoolParam,
+
+    #[id = "wah_mode"]
+    pub wah_mode: EnumParam<WahMode>,
+
+    /// The pedal position in manual mode, from heel to toe. Map a host expression lane to this.
+    #[id = "wah_position"]
+    pub wah_position: FloatParam,
+
+    #[id = "wah_min"]
+    pub wah_min_frequency: FloatParam,
+
+    #[id = "wah_max"]
+    pub wah_max_frequency: FloatParam,
+
+    #[id = "wah_q"]
+    pub wah_resonance: FloatParam,
+
+    /// How quickly the filter follows the pedal, the envelope or the LFO.
+    #[id = "wah_response"]
+    pub wah_response: FloatParam,
+
+    /// How far the envelope opens the filter in envelope mode.
+    #[id = "wah_sensitivity"]
+    pub wah_sensitivity: FloatParam,
+
+    #[id = "wah_rate"]
+    pub wah_rate: FloatParam,
+
+    /// Enables the multiband saturation after the wah. The linear-phase crossover adds latency.
+    #[id = "mb"]
+    pub multiband_enabled: BoolParam,
+
+    #[id = "mb_num_bands"]
+    pub multiband_num_bands: IntParam,
+
+    /// The crossover frequencies. These are sorted before use, and only the lowest
+    /// `multiband_num_bands - 1` of them are used.
+    #[id = "mb_xover_low"]
+    pub multiband_low_crossover: FloatParam,
+
+    #[id = "mb_xover_mid"]
+    pub multiband_mid_crossover: FloatParam,
+
+    #[id = "mb_xover_high"]
+    pub multiband_high_crossover: FloatParam,
+
+    #[nested(array, group = "Multiband Band")]
+    pub multiband_bands: [MultibandBandParams; MULTIBAND_MAX_BANDS],
+
+    /// Enables the noise gate. The lookahead adds latency.
+    #[id = "gate"]
+    pub gate_enabled: BoolParam,
+
+    #[id = "gate_position"]
+    pub gate_position: EnumParam<GatePosition>,
+
+    #[id = "gate_open"]
+    pub gate_open_threshold: FloatParam,
+
+    /// The level the signal needs to drop below before the gate closes again. This is clamped to
+    /// the open threshold.
+    #[id = "gate_close"]
+    pub gate_close_threshold: FloatParam,
+
+    #[id = "gate_hold"]
+    pub gate_hold: FloatParam,
+
+    #[id = "gate_attack"]
+    pub gate_attack: FloatParam,
+
+    #[id = "gate_release"]
+    pub gate_release: FloatParam,
+
+    #[id = "gate_range"]
+    pub gate_range: FloatParam,
+
+    /// The expansion ratio below the open threshold. The maximum acts as a hard gate.
+    #[id = "gate_ratio"]
+    pub gate_ratio: FloatParam,
+
+    /// The cutoff of the high-pass filter in front of the gate's detector.
+    #[id = "gate_hpf"]
+    pub gate_sidechain_high_pass: FloatParam,
+
+    #[id = "gate_lookahead"]
+    pub gate_lookahead: FloatParam,
+
+    /// Enables the true peak limiter at the very end of the chain, as a safety stage. Its
+    /// lookahead adds latency.
+    #[id = "limiter"]
+    pub limiter_enabled: BoolParam,
+
+    /// The true peak ceiling in dBTP.
+    #[id = "limiter_ceiling"]
+    pub limiter_ceiling: FloatParam,
+
+    #[id = "limiter_release"]
+    pub limiter_release: FloatParam,
+
+    /// The path to the cabinet impulse response WAV file. There is no GUI to change this yet, so
+    /// for now it can only be set through the plugin's state.
+    #[persist = "ir-path"]
+    pub ir_path: Arc<RwLock<Option<String>>>,
+
+    /// An optional WAV file to use as the match EQ reference. When this is not set, the sidechain
+    /// input is used instead. Like `ir_path`, this can only be set through the plugin's state.
+    #[persist = "match-reference-path"]
+    pub match_reference_path: Arc<RwLock<Option<String>>>,
+
+    /// The learned long-term average power spectra of the input and the reference, stored so the
+    /// match EQ can be redesigned when the smoothing or the sample rate changes.
+    #[persist = "match-input-spectrum"]
+    pub match_input_spectrum: Arc<RwLock<Vec<f32>>>,
+    #[persist = "match-reference-spectrum"]
+    pub match_reference_spectrum: Arc<RwLock<Vec<f32>>>,
+    /// The sample rate the spectra above were analysed at.
+    #[persist = "match-analysis-rate"]
+    pub match_analysis_sample_rate: Arc<RwLock<f32>>,
+}
+
+/// Tasks that run on nih_plug's background thread.
+enum PluginTask {
+    /// (Re)load the impulse response from `NihPluginParams::ir_path`.
+    LoadImpulseResponse,
+    /// Store the spectra gathered while learning, and then design a new match EQ filter from them.
+    LearnMatchEq,
+    /// Design a new match EQ filter from the stored spectra.
+    DesignMatchEq,
+}
+
+/// Hands cabinets between the background thread and the audio thread so that the audio thread
+/// never allocates or deallocates one.
+#[derive(Default)]
+struct CabinetExchange {
+    /// A freshly loaded cabinet waiting to be picked up by the audio thread.
+    loaded: Option<Cabinet>,
+    /// The cabinet it replaced, left here so it gets dropped on the background thread. The audio
+    /// thread only picks up a new cabinet while this is empty.
+    retired: Option<Cabinet>,
+}
+
+/// The analysers for the match EQ's input and reference signals. These live behind a mutex so the
+/// background thread can read out the results once learning stops.
+#[derive(Default)]
+struct MatchEqAnalyzers {
+    input: SpectrumAccumulator,
+    reference: SpectrumAccumulator,
+}
+
+/// Hands newly designed match EQ coefficients to the audio thread. The coefficients are overwritten
+/// in place, so nothing needs to be allocated or freed on the audio thread.
+#[derive(Default)]
+struct MatchEqExchange {
+    coefficients: FirCoefficients<FILTER_SIZE>,
+    /// Set when `coefficients` contains coefficients the audio thread hasn't picked up yet.
+    updated: bool,
 }
 
 struct NihPlugin {
     params: Arc<NihPluginParams>,
     sample_rate: f32,
     univibe: UniVibe,
+    phaser: Phaser,
+    flanger: Flanger,
+    chorus: Chorus,
+    tremolo: Tremolo,
+    envelope_follower: EnvelopeFollower,
+    cabinet: Option<Cabinet>,
+    cabinet_exchange: Arc<Mutex<CabinetExchange>>,
+    /// The sample rate impulse responses get resampled to, stored as `f32` bits so the background
+    /// thread can read it.
+    shared_sample_rate: Arc<AtomicU32>,
+    /// Set when the impulse response needs to be loaded at the start of the next process call.
+    cabinet_load_requested: bool,
+    delay: Delay,
+    /// Whether the delay was enabled during the last process call, used to clear it when it gets
+    /// enabled.
+    delay_active: bool,
+    plate_reverb: PlateReverb,
+    spring_reverb: SpringReverb,
+    /// The reverb that was enabled during the last process call, used to clear the reverb's state
+    /// when it gets enabled or switched.
+    active_reverb: Option<ReverbType>,
+    high_cut: BufferedFftFirFilter<HIGH_CUT_FFT_SIZE>,
+    /// Whether the high cut was enabled during the last process call, used to clear its buffers
+    /// when it gets toggled.
+    high_cut_active: bool,
+    /// The cutoff frequency the high cut's current coefficients were designed for.
+    high_cut_designed_frequency: f32,
+    match_eq: BufferedFftFirFilter<FFT_SIZE>,
+    match_eq_analyzers: Arc<Mutex<MatchEqAnalyzers>>,
+    match_eq_exchange: Arc<Mutex<MatchEqExchange>>,
+    /// Whether the match EQ was enabled during the last process call.
+    match_eq_active: bool,
+    /// Whether the analysers are currently gathering spectra.
+    match_eq_learning: bool,
+    /// The smoothing the last match EQ design was requested with.
+    match_eq_designed_smoothing: f32,
+    /// Set when the match EQ needs to be redesigned at the start of the next process call.
+    match_eq_design_requested: bool,
+    compressor: Compressor,
+    /// Whether the compressor was enabled during the last process call. Its lookahead only counts
+    /// towards the latency while it's enabled.
+    compressor_active: bool,
+    wah: Wah,
+    /// Whether the wah was enabled during the last process call.
+    wah_active: bool,
+    multiband: MultibandSaturation,
+    /// Whether the multiband saturation was enabled during the last process call.
+    multiband_active: bool,
+    gate: NoiseGate,
+    /// The position of the gate during the last process call, if it was enabled.
+    active_gate: Option<GatePosition>,
+    limiter: TruePeakLimiter,
+    /// Whether the limiter was enabled during the last process call.
+    limiter_active: bool,
+    /// The latency last reported to the host.
+    reported_latency: u32,
 }
 
 impl Default for NihPlugin {
@@ -34,6 +751,41 @@ impl Default for NihPlugin {
             params: Arc::new(NihPluginParams::default()),
             sample_rate: 44100.0,
             univibe: UniVibe::new(44100.0, 2), // Default number of stages
+            phaser: Phaser::new(44100.0, NUM_CHANNELS as usize),
+            flanger: Flanger::new(44100.0, NUM_CHANNELS as usize),
+            chorus: Chorus::new(44100.0, NUM_CHANNELS as usize),
+            tremolo: Tremolo::new(44100.0, NUM_CHANNELS as usize),
+            envelope_follower: EnvelopeFollower::new(44100.0),
+            cabinet: None,
+            cabinet_exchange: Arc::new(Mutex::new(CabinetExchange::default())),
+            shared_sample_rate: Arc::new(AtomicU32::new(44100.0f32.to_bits())),
+            cabinet_load_requested: false,
+            delay: Delay::new(44100.0),
+            delay_active: false,
+            plate_reverb: PlateReverb::new(44100.0),
+            spring_reverb: SpringReverb::new(44100.0, NUM_CHANNELS as usize),
+            active_reverb: None,
+            high_cut: BufferedFftFirFilter::new(NUM_CHANNELS as usize),
+            high_cut_active: false,
+            high_cut_designed_frequency: 0.0,
+            match_eq: BufferedFftFirFilter::new(NUM_CHANNELS as usize),
+            match_eq_analyzers: Arc::new(Mutex::new(MatchEqAnalyzers::default())),
+            match_eq_exchange: Arc::new(Mutex::new(MatchEqExchange::default())),
+            match_eq_active: false,
+            match_eq_learning: false,
+            match_eq_designed_smoothing: 0.0,
+            match_eq_design_requested: false,
+            compressor: Compressor::new(44100.0, NUM_CHANNELS as usize),
+            compressor_active: false,
+            wah: Wah::new(44100.0, NUM_CHANNELS as usize),
+            wah_active: false,
+            multiband: MultibandSaturation::new(44100.0, NUM_CHANNELS as usize),
+            multiband_active: false,
+            gate: NoiseGate::new(44100.0, NUM_CHANNELS as usize),
+            active_gate: None,
+            limiter: TruePeakLimiter::new(44100.0, NUM_CHANNELS as usize),
+            limiter_active: false,
+            reported_latency: 0,
         }
     }
 }
@@ -41,11 +793,605 @@ impl Default for NihPlugin {
 impl Default for NihPluginParams {
     fn default() -> Self {
         Self {
+            effect: EnumParam::new("Effect", Effect::UniVibe),
             rate: FloatParam::new("Rate", 0.8, FloatRange::Linear { min: 0.1, max: 5.0 }),
+            tempo_sync: BoolParam::new("Tempo Sync", false),
+            sync_division: EnumParam::new("Sync Division", SyncDivision::Quarter),
+            lfo_shape: EnumParam::new("LFO Shape", LfoShape::Sine),
             depth: FloatParam::new("Depth", 0.7, FloatRange::Linear { min: 0.0, max: 1.0 }),
             num_stages: IntParam::new("Stages", 2, IntRange::Linear { min: 1, max: 4 }),
             feedback: FloatParam::new("Feedback", 0.5, FloatRange::Linear { min: 0.0, max: 0.9 }),
             mix: FloatParam::new("Mix", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            phaser_stages: IntParam::new(
+                "Phaser Stages",
+                6,
+                IntRange::Linear {
+                    min: 2,
+                    max: PHASER_MAX_STAGES as i32,
+                },
+            ),
+            phaser_min_frequency: FloatParam::new(
+                "Phaser Min Frequency",
+                200.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+            phaser_max_frequency: FloatParam::new(
+                "Phaser Max Frequency",
+                4000.0,
+                FloatRange::Skewed {
+                    min: 200.0,
+                    max: 16000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+            phaser_invert_feedback: BoolParam::new("Phaser Invert Feedback", false),
+            phaser_stereo_offset: FloatParam::new(
+                "Phaser Stereo Offset",
+                90.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 180.0,
+                },
+            )
+            .with_unit("°"),
+            flanger_manual_delay: FloatParam::new(
+                "Flanger Manual",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.1,
+                    max: FLANGER_MAX_MANUAL_DELAY_MS,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            flanger_invert_regeneration: BoolParam::new("Flanger Invert Regeneration", false),
+            flanger_through_zero: BoolParam::new("Flanger Through Zero", false),
+            chorus_voices: IntParam::new(
+                "Chorus Voices",
+                3,
+                IntRange::Linear {
+                    min: 1,
+                    max: CHORUS_MAX_VOICES as i32,
+                },
+            ),
+            chorus_spread: FloatParam::new(
+                "Chorus Spread",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            chorus_drift: FloatParam::new(
+                "Chorus Drift",
+                0.3,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            chorus_bbd: BoolParam::new("Chorus BBD", false),
+            tremolo_mode: EnumParam::new("Tremolo Mode", TremoloMode::Mono),
+            tremolo_crossover_frequency: FloatParam::new(
+                "Tremolo Crossover",
+                TREMOLO_DEFAULT_CROSSOVER_FREQUENCY,
+                FloatRange::Skewed {
+                    min: 100.0,
+                    max: 4000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+            envelope_enabled: BoolParam::new("Envelope", false),
+            envelope_target: EnumParam::new("Envelope Target", EnvelopeTarget::Rate),
+            envelope_amount: FloatParam::new(
+                "Envelope Amount",
+                0.5,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            ),
+            envelope_sensitivity: FloatParam::new(
+                "Envelope Sensitivity",
+                6.0,
+                FloatRange::Linear {
+                    min: -12.0,
+                    max: 36.0,
+                },
+            )
+            .with_unit(" dB"),
+            envelope_detector: EnumParam::new("Envelope Detector", DetectorMode::Peak),
+            envelope_attack: FloatParam::new(
+                "Envelope Attack",
+                10.0,
+                FloatRange::Skewed {
+                    min: 0.1,
+                    max: 200.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            envelope_release: FloatParam::new(
+                "Envelope Release",
+                200.0,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            cabinet_enabled: BoolParam::new("Cabinet", true),
+            delay_enabled: BoolParam::new("Delay", false),
+            delay_sync: BoolParam::new("Delay Sync", false),
+            delay_division: EnumParam::new("Delay Division", SyncDivision::DottedEighth),
+            delay_time: FloatParam::new(
+                "Delay Time",
+                350.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: DELAY_MAX_TIME_MS,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            delay_time_mode: EnumParam::new("Delay Time Mode", DelayTimeMode::Glide),
+            delay_feedback: FloatParam::new(
+                "Delay Feedback",
+                0.4,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            delay_mix: FloatParam::new("Delay Mix", 0.3, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            delay_low_cut: FloatParam::new(
+                "Delay Low Cut",
+                80.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+            delay_high_cut: FloatParam::new(
+                "Delay High Cut",
+                5000.0,
+                FloatRange::Skewed {
+                    min: 1000.0,
+                    max: 20000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+            delay_saturation: BoolParam::new("Delay Saturation", true),
+            delay_curve: EnumParam::new("Delay Curve", WaveShaper::HardClipper),
+            delay_drive: FloatParam::new(
+                "Delay Drive",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB"),
+            delay_wow_flutter: FloatParam::new(
+                "Delay Wow/Flutter",
+                0.2,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            delay_ping_pong: BoolParam::new("Delay Ping-Pong", false),
+            reverb_enabled: BoolParam::new("Reverb", false),
+            reverb_type: EnumParam::new("Reverb Type", ReverbType::Plate),
+            reverb_mix: FloatParam::new(
+                "Reverb Mix",
+                0.25,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            reverb_pre_delay: FloatParam::new(
+                "Reverb Pre-Delay",
+                10.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: PLATE_MAX_PRE_DELAY_MS,
+                },
+            )
+            .with_unit(" ms"),
+            reverb_size: FloatParam::new(
+                "Reverb Size",
+                1.0,
+                FloatRange::Linear {
+                    min: PLATE_MIN_SIZE,
+                    max: PLATE_MAX_SIZE,
+                },
+            ),
+            reverb_decay: FloatParam::new(
+                "Reverb Decay",
+                0.5,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: PLATE_MAX_DECAY,
+                },
+            ),
+            reverb_damping: FloatParam::new(
+                "Reverb Damping",
+                0.3,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            spring_tank: EnumParam::new("Spring Tank", SpringTank::TwoSpring),
+            spring_tension: FloatParam::new(
+                "Spring Tension",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            spring_decay: FloatParam::new(
+                "Spring Decay",
+                2.0,
+                FloatRange::Skewed {
+                    min: SPRING_MIN_DECAY_SECONDS,
+                    max: SPRING_MAX_DECAY_SECONDS,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" s"),
+            spring_tone: FloatParam::new(
+                "Spring Tone",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            high_cut_enabled: BoolParam::new("High Cut", false),
+            high_cut_frequency: FloatParam::new(
+                "High Cut Frequency",
+                8000.0,
+                FloatRange::Skewed {
+                    min: 1000.0,
+                    max: 20000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+            match_eq_enabled: BoolParam::new("Match EQ", false),
+            match_eq_learn: BoolParam::new("Match EQ Learn", false),
+            match_eq_smoothing: FloatParam::new(
+                "Match EQ Smoothing",
+                1.0 / 3.0,
+                FloatRange::Skewed {
+                    min: 1.0 / 24.0,
+                    max: 2.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" oct"),
+            compressor_enabled: BoolParam::new("Compressor", false),
+            compressor_threshold: FloatParam::new(
+                "Compressor Threshold",
+                -18.0,
+                FloatRange::Linear {
+                    min: -60.0,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB"),
+            compressor_ratio: FloatParam::new(
+                "Compressor Ratio",
+                4.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            ),
+            compressor_knee: FloatParam::new(
+                "Compressor Knee",
+                6.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB"),
+            compressor_attack: FloatParam::new(
+                "Compressor Attack",
+                10.0,
+                FloatRange::Skewed {
+                    min: 0.1,
+                    max: 100.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            compressor_release: FloatParam::new(
+                "Compressor Release",
+                100.0,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            compressor_detector: EnumParam::new("Compressor Detector", DetectorMode::Peak),
+            compressor_makeup_gain: FloatParam::new(
+                "Compressor Makeup Gain",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB"),
+            compressor_lookahead: FloatParam::new(
+                "Compressor Lookahead",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: COMPRESSOR_MAX_LOOKAHEAD_MS,
+                },
+            )
+            .with_unit(" ms"),
+            compressor_sidechain: BoolParam::new("Compressor Sidechain", false),
+            compressor_stereo_link: FloatParam::new(
+                "Compressor Stereo Link",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            wah_enabled: BoolParam::new("Wah", false),
+            wah_mode: EnumParam::new("Wah Mode", WahMode::Manual),
+            wah_position: FloatParam::new(
+                "Wah Position",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            wah_min_frequency: FloatParam::new(
+                "Wah Min Frequency",
+                WAH_DEFAULT_MIN_FREQUENCY,
+                FloatRange::Skewed {
+                    min: 100.0,
+                    max: 1000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+            wah_max_frequency: FloatParam::new(
+                "Wah Max Frequency",
+                WAH_DEFAULT_MAX_FREQUENCY,
+                FloatRange::Skewed {
+                    min: 1000.0,
+                    max: 5000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+            wah_resonance: FloatParam::new(
+                "Wah Resonance",
+                5.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            ),
+            wah_response: FloatParam::new(
+                "Wah Response",
+                20.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            wah_sensitivity: FloatParam::new(
+                "Wah Sensitivity",
+                12.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 36.0,
+                },
+            )
+            .with_unit(" dB"),
+            wah_rate: FloatParam::new(
+                "Wah Rate",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.05,
+                    max: 10.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+            multiband_enabled: BoolParam::new("Multiband", false),
+            multiband_num_bands: IntParam::new(
+                "Multiband Bands",
+                3,
+                IntRange::Linear {
+                    min: MULTIBAND_MIN_BANDS as i32,
+                    max: MULTIBAND_MAX_BANDS as i32,
+                },
+            ),
+            multiband_low_crossover: FloatParam::new(
+                "Multiband Low Crossover",
+                200.0,
+                FloatRange::Skewed {
+                    min: 40.0,
+                    max: 12000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+            multiband_mid_crossover: FloatParam::new(
+                "Multiband Mid Crossover",
+                1000.0,
+                FloatRange::Skewed {
+                    min: 40.0,
+                    max: 12000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+            multiband_high_crossover: FloatParam::new(
+                "Multiband High Crossover",
+                4000.0,
+                FloatRange::Skewed {
+                    min: 40.0,
+                    max: 12000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+            multiband_bands: std::array::from_fn(MultibandBandParams::new),
+            gate_enabled: BoolParam::new("Gate", false),
+            gate_position: EnumParam::new("Gate Position", GatePosition::PreDrive),
+            gate_open_threshold: FloatParam::new(
+                "Gate Open Threshold",
+                -50.0,
+                FloatRange::Linear {
+                    min: -90.0,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB"),
+            gate_close_threshold: FloatParam::new(
+                "Gate Close Threshold",
+                -56.0,
+                FloatRange::Linear {
+                    min: -90.0,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB"),
+            gate_hold: FloatParam::new(
+                "Gate Hold",
+                50.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            gate_attack: FloatParam::new(
+                "Gate Attack",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.01,
+                    max: 50.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            gate_release: FloatParam::new(
+                "Gate Release",
+                100.0,
+                FloatRange::Skewed {
+                    min: 5.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            gate_range: FloatParam::new(
+                "Gate Range",
+                -80.0,
+                FloatRange::Linear {
+                    min: -80.0,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB"),
+            gate_ratio: FloatParam::new(
+                "Gate Ratio",
+                100.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 100.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            ),
+            gate_sidechain_high_pass: FloatParam::new(
+                "Gate Sidechain High Pass",
+                80.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+            gate_lookahead: FloatParam::new(
+                "Gate Lookahead",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: GATE_MAX_LOOKAHEAD_MS,
+                },
+            )
+            .with_unit(" ms"),
+            limiter_enabled: BoolParam::new("Limiter", false),
+            limiter_ceiling: FloatParam::new(
+                "Limiter Ceiling",
+                -1.0,
+                FloatRange::Linear {
+                    min: -12.0,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dBTP"),
+            limiter_release: FloatParam::new(
+                "Limiter Release",
+                100.0,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: 1000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            ir_path: Arc::new(RwLock::new(None)),
+            match_reference_path: Arc::new(RwLock::new(None)),
+            match_input_spectrum: Arc::new(RwLock::new(Vec::new())),
+            match_reference_spectrum: Arc::new(RwLock::new(Vec::new())),
+            match_analysis_sample_rate: Arc::new(RwLock::new(44100.0)),
+        }
+    }
+}
+
+/// The settings for a single band of the multiband saturation.
+#[derive(Params)]
+struct MultibandBandParams {
+    #[id = "curve"]
+    pub curve: EnumParam<WaveShaper>,
+
+    #[id = "drive"]
+    pub drive: FloatParam,
+
+    #[id = "output"]
+    pub output_gain: FloatParam,
+}
+
+impl MultibandBandParams {
+    fn new(band_idx: usize) -> Self {
+        let band_number = band_idx + 1;
+
+        Self {
+            curve: EnumParam::new(format!("Band {band_number} Curve"), WaveShaper::HardClipper),
+            drive: FloatParam::new(
+                format!("Band {band_number} Drive"),
+                6.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 36.0,
+                },
+            )
+            .with_unit(" dB"),
+            output_gain: FloatParam::new(
+                format!("Band {band_number} Output"),
+                0.0,
+                FloatRange::Linear {
+                    min: -36.0,
+                    max: 12.0,
+                },
+            )
+            .with_unit(" dB"),
         }
     }
 }
@@ -57,61 +1403,752 @@ impl Plugin for NihPlugin {
     const EMAIL: &'static str = "hello@kevontheweb.net";
     const VERSION: &'static str = env!("CARGO_PKG_VERSION");
     const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
+        main_input_channels: NonZeroU32::new(NUM_CHANNELS),
+        main_output_channels: NonZeroU32::new(NUM_CHANNELS),
+        // Used as the match EQ's reference and as the compressor's sidechain
+        aux_input_ports: &[new_nonzero_u32(NUM_CHANNELS)],
+        names: PortNames {
+            aux_inputs: &["Sidechain"],
+            ..PortNames::const_default()
+        },
         ..AudioIOLayout::const_default()
     }];
     const MIDI_INPUT: MidiConfig = MidiConfig::None;
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
     type SysExMessage = ();
-    type BackgroundTask = ();
+    type BackgroundTask = PluginTask;
 
     fn params(&self) -> Arc<dyn Params> {
         self.params.clone()
     }
 
+    fn task_executor(&mut self) -> TaskExecutor<Self> {
+        let params = self.params.clone();
+        let cabinet_exchange = self.cabinet_exchange.clone();
+        let shared_sample_rate = self.shared_sample_rate.clone();
+        let match_eq_analyzers = self.match_eq_analyzers.clone();
+        let match_eq_exchange = self.match_eq_exchange.clone();
+
+        Box::new(move |task| match task {
+            PluginTask::LoadImpulseResponse => {
+                let Some(path) = params.ir_path.read().clone() else {
+                    return;
+                };
+                let sample_rate = f32::from_bits(shared_sample_rate.load(Ordering::Relaxed));
+                match Cabinet::load(&path, sample_rate, NUM_CHANNELS as usize) {
+                    Ok(cabinet) => {
+                        // Dropping the retired cabinet makes room for the one the new cabinet
+                        // is going to replace
+                        let mut exchange = cabinet_exchange.lock();
+                        exchange.retired = None;
+                        exchange.loaded = Some(cabinet);
+                    }
+                    Err(err) => nih_error!("Could not load impulse response '{path}': {err}"),
+                }
+            }
+            PluginTask::LearnMatchEq => {
+                let sample_rate = f32::from_bits(shared_sample_rate.load(Ordering::Relaxed));
+                let mut input_spectrum = vec![0.0; ANALYSIS_NUM_BINS];
+                let mut reference_spectrum = vec![0.0; ANALYSIS_NUM_BINS];
+                {
+                    let analyzers = match_eq_analyzers.lock();
+                    analyzers.input.average_power(&mut input_spectrum);
+                    analyzers.reference.average_power(&mut reference_spectrum);
+                }
+
+                let reference_path = params.match_reference_path.read().clone();
+                if let Some(path) = reference_path {
+                    // This isn't an impulse response, but the loader works for any mono or stereo
+                    // WAV file
+                    match ImpulseResponse::load_wav(&path) {
+                        Ok(reference) => {
+                            let reference = reference.resample(sample_rate);
+                            let num_channels = reference.channels.len() as f32;
+                            let mono: Vec<f32> = (0..reference.channels[0].len())
+                                .map(|idx| {
+                                    let sum: f32 =
+                                        reference.channels.iter().map(|channel| channel[idx]).sum();
+                                    sum / num_channels
+                                })
+                                .collect();
+                            reference_spectrum = SpectrumAccumulator::analyze(&mono);
+                        }
+                        Err(err) => {
+                            nih_error!("Could not load match EQ reference '{path}': {err}");
+                            return;
+                        }
+                    }
+                }
+
+                if input_spectrum.iter().all(|power| *power == 0.0)
+                    || reference_spectrum.iter().all(|power| *power == 0.0)
+                {
+                    nih_log!("Not enough audio to learn a match EQ curve from");
+                    return;
+                }
+
+                *params.match_input_spectrum.write() = input_spectrum;
+                *params.match_reference_spectrum.write() = reference_spectrum;
+                *params.match_analysis_sample_rate.write() = sample_rate;
+                design_match_eq(&params, sample_rate, &match_eq_exchange);
+            }
+            PluginTask::DesignMatchEq => {
+                let sample_rate = f32::from_bits(shared_sample_rate.load(Ordering::Relaxed));
+                design_match_eq(&params, sample_rate, &match_eq_exchange);
+            }
+        })
+    }
+
     fn initialize(
         &mut self,
         _audio_io_layout: &AudioIOLayout,
         buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
         self.sample_rate = buffer_config.sample_rate as f32;
         self.univibe.set_sample_rate(self.sample_rate);
+        self.phaser.set_sample_rate(self.sample_rate);
+        self.flanger.set_sample_rate(self.sample_rate);
+        self.chorus.set_sample_rate(self.sample_rate);
+        self.tremolo.set_sample_rate(self.sample_rate);
+        self.envelope_follower.set_sample_rate(self.sample_rate);
+        self.delay.set_sample_rate(self.sample_rate);
+        self.delay_active = false;
+        self.plate_reverb.set_sample_rate(self.sample_rate);
+        self.spring_reverb.set_sample_rate(self.sample_rate);
+        self.active_reverb = None;
+
+        // Any loaded impulse response was resampled for the old sample rate
+        self.shared_sample_rate
+            .store(self.sample_rate.to_bits(), Ordering::Relaxed);
+        self.cabinet = None;
+        self.cabinet_load_requested = self.params.ir_path.read().is_some();
+
+        // The coefficients depend on the sample rate, so they always need to be redesigned here
+        self.design_high_cut(self.params.high_cut_frequency.value());
+        self.high_cut_active = self.params.high_cut_enabled.value();
+        self.match_eq_active = self.params.match_eq_enabled.value();
+        self.compressor.set_sample_rate(self.sample_rate);
+        self.compressor
+            .set_lookahead(self.params.compressor_lookahead.value());
+        self.compressor_active = self.params.compressor_enabled.value();
+        self.wah.set_sample_rate(self.sample_rate);
+        self.multiband.set_sample_rate(self.sample_rate);
+        self.multiband_active = self.params.multiband_enabled.value();
+        self.gate.set_sample_rate(self.sample_rate);
+        self.gate.set_lookahead(self.params.gate_lookahead.value());
+        self.active_gate = self
+            .params
+            .gate_enabled
+            .value()
+            .then(|| self.params.gate_position.value());
+        self.limiter.set_sample_rate(self.sample_rate);
+        self.limiter_active = self.params.limiter_enabled.value();
+        self.match_eq_designed_smoothing = self.params.match_eq_smoothing.value();
+        self.match_eq_design_requested = true;
+
+        self.reported_latency = self.latency_samples();
+        context.set_latency_samples(self.reported_latency);
         true
     }
 
     fn reset(&mut self) {
         self.univibe.reset();
+        self.phaser.reset();
+        self.flanger.reset();
+        self.chorus.reset();
+        self.tremolo.reset();
+        self.envelope_follower.reset();
+        self.delay.reset();
+        self.plate_reverb.reset();
+        self.spring_reverb.reset();
+        if let Some(cabinet) = &mut self.cabinet {
+            cabinet.reset();
+        }
+        self.high_cut.reset();
+        self.match_eq.reset();
+        self.compressor.reset();
+        self.wah.reset();
+        self.multiband.reset();
+        self.gate.reset();
+        self.limiter.reset();
     }
 
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
+        if self.cabinet_load_requested {
+            self.cabinet_load_requested = false;
+            context.execute_background(PluginTask::LoadImpulseResponse);
+        }
+        if let Some(mut exchange) = self.cabinet_exchange.try_lock() {
+            if exchange.retired.is_none() {
+                if let Some(cabinet) = exchange.loaded.take() {
+                    exchange.retired = self.cabinet.replace(cabinet);
+                }
+            }
+        }
+        if let Some(mut exchange) = self.match_eq_exchange.try_lock() {
+            if exchange.updated {
+                exchange.updated = false;
+                self.match_eq.set_coefficients(&exchange.coefficients);
+            }
+        }
+
+        self.learn_match_eq(buffer, aux, context);
+
+        let match_eq_smoothing = self.params.match_eq_smoothing.value();
+        if match_eq_smoothing != self.match_eq_designed_smoothing {
+            self.match_eq_designed_smoothing = match_eq_smoothing;
+            self.match_eq_design_requested = true;
+        }
+        if self.match_eq_design_requested && !self.match_eq_learning {
+            self.match_eq_design_requested = false;
+            context.execute_background(PluginTask::DesignMatchEq);
+        }
+
+        let gate_position = self
+            .params
+            .gate_enabled
+            .value()
+            .then(|| self.params.gate_position.value());
+        if gate_position != self.active_gate {
+            // The lookahead buffer holds audio from the other position
+            self.active_gate = gate_position;
+            self.gate.reset();
+        }
+        if gate_position == Some(GatePosition::PreDrive) {
+            self.process_gate(buffer.as_slice());
+        }
+
+        let match_eq_enabled = self.params.match_eq_enabled.value();
+        if match_eq_enabled != self.match_eq_active {
+            // Whatever is still buffered is from before the filter was toggled
+            self.match_eq_active = match_eq_enabled;
+            self.match_eq.reset();
+        }
+        if match_eq_enabled {
+            self.match_eq.process(buffer.as_slice());
+        }
+
+        let compressor_enabled = self.params.compressor_enabled.value();
+        if compressor_enabled != self.compressor_active {
+            self.compressor_active = compressor_enabled;
+            self.compressor.reset();
+        }
+        if compressor_enabled {
+            self.compressor
+                .set_threshold(self.params.compressor_threshold.value());
+            self.compressor
+                .set_ratio(self.params.compressor_ratio.value());
+            self.compressor
+                .set_knee(self.params.compressor_knee.value());
+            self.compressor
+                .set_attack(self.params.compressor_attack.value());
+            self.compressor
+                .set_release(self.params.compressor_release.value());
+            self.compressor
+                .set_detector_mode(self.params.compressor_detector.value().into());
+            self.compressor
+                .set_makeup_gain(self.params.compressor_makeup_gain.value());
+            self.compressor
+                .set_lookahead(self.params.compressor_lookahead.value());
+            self.compressor
+                .set_stereo_link(self.params.compressor_stereo_link.value());
+
+            let sidechain = if self.params.compressor_sidechain.value() {
+                aux.inputs.first().map(|buffer| buffer.as_slice_immutable())
+            } else {
+                None
+            };
+            self.compressor.process(buffer.as_slice(), sidechain);
+        }
+
+        let wah_enabled = self.params.wah_enabled.value();
+        if wah_enabled {
+            self.wah.set_mode(self.params.wah_mode.value().into());
+            self.wah.set_range(
+                self.params.wah_min_frequency.value(),
+                self.params.wah_max_frequency.value(),
+            );
+            self.wah.set_resonance(self.params.wah_resonance.value());
+            self.wah.set_response(self.params.wah_response.value());
+            self.wah
+                .set_sensitivity(util::db_to_gain(self.params.wah_sensitivity.value()));
+            self.wah.set_rate(self.params.wah_rate.value());
+            self.wah.set_lfo_shape(self.params.lfo_shape.value().into());
+            // The response time smooths out any steps in the pedal position's automation
+            self.wah.set_position(self.params.wah_position.value());
+            if !self.wah_active {
+                // Start at the current pedal position instead of sweeping to it
+                self.wah.reset();
+            }
+
+            for channel_samples in buffer.iter_samples() {
+                for (channel_idx, sample) in channel_samples.into_iter().enumerate() {
+                    *sample = self.wah.process(*sample, channel_idx);
+                }
+            }
+        }
+        self.wah_active = wah_enabled;
+
+        let multiband_enabled = self.params.multiband_enabled.value();
+        if multiband_enabled != self.multiband_active {
+            // Whatever is still buffered is from before the saturation was toggled
+            self.multiband_active = multiband_enabled;
+            self.multiband.reset();
+        }
+        if multiband_enabled {
+            self.multiband
+                .set_num_bands(self.params.multiband_num_bands.value() as usize);
+            self.multiband.set_crossover_frequencies(&[
+                self.params.multiband_low_crossover.value(),
+                self.params.multiband_mid_crossover.value(),
+                self.params.multiband_high_crossover.value(),
+            ]);
+            for (band_idx, band_params) in self.params.multiband_bands.iter().enumerate() {
+                self.multiband
+                    .set_wave_shaper(band_idx, band_params.curve.value().into());
+                self.multiband
+                    .set_drive(band_idx, util::db_to_gain(band_params.drive.value()));
+                self.multiband
+                    .set_output_gain(band_idx, util::db_to_gain(band_params.output_gain.value()));
+            }
+            self.multiband.process(buffer.as_slice());
+        }
+
+        if gate_position == Some(GatePosition::PostDrive) {
+            self.process_gate(buffer.as_slice());
+        }
+
         let num_samples = buffer.samples();
         let num_channels = buffer.channels();
         let rate = self.params.rate.smoothed.next();
+        let rate = match context.transport().tempo {
+            Some(tempo) if self.params.tempo_sync.value() => {
+                NoteDivision::from(self.params.sync_division.value()).frequency(tempo as f32)
+            }
+            _ => rate,
+        };
+        let lfo_shape = modulation::LfoShape::from(self.params.lfo_shape.value());
         let depth = self.params.depth.smoothed.next();
         let feedback = self.params.feedback.smoothed.next();
         let num_stages = self.params.num_stages.value() as usize;
         let mix = self.params.mix.smoothed.next();
+        let cabinet_enabled = self.params.cabinet_enabled.value();
+        let effect = self.params.effect.value();
+
+        if effect == Effect::Phaser {
+            self.phaser.set_rate(rate);
+            self.phaser.set_depth(depth);
+            self.phaser.set_sweep_range(
+                self.params.phaser_min_frequency.value(),
+                self.params.phaser_max_frequency.value(),
+            );
+            self.phaser
+                .set_feedback(if self.params.phaser_invert_feedback.value() {
+                    -feedback
+                } else {
+                    feedback
+                });
+            self.phaser
+                .set_num_stages(self.params.phaser_stages.value() as usize);
+            self.phaser
+                .set_stereo_offset(self.params.phaser_stereo_offset.value());
+        }
+        if effect == Effect::Flanger {
+            self.flanger.set_rate(rate);
+            self.flanger.set_depth(depth);
+            self.flanger
+                .set_manual_delay(self.params.flanger_manual_delay.value());
+            self.flanger
+                .set_regeneration(if self.params.flanger_invert_regeneration.value() {
+                    -feedback
+                } else {
+                    feedback
+                });
+            self.flanger
+                .set_through_zero(self.params.flanger_through_zero.value());
+            self.flanger.set_mix(mix);
+        }
+        if effect == Effect::UniVibe {
+            self.univibe.set_lfo_shape(lfo_shape);
+        }
+        if effect == Effect::Tremolo {
+            self.tremolo.set_rate(rate);
+            self.tremolo.set_depth(depth);
+            self.tremolo.set_lfo_shape(lfo_shape);
+            self.tremolo
+                .set_mode(self.params.tremolo_mode.value().into());
+            self.tremolo
+                .set_crossover_frequency(self.params.tremolo_crossover_frequency.value());
+        }
+        if effect == Effect::Chorus {
+            self.chorus.set_rate(rate);
+            self.chorus.set_depth(depth);
+            self.chorus
+                .set_num_voices(self.params.chorus_voices.value() as usize);
+            self.chorus.set_spread(self.params.chorus_spread.value());
+            self.chorus.set_drift(self.params.chorus_drift.value());
+            self.chorus.set_bbd(self.params.chorus_bbd.value());
+        }
+
+        let envelope_enabled = self.params.envelope_enabled.value();
+        let envelope_target = self.params.envelope_target.value();
+        let envelope_amount = self.params.envelope_amount.value();
+        let envelope_sensitivity = util::db_to_gain(self.params.envelope_sensitivity.value());
+        if envelope_enabled {
+            self.envelope_follower
+                .set_detector_mode(self.params.envelope_detector.value().into());
+            self.envelope_follower
+                .set_attack(self.params.envelope_attack.value());
+            self.envelope_follower
+                .set_release(self.params.envelope_release.value());
+        }
 
-        for channel_samples in buffer.iter_samples() {
-            for sample in channel_samples {
+        for mut channel_samples in buffer.iter_samples() {
+            let (univibe_rate, univibe_depth) = if envelope_enabled {
+                let peak = channel_samples
+                    .iter_mut()
+                    .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+                let envelope = self
+                    .envelope_follower
+                    .process(peak * envelope_sensitivity)
+                    .min(1.0);
+                match envelope_target {
+                    EnvelopeTarget::Rate => (
+                        rate * 2.0f32.powf(envelope * envelope_amount * ENVELOPE_RATE_OCTAVES),
+                        depth,
+                    ),
+                    EnvelopeTarget::Depth => {
+                        (rate, (depth + envelope * envelope_amount).clamp(0.0, 1.0))
+                    }
+                    EnvelopeTarget::HighCut => (rate, depth),
+                }
+            } else {
+                (rate, depth)
+            };
+
+            for (channel_idx, sample) in channel_samples.into_iter().enumerate() {
                 let input = *sample;
-                let processed = self
-                    .univibe
-                    .process(input, rate, depth, feedback, num_stages);
-                let output = input * (1.0 - mix) + processed * mix;
+                let mut output = match effect {
+                    Effect::UniVibe => {
+                        let processed = self.univibe.process(
+                            input,
+                            univibe_rate,
+                            univibe_depth,
+                            feedback,
+                            num_stages,
+                        );
+                        input * (1.0 - mix) + processed * mix
+                    }
+                    Effect::Phaser => {
+                        let processed = self.phaser.process(input, channel_idx);
+                        input * (1.0 - mix) + processed * mix
+                    }
+                    // The flanger delays the dry signal in through-zero mode, so it does its own
+                    // mixing
+                    Effect::Flanger => self.flanger.process(input, channel_idx),
+                    Effect::Chorus => {
+                        let processed = self.chorus.process(input, channel_idx);
+                        input * (1.0 - mix) + processed * mix
+                    }
+                    Effect::Tremolo => {
+                        let processed = self.tremolo.process(input, channel_idx);
+                        input * (1.0 - mix) + processed * mix
+                    }
+                };
+                if cabinet_enabled {
+                    if let Some(cabinet) = &mut self.cabinet {
+                        output = cabinet.process(output, channel_idx);
+                    }
+                }
                 *sample = output;
             }
         }
-        ProcessStatus::Normal
+
+        let delay_enabled = self.params.delay_enabled.value();
+        if delay_enabled && !self.delay_active {
+            self.delay.reset();
+        }
+        self.delay_active = delay_enabled;
+        if delay_enabled {
+            let delay_mix = self.params.delay_mix.smoothed.next();
+            let delay_time = match context.transport().tempo {
+                Some(tempo) if self.params.delay_sync.value() => {
+                    NoteDivision::from(self.params.delay_division.value()).length_ms(tempo as f32)
+                }
+                _ => self.params.delay_time.value(),
+            };
+            self.delay.set_time(delay_time);
+            self.delay
+                .set_time_mode(self.params.delay_time_mode.value().into());
+            self.delay.set_feedback(self.params.delay_feedback.value());
+            self.delay.set_filters(
+                self.params.delay_low_cut.value(),
+                self.params.delay_high_cut.value(),
+            );
+            self.delay.set_saturation(
+                self.params
+                    .delay_saturation
+                    .value()
+                    .then(|| self.params.delay_curve.value().into()),
+            );
+            self.delay
+                .set_drive(util::db_to_gain(self.params.delay_drive.value()));
+            self.delay
+                .set_wow_flutter(self.params.delay_wow_flutter.value());
+            self.delay
+                .set_ping_pong(self.params.delay_ping_pong.value());
+
+            for mut channel_samples in buffer.iter_samples() {
+                let left = channel_samples.get_mut(0).map_or(0.0, |sample| *sample);
+                let right = channel_samples.get_mut(1).map_or(left, |sample| *sample);
+                let (wet_left, wet_right) = self.delay.process(left, right);
+                for (sample, wet) in channel_samples.into_iter().zip([wet_left, wet_right]) {
+                    *sample = *sample * (1.0 - delay_mix) + wet * delay_mix;
+                }
+            }
+        }
+
+        let reverb = if self.params.reverb_enabled.value() {
+            Some(self.params.reverb_type.value())
+        } else {
+            None
+        };
+        if reverb != self.active_reverb {
+            // Don't let an old tail come back when the reverb gets enabled again
+            self.plate_reverb.reset();
+            self.spring_reverb.reset();
+            self.active_reverb = reverb;
+        }
+        let reverb_mix = self.params.reverb_mix.smoothed.next();
+        if reverb == Some(ReverbType::Plate) {
+            self.plate_reverb
+                .set_pre_delay(self.params.reverb_pre_delay.value());
+            self.plate_reverb.set_size(self.params.reverb_size.value());
+            self.plate_reverb
+                .set_decay(self.params.reverb_decay.value());
+            self.plate_reverb
+                .set_damping(self.params.reverb_damping.value());
+
+            for mut channel_samples in buffer.iter_samples() {
+                let left = channel_samples.get_mut(0).map_or(0.0, |sample| *sample);
+                let right = channel_samples.get_mut(1).map_or(left, |sample| *sample);
+                let (wet_left, wet_right) = self.plate_reverb.process(left, right);
+                for (sample, wet) in channel_samples.into_iter().zip([wet_left, wet_right]) {
+                    *sample = *sample * (1.0 - reverb_mix) + wet * reverb_mix;
+                }
+            }
+        }
+        if reverb == Some(ReverbType::Spring) {
+            self.spring_reverb
+                .set_tank(self.params.spring_tank.value().into());
+            self.spring_reverb
+                .set_tension(self.params.spring_tension.value());
+            self.spring_reverb
+                .set_decay(self.params.spring_decay.value());
+            self.spring_reverb.set_tone(self.params.spring_tone.value());
+            self.spring_reverb.set_mix(reverb_mix);
+
+            for channel_samples in buffer.iter_samples() {
+                for (channel_idx, sample) in channel_samples.into_iter().enumerate() {
+                    *sample = self.spring_reverb.process(*sample, channel_idx);
+                }
+            }
+        }
+
+        let high_cut_enabled = self.params.high_cut_enabled.value();
+        if high_cut_enabled != self.high_cut_active {
+            // Whatever is still buffered is from before the filter was toggled
+            self.high_cut_active = high_cut_enabled;
+            self.high_cut.reset();
+        }
+        if high_cut_enabled {
+            let mut frequency = self.params.high_cut_frequency.value();
+            if envelope_enabled && envelope_target == EnvelopeTarget::HighCut {
+                let envelope = self.envelope_follower.value().min(1.0);
+                let octaves = envelope * envelope_amount * ENVELOPE_HIGH_CUT_OCTAVES;
+                let octaves = (octaves * ENVELOPE_HIGH_CUT_STEPS_PER_OCTAVE).round()
+                    / ENVELOPE_HIGH_CUT_STEPS_PER_OCTAVE;
+                frequency = (frequency * 2.0f32.powf(octaves)).clamp(1000.0, 20000.0);
+            }
+            if frequency != self.high_cut_designed_frequency {
+                self.design_high_cut(frequency);
+            }
+            self.high_cut.process(buffer.as_slice());
+        }
+
+        let limiter_enabled = self.params.limiter_enabled.value();
+        if limiter_enabled != self.limiter_active {
+            self.limiter_active = limiter_enabled;
+            self.limiter.reset();
+        }
+        if limiter_enabled {
+            self.limiter
+                .set_ceiling(self.params.limiter_ceiling.value());
+            self.limiter
+                .set_release(self.params.limiter_release.value());
+            self.limiter.process(buffer.as_slice());
+        }
+
+        let latency = self.latency_samples();
+        if latency != self.reported_latency {
+            self.reported_latency = latency;
+            context.set_latency_samples(latency);
+        }
+
+        // The delay feeds the reverb, so their tails add up
+        let reverb_tail = match reverb {
+            Some(ReverbType::Plate) => self.plate_reverb.tail_length_samples(),
+            Some(ReverbType::Spring) => self.spring_reverb.tail_length_samples(),
+            None => 0,
+        };
+        let delay_tail = if delay_enabled {
+            self.delay.tail_length_samples()
+        } else {
+            Some(0)
+        };
+        match delay_tail {
+            None => ProcessStatus::KeepAlive,
+            Some(0) if reverb_tail == 0 => ProcessStatus::Normal,
+            Some(delay_tail) => ProcessStatus::Tail(delay_tail.saturating_add(reverb_tail)),
+        }
+    }
+}
+
+impl NihPlugin {
+    /// The total latency of all enabled latency-inducing stages.
+    fn latency_samples(&self) -> u32 {
+        let mut latency = 0;
+        if self.high_cut_active {
+            latency += self.high_cut.latency_samples();
+        }
+        if self.match_eq_active {
+            latency += self.match_eq.latency_samples();
+        }
+        if self.compressor_active {
+            latency += self.compressor.latency_samples();
+        }
+        if self.multiband_active {
+            latency += self.multiband.latency_samples();
+        }
+        if self.active_gate.is_some() {
+            latency += self.gate.latency_samples();
+        }
+        if self.limiter_active {
+            latency += self.limiter.latency_samples();
+        }
+
+        latency
     }
+
+    /// Update the noise gate's parameters and gate `buffer` in place.
+    fn process_gate(&mut self, buffer: &mut [&mut [f32]]) {
+        self.gate.set_thresholds(
+            self.params.gate_open_threshold.value(),
+            self.params.gate_close_threshold.value(),
+        );
+        self.gate.set_hold(self.params.gate_hold.value());
+        self.gate.set_attack(self.params.gate_attack.value());
+        self.gate.set_release(self.params.gate_release.value());
+        self.gate.set_range(self.params.gate_range.value());
+        self.gate.set_ratio(self.params.gate_ratio.value());
+        self.gate
+            .set_sidechain_high_pass(self.params.gate_sidechain_high_pass.value());
+        self.gate.set_lookahead(self.params.gate_lookahead.value());
+        self.gate.process(buffer);
+    }
+
+    /// Feed the unprocessed input and the sidechain input to the match EQ's analysers while
+    /// learning is enabled, and request a new design once it gets disabled again.
+    fn learn_match_eq(
+        &mut self,
+        buffer: &Buffer,
+        aux: &AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) {
+        let learn = self.params.match_eq_learn.value();
+        if !learn && !self.match_eq_learning {
+            return;
+        }
+        // The background thread briefly holds this lock when it reads out the spectra. If that
+        // happens we'll just try again during the next block.
+        let Some(mut analyzers) = self.match_eq_analyzers.try_lock() else {
+            return;
+        };
+
+        if !learn {
+            self.match_eq_learning = false;
+            context.execute_background(PluginTask::LearnMatchEq);
+            return;
+        }
+        if !self.match_eq_learning {
+            self.match_eq_learning = true;
+            analyzers.input.reset();
+            analyzers.reference.reset();
+        }
+
+        let main = buffer.as_slice_immutable();
+        let sidechain = aux.inputs.first().map(|buffer| buffer.as_slice_immutable());
+        for sample_idx in 0..buffer.samples() {
+            let input: f32 = main.iter().map(|channel| channel[sample_idx]).sum();
+            analyzers.input.process(input / main.len() as f32);
+
+            if let Some(sidechain) = sidechain {
+                let reference: f32 = sidechain.iter().map(|channel| channel[sample_idx]).sum();
+                analyzers
+                    .reference
+                    .process(reference / sidechain.len() as f32);
+            }
+        }
+    }
+
+    /// Design a linear-phase fourth order low-pass for the high cut and update the filter with it.
+    fn design_high_cut(&mut self, frequency: f32) {
+        let coefficients =
+            FirCoefficients::<HIGH_CUT_FILTER_SIZE>::design_linear_phase_lr4_low_pass(
+                self.sample_rate,
+                frequency,
+            );
+        self.high_cut.set_coefficients(&coefficients);
+        self.high_cut_designed_frequency = frequency;
+    }
+}
+
+/// Design a match EQ filter from the spectra stored in `params` and hand it to the audio thread.
+/// Does nothing if nothing has been learned yet. Runs on the background thread.
+fn design_match_eq(
+    params: &NihPluginParams,
+    sample_rate: f32,
+    match_eq_exchange: &Mutex<MatchEqExchange>,
+) {
+    let curve = {
+        let input_spectrum = params.match_input_spectrum.read();
+        let reference_spectrum = params.match_reference_spectrum.read();
+        if input_spectrum.len() != ANALYSIS_NUM_BINS
+            || reference_spectrum.len() != ANALYSIS_NUM_BINS
+        {
+            return;
+        }
+
+        match_eq::match_curve(
+            &input_spectrum,
+            &reference_spectrum,
+            params.match_eq_smoothing.value(),
+        )
+    };
+
+    let analysis_sample_rate = *params.match_analysis_sample_rate.read();
+    let coefficients =
+        match_eq::design_match_filter::<FILTER_SIZE>(&curve, analysis_sample_rate, sample_rate);
+    let mut exchange = match_eq_exchange.lock();
+    exchange.coefficients = coefficients;
+    exchange.updated = true;
 }
 
 impl ClapPlugin for NihPlugin {